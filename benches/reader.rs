@@ -0,0 +1,29 @@
+// native reader的benchmark
+// native: 字符串借用输入; native_owned: 读取后再into_owned, 每个字符串都复制一次
+// 与修改前的版本对比: 先在旧版本上 cargo bench --bench reader -- --save-baseline old,
+// 再在新版本上 cargo bench --bench reader -- --baseline old
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use texmath_rust::ast::node::Exp;
+use texmath_rust::ast::reader::read_with;
+
+// 一个中等大小的表达式, 包含转义字符串和各种构造器
+const EXP: &str = r#"ESubsup (ESymbol Op "\8721") (EGrouped [ EIdentifier "i" , ESymbol Rel "=" , ENumber "1" ]) (EIdentifier "n") , EFraction NormalFrac (EGrouped [ EIdentifier "x" , ESymbol Bin "\8722" , ENumber "1" ]) (ESqrt (ESuper (EIdentifier "y") (ENumber "2"))) , EDelimited "(" ")" [ Right (EText TextNormal "if x \8805 0") , Left "|" ] , EUnderover False (ESymbol Op "\8747") (ENumber "0") (EMathOperator "max") , ESpace (1 % 6)"#;
+
+fn corpus(n: usize) -> String {
+    format!("[ {} ]", vec![EXP; n].join(" , "))
+}
+
+fn bench_reader(c: &mut Criterion) {
+    let native = corpus(200);
+
+    let mut group = c.benchmark_group("read");
+    group.throughput(Throughput::Bytes(native.len() as u64));
+    group.bench_function("native", |b| b.iter(|| read_with("native", black_box(&native)).unwrap()));
+    group.bench_function("native_owned", |b| b.iter(|| {
+        read_with("native", black_box(&native)).unwrap().into_iter().map(Exp::into_owned).collect::<Vec<_>>()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, bench_reader);
+criterion_main!(benches);
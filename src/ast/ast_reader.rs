@@ -1,14 +1,13 @@
-use std::{collections::HashMap, hash::BuildHasherDefault};
-use ahash::AHasher;
-use lazy_static::lazy_static;
+use std::borrow::Cow;
 use super::node;
 use nom::{
-    branch::alt, bytes::complete::tag, character::complete::{char, digit1, multispace0, none_of}, combinator::map, error::ErrorKind, Err, IResult
+    branch::alt, bytes::complete::tag, character::complete::{alphanumeric1, char, digit1, multispace0}, combinator::map, error::ErrorKind, Err, IResult
 };
 use crate::ast::node::{Exp, TextType};
+use super::reader::{ReadError, Reader};
 
 // ast reader [Exp ...]
-pub fn read_ast(ast: &str) -> Result<Vec<node::Exp>, String> {
+pub fn read_ast(ast: &str) -> Result<Vec<node::Exp<'_>>, String> {
     match parse_exp_list(ast) {
         Ok((_, e)) => {
             Ok(e)
@@ -20,7 +19,25 @@ pub fn read_ast(ast: &str) -> Result<Vec<node::Exp>, String> {
     }
 }
 
-fn parse_indelimited(input: &str) -> IResult<&str, Vec<node::InEDelimited>> {
+pub struct NativeReader;
+
+impl Reader for NativeReader {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn read<'a>(&self, input: &'a str) -> Result<Vec<node::Exp<'a>>, ReadError> {
+        match parse_exp_list(input) {
+            Ok((_, e)) => Ok(e),
+            Err(Err::Error(e)) | Err(Err::Failure(e)) => {
+                Err(ReadError::at_rest(format!("Parse error: {:?}", e.code), input, e.input))
+            },
+            Err(e) => Err(ReadError::new(format!("Parse error: {:?}", e))),
+        }
+    }
+}
+
+fn parse_indelimited(input: &str) -> IResult<&str, Vec<node::InEDelimited<'_>>> {
     let mut input = input;
     (input, _) = multispace0(input)?;
     (input, _) = char('[')(input)?;
@@ -65,7 +82,7 @@ fn parse_indelimited(input: &str) -> IResult<&str, Vec<node::InEDelimited>> {
 }
 
 // [Exp, Exp, Exp ...]
-fn parse_exp_list(input: &str) -> IResult<&str, Vec<node::Exp>> {
+fn parse_exp_list(input: &str) -> IResult<&str, Vec<node::Exp<'_>>> {
     let mut input = input;
     (input, _) = multispace0(input)?;
     (input, _) = char('[')(input)?;
@@ -100,174 +117,53 @@ fn parse_exp_list(input: &str) -> IResult<&str, Vec<node::Exp>> {
     Ok((input, exp_list))
 }
 
-#[test]
-fn test_parse_str_until_space() {
-    let test_case = "EText TextNormal \"This is a test.\"";
-    println!("{:?}", parse_str_until_space(test_case));
-    // assert_eq!(parse_str_until_space(test_case), Ok(("EText".to_string()," TextNormal \"This is a test.\"".to_string())));
-}
-
-// 遍历直到遇到空格
-// input, symbol
-fn parse_str_until_space(input: &str) -> IResult<&str, String> {
-    let end = input.find(' ').unwrap_or(input.len());
-    let ret_str = input[..end].to_string().trim().to_string();
-    Ok((&input[end..], ret_str))
-}
-
 #[test]
 fn test_parse_exp() {
     let test_case = "EIdentifier \"x\"";
-    assert_eq!(parse_exp(test_case), Ok(("", Exp::EIdentifier("x".to_string()))));
+    assert_eq!(parse_exp(test_case), Ok(("", Exp::EIdentifier("x".into()))));
 
     let test_case = "ENumber \"123\"";
-    assert_eq!(parse_exp(test_case), Ok(("", Exp::ENumber("123".to_string()))));
+    assert_eq!(parse_exp(test_case), Ok(("", Exp::ENumber("123".into()))));
 
     let test_case = "EMathOperator \"sin\"";
-    assert_eq!(parse_exp(test_case), Ok(("", Exp::EMathOperator("sin".to_string()))));
+    assert_eq!(parse_exp(test_case), Ok(("", Exp::EMathOperator("sin".into()))));
+
+}
 
+// 每层嵌套都经过parse_exp, 在这里检查剩余的栈空间
+fn parse_exp(input: &str) -> IResult<&str, Exp<'_>> {
+    node::with_stack(|| parse_exp_inner(input))
 }
 
-fn parse_exp(input: &str) -> IResult<&str, Exp> {
+fn parse_exp_inner(input: &str) -> IResult<&str, Exp<'_>> {
     let (input, _) = multispace0(input)?;
-    if input.starts_with("ESymbol"){
-        // symbol
-        let (input, exp) = parse_symbol(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("ENumber"){
-        // number
-        let (input, exp) = parse_number(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EIdentifier"){
-        // identifier
-        let (input, exp) = parse_identifier(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EMathOperator"){
-        // math_operator
-        let (input, exp) = parse_math_operator(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EText"){
-        // text
-        let (input, exp) = parse_text(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EDelimited"){
-        // delimited
-        let (input, exp) = parse_delimited(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EGrouped"){
-        // grouped
-        let (input, exp) = parse_grouped(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("ERoot"){
-        // root
-        let (input, exp) = parse_root(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("ESqrt"){
-        // sqrt
-        let (input, exp) = parse_sqrt(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EFraction"){
-        // fraction
-        let (input, exp) = parse_fraction(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("ESubsup"){
-        // subsup
-        let (input, exp) = parse_subsup(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("ESub"){
-        // sub
-        let (input, exp) = parse_sub(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("ESuper"){
-        // super
-        let (input, exp) = parse_super(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EOver"){
-        // over
-        let (input, exp) = parse_over(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EUnderover"){
-        // under
-        let (input, exp) = parse_under_over(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EUnder"){
-        // under_over
-        let (input, exp) = parse_under(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EPhantom"){
-        // phantom
-        let (input, exp) = parse_phantom(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EBoxed"){
-        // boxed
-        let (input, exp) = parse_boxed(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EScaled"){
-        // scaled
-        let (input, exp) = parse_scaled(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EStyled"){
-        // styled
-        let (input, exp) = parse_styled(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("EArray"){
-        // array
-        let (input, exp) = parse_array(input)?;
-        return Ok((input, exp));
-    }else if input.starts_with("ESpace"){
-        // space
-        let (input, exp) = parse_space(input)?;
-        return Ok((input, exp));
+    // 先取出构造器名, 只分派一次; 各子解析器仍然自己匹配构造器名, 所以传入原来的input
+    let (_, name) = alphanumeric1(input)?;
+    match name {
+        "ESymbol" => parse_symbol(input),
+        "ENumber" => parse_number(input),
+        "EIdentifier" => parse_identifier(input),
+        "EMathOperator" => parse_math_operator(input),
+        "EText" => parse_text(input),
+        "EDelimited" => parse_delimited(input),
+        "EGrouped" => parse_grouped(input),
+        "ERoot" => parse_root(input),
+        "ESqrt" => parse_sqrt(input),
+        "EFraction" => parse_fraction(input),
+        "ESubsup" => parse_subsup(input),
+        "ESub" => parse_sub(input),
+        "ESuper" => parse_super(input),
+        "EOver" => parse_over(input),
+        "EUnderover" => parse_under_over(input),
+        "EUnder" => parse_under(input),
+        "EPhantom" => parse_phantom(input),
+        "EBoxed" => parse_boxed(input),
+        "EScaled" => parse_scaled(input),
+        "EStyled" => parse_styled(input),
+        "EArray" => parse_array(input),
+        "ESpace" => parse_space(input),
+        _ => Err(Err::Error(nom::error::Error::new(input, ErrorKind::Tag))),
     }
-
-
-    return Err(Err::Error(nom::error::Error::new(input, ErrorKind::Tag)));
-}
-
-type ExpParser = fn(&str) -> IResult<&str, Exp>;
-
-lazy_static!{
-    static ref EXP_PARSER: HashMap<&'static str, ExpParser, BuildHasherDefault<AHasher>> = {
-        let mut m: HashMap<&'static str, ExpParser, BuildHasherDefault<AHasher>> = HashMap::with_hasher(BuildHasherDefault::<AHasher>::default());
-        m.insert("ESymbol", parse_symbol);
-        m.insert("ENumber", parse_number);
-        m.insert("EIdentifier", parse_identifier);
-        m.insert("EMathOperator", parse_math_operator);
-        m.insert("EText", parse_text);
-        m.insert("EDelimited", parse_delimited);
-        m.insert("EGrouped", parse_grouped);
-        m.insert("ERoot", parse_root);
-        m.insert("ESqrt", parse_sqrt);
-        m.insert("EFraction", parse_fraction);
-        m.insert("ESubsup", parse_subsup);
-        m.insert("ESub", parse_sub);
-        m.insert("ESuper", parse_super);
-        m.insert("EOver", parse_over);
-        m.insert("EUnderover", parse_under_over);
-        m.insert("EUnder", parse_under);
-        m.insert("EPhantom", parse_phantom);
-        m.insert("EBoxed", parse_boxed);
-        m.insert("EScaled", parse_scaled);
-        m.insert("EStyled", parse_styled);
-        m.insert("EArray", parse_array);
-        m.insert("ESpace", parse_space);
-        m
-    };
-}
-fn parse_exp2(input: &str) -> IResult<&str, node::Exp> {
-    // TODO: 通过直接提取Type避免if else多次匹配, 但先提取出了type, 与现有parse_exp不兼容
-
-    let (mut input, _) = multispace0(input)?;
-    let exp_type;
-    // println!("{:?}", type_symbol);
-
-    (input, exp_type) = parse_str_until_space(input)?;
-
-    // println!("{:?}", exp_type);
-    println!("key: {:?}", exp_type);
-    let parser = EXP_PARSER.get(exp_type.as_str()).unwrap();
-    let (input, exp) = parser(input)?;
-    Ok((input, exp))
 }
 
 fn parse_tex_symbol_type(input: &str) -> IResult<&str, node::TeXSymbolType>{
@@ -295,89 +191,154 @@ fn test_parse_quoted_string() {
     // 正常情况，没有转义字符
     assert_eq!(
         parse_quoted_string("\"This is a test.\""),
-        Ok(("", "This is a test.".to_string()))
+        Ok(("", "This is a test.".into()))
     );
 
     // 包含转义引号
     assert_eq!(
         parse_quoted_string(r#" "This is a \"test\".""#),
-        Ok(("", r#"This is a \"test\"."#.to_string()))
+        Ok(("", r#"This is a "test"."#.into()))
     );
 
     // 包含转义反斜杠
     assert_eq!(
         parse_quoted_string(r#" "This is a \\\\ test.""#),
-        Ok(("", r#"This is a \\\\ test."#.to_string()))
+        Ok(("", r#"This is a \\ test."#.into()))
     );
 
     assert_eq!(
         parse_quoted_string(r#""\8722""#),
-        Ok(("", r#"\8722"#.to_string()))
+        Ok(("", "\u{2212}".into()))
     );
 
+    // \&用于分隔数字: "\8721\&1" -> ∑1
     assert_eq!(
-        parse_quoted_string(r#""\"""#),
-        Ok(("", r#"\""#.to_string()))
+        parse_quoted_string(r#""\8721\&1""#),
+        Ok(("", "\u{2211}1".into()))
     );
+
+    // 十六进制, 八进制, ASCII控制字符名, ^控制字符
     assert_eq!(
-        parse_quoted_string(r#""\\""#),
-        Ok(("", r#"\\"#.to_string()))
+        parse_quoted_string(r#""\x2212\o55\SOH\SO\DEL\^A\n""#),
+        Ok(("", "\u{2212}-\u{1}\u{e}\u{7f}\u{1}\n".into()))
     );
-}
-
-fn parse_quoted_string(input: &str) -> IResult<&str, String> {
-    let mut output = input;
-    (output, _) = multispace0(output)?;
-    (output, _) = char('"')(output)?;
-    
-    // empty
-    if output.starts_with('"') {
-        (output, _) = char('"')(output)?;
-        return Ok((output, "".to_string()));
-    }
 
+    // 字符串间隔: \ 空白 \ 之间的内容被忽略
+    assert_eq!(
+        parse_quoted_string("\"ab\\  \n \\cd\""),
+        Ok(("", "abcd".into()))
+    );
 
-    // 读取字符串, 直到遇到"且前面没有转义字符
-    let mut res = String::new();
-    loop {
-        // 如果遇到转义字符
-        if output.starts_with("\\") {
-            res.push_str("\\");
+    assert!(parse_quoted_string(r#""\q""#).is_err());
+    assert!(parse_quoted_string(r#""\1114112""#).is_err());
+    assert!(parse_quoted_string(r#""abc"#).is_err());
+}
 
-            // 跳过转义字符
-            output = &output[1..];
+// Haskell的字符串字面量, 转义在读取时解码为unicode字符:
+// \8722 \x2212 \o20422 -> −, \n \" \\ 等单字符转义, \NUL \SOH ... \DEL, \^A ... \^_
+// \& 为空, 用于分隔数字; \ 空白 \ 为字符串间隔, 中间的内容被忽略
+fn parse_quoted_string(input: &str) -> IResult<&str, Cow<'_, str>> {
+    let (input, _) = multispace0(input)?;
+    let (body, _) = char('"')(input)?;
 
-            let c = output.chars().next().unwrap();
-            res.push(c);
-            output = &output[1..];
-            continue;
-        }
+    // 没有转义时直接借用输入, 不分配
+    let mut end = body.find(['"', '\\'])
+        .ok_or_else(|| Err::Error(nom::error::Error::new(body, ErrorKind::Char)))?;
+    if body[end..].starts_with('"') {
+        return Ok((&body[end + 1..], Cow::Borrowed(&body[..end])));
+    }
 
-        // 如果遇到引号 -> 结束
+    let mut res = String::with_capacity(body.len());
+    let mut output = body;
+    loop {
+        res.push_str(&output[..end]);
+        output = &output[end..];
         if output.starts_with('"') {
             break;
         }
+        let (rest, c) = parse_escape(&output[1..])?;
+        if let Some(c) = c {
+            res.push(c);
+        }
+        output = rest;
+        end = output.find(['"', '\\'])
+            .ok_or_else(|| Err::Error(nom::error::Error::new(output, ErrorKind::Char)))?;
+    }
 
-        // 读取一个字符
-        let (tmp, c) = none_of("\"")(output)?;
-        output = tmp;
-        res.push(c);
+    Ok((&output[1..], Cow::Owned(res)))
+}
+
+// ASCII控制字符的名字, SOH要在SO之前匹配
+const ASCII_ESCAPES: [(&str, u32); 34] = [
+    ("NUL", 0), ("SOH", 1), ("STX", 2), ("ETX", 3), ("EOT", 4), ("ENQ", 5), ("ACK", 6), ("BEL", 7),
+    ("BS", 8), ("HT", 9), ("LF", 10), ("VT", 11), ("FF", 12), ("CR", 13), ("SO", 14), ("SI", 15),
+    ("DLE", 16), ("DC1", 17), ("DC2", 18), ("DC3", 19), ("DC4", 20), ("NAK", 21), ("SYN", 22), ("ETB", 23),
+    ("CAN", 24), ("EM", 25), ("SUB", 26), ("ESC", 27), ("FS", 28), ("GS", 29), ("RS", 30), ("US", 31),
+    ("SP", 32), ("DEL", 127),
+];
+
+// 解析\后面的转义, 返回None表示\&和字符串间隔这类不产生字符的转义
+fn parse_escape(input: &str) -> IResult<&str, Option<char>> {
+    let fail = || Err::Failure(nom::error::Error::new(input, ErrorKind::Escaped));
+    let c = input.chars().next().ok_or_else(fail)?;
+    let rest = &input[c.len_utf8()..];
+    match c {
+        '0'..='9' => parse_code_point(input, 10),
+        'x' => parse_code_point(rest, 16),
+        'o' => parse_code_point(rest, 8),
+        '&' => Ok((rest, None)),
+        '^' => match rest.chars().next() {
+            Some(c @ '@'..='_') => Ok((&rest[1..], char::from_u32(c as u32 - 64))),
+            _ => Err(fail()),
+        },
+        c if c.is_whitespace() => {
+            let rest = rest.trim_start();
+            match rest.strip_prefix('\\') {
+                Some(rest) => Ok((rest, None)),
+                None => Err(fail()),
+            }
+        },
+        'a' => Ok((rest, Some('\u{7}'))),
+        'b' => Ok((rest, Some('\u{8}'))),
+        'f' => Ok((rest, Some('\u{c}'))),
+        'n' => Ok((rest, Some('\n'))),
+        'r' => Ok((rest, Some('\r'))),
+        't' => Ok((rest, Some('\t'))),
+        'v' => Ok((rest, Some('\u{b}'))),
+        '\\' | '"' | '\'' => Ok((rest, Some(c))),
+        _ => {
+            for (name, code) in ASCII_ESCAPES {
+                if let Some(rest) = input.strip_prefix(name) {
+                    return Ok((rest, char::from_u32(code)));
+                }
+            }
+            Err(fail())
+        }
     }
+}
 
-    (output, _) = char('"')(output)?;
-    Ok((output, res))
+// 数字形式的码点, 超出unicode范围时报错
+fn parse_code_point(input: &str, radix: u32) -> IResult<&str, Option<char>> {
+    let end = input.find(|c: char| !c.is_digit(radix)).unwrap_or(input.len());
+    match u32::from_str_radix(&input[..end], radix).ok().and_then(char::from_u32) {
+        Some(c) => Ok((&input[end..], Some(c))),
+        None => Err(Err::Failure(nom::error::Error::new(input, ErrorKind::Escaped))),
+    }
 }
 
 #[test]
 fn test_parse_symbol() {
     let test = r#"ESymbol Op "=""#;
-    assert_eq!(parse_symbol(test), Ok(("", node::Exp::ESymbol(node::TeXSymbolType::Op, "=".to_string()))));
+    assert_eq!(parse_symbol(test), Ok(("", node::Exp::ESymbol(node::TeXSymbolType::Op, "=".into()))));
     let test = r#"ESymbol Op "\8722""#;
-    assert_eq!(parse_symbol(test), Ok(("", node::Exp::ESymbol(node::TeXSymbolType::Op, "\\8722".to_string()))));
+    assert_eq!(parse_symbol(test), Ok(("", node::Exp::ESymbol(node::TeXSymbolType::Op, "\u{2212}".into()))));
+    // 没有转义的字符串直接借用输入
+    assert!(matches!(parse_symbol(test), Ok((_, Exp::ESymbol(_, Cow::Owned(_))))));
+    assert!(matches!(parse_symbol(r#"ESymbol Op "=""#), Ok((_, Exp::ESymbol(_, Cow::Borrowed("="))))));
 }
 
 // symbol: ESymbol TeXSymbolType String
-fn parse_symbol(input: &str) -> IResult<&str, node::Exp> {
+fn parse_symbol(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut input = input;
     let symbol_type;
     let text_type;
@@ -393,10 +354,10 @@ fn parse_symbol(input: &str) -> IResult<&str, node::Exp> {
 #[test]
 fn test_parse_number() {
     let test_case = "ENumber \"123\"";
-    assert_eq!(parse_number(test_case), Ok(("", node::Exp::ENumber("123".to_string()))));
+    assert_eq!(parse_number(test_case), Ok(("", node::Exp::ENumber("123".into()))));
 }
 // number: ENumber String
-fn parse_number(input: &str) -> IResult<&str, Exp> {
+fn parse_number(input: &str) -> IResult<&str, Exp<'_>> {
     let mut input = input;
     let number;
     (input, _) = multispace0(input)?;
@@ -409,11 +370,11 @@ fn parse_number(input: &str) -> IResult<&str, Exp> {
 #[test]
 fn test_parse_identifier() {
     let test_case = "EIdentifier \"x\"";
-    assert_eq!(parse_identifier(test_case), Ok(("", node::Exp::EIdentifier("x".to_string()))));
+    assert_eq!(parse_identifier(test_case), Ok(("", node::Exp::EIdentifier("x".into()))));
 }
 
 // identifier: EIdentifier String
-fn parse_identifier(input: &str) -> IResult<&str, Exp> {
+fn parse_identifier(input: &str) -> IResult<&str, Exp<'_>> {
     let mut input = input;
     let identifier;
     (input, _) = multispace0(input)?;
@@ -425,14 +386,14 @@ fn parse_identifier(input: &str) -> IResult<&str, Exp> {
 #[test]
 fn test_parse_math_operator() {
     let test_case = "EMathOperator \"sin\"";
-    assert_eq!(parse_math_operator(test_case), Ok(("", node::Exp::EMathOperator("sin".to_string()))));
+    assert_eq!(parse_math_operator(test_case), Ok(("", node::Exp::EMathOperator("sin".into()))));
 
     let test_case = r#"EMathOperator """#;
-    assert_eq!(parse_math_operator(test_case), Ok(("", node::Exp::EMathOperator("".to_string()))))
+    assert_eq!(parse_math_operator(test_case), Ok(("", node::Exp::EMathOperator("".into()))))
 }
 
 // math_operator: EMathOperator String
-fn parse_math_operator(input: &str) -> IResult<&str, node::Exp> {
+fn parse_math_operator(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut output = input;
     (output, _) = multispace0(output)?;
     (output, _) = tag("EMathOperator")(output)?;
@@ -479,7 +440,7 @@ fn test_parse_text() {
         parse_text(test_case), 
         Ok(
             ("", 
-            node::Exp::EText(node::TextType::TextNormal, "This is a test.".to_string()))
+            node::Exp::EText(node::TextType::TextNormal, "This is a test.".into()))
         ));
     let test_case = r#"
     EText TextNormal "0"
@@ -489,7 +450,7 @@ fn test_parse_text() {
 }
 
 // text: EText TextType String
-fn parse_text(input: &str) -> IResult<&str, node::Exp> {
+fn parse_text(input: &str) -> IResult<&str, node::Exp<'_>> {
     let (input, _) = tag("EText")(input)?;
     let (input, _) = multispace0(input)?;
     let (input, text_type) = parse_text_type(input)?;
@@ -513,16 +474,16 @@ fn test_parse_delimited() {
     assert_eq!(
         (output.trim(), res), 
         ("", 
-        node::Exp::EDelimited("|".to_string(), "|".to_string(), 
+        node::Exp::EDelimited("|".into(), "|".into(), 
         vec![node::InEDelimited::Right(
             node::Exp::EFraction(node::FractionType::NormalFrac,
-            Box::new(node::Exp::EIdentifier("H".to_string())),
-            Box::new(node::Exp::EIdentifier("K".to_string()))))]))    
+            Box::new(node::Exp::EIdentifier("H".into())),
+            Box::new(node::Exp::EIdentifier("K".into()))))]))    
     )
 }
 
 // delimited: EDelimited String String [Exp]
-fn parse_delimited(input: &str) -> IResult<&str, node::Exp> {
+fn parse_delimited(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut output = input;
     (output, _) = multispace0(output)?;
     (output, _) = tag("EDelimited")(output)?;
@@ -546,8 +507,8 @@ fn test_parse_grouped() {
             ("", 
             node::Exp::EGrouped(
                 vec![
-                    node::Exp::ENumber("1".to_string()), 
-                    node::Exp::ENumber("2".to_string())
+                    node::Exp::ENumber("1".into()), 
+                    node::Exp::ENumber("2".into())
                 ]
             ))
         ));
@@ -564,7 +525,7 @@ fn test_parse_grouped() {
 }
 
 // grouped: EGrouped [Exp]
-fn parse_grouped(input: &str) -> IResult<&str, node::Exp> {
+fn parse_grouped(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut ouput = input;
 
     (ouput, _) = multispace0(ouput)?;
@@ -584,8 +545,8 @@ fn test_parse_root() {
         parse_root(test_case), 
         Ok(
             ("", 
-            node::Exp::ERoot(Box::new(node::Exp::ENumber("1".to_string())), 
-            Box::new(node::Exp::ENumber("2".to_string()))))
+            node::Exp::ERoot(Box::new(node::Exp::ENumber("1".into())), 
+            Box::new(node::Exp::ENumber("2".into()))))
         ));
 
     let test_case = r#"ERoot (ENumber "1")"#;
@@ -593,14 +554,14 @@ fn test_parse_root() {
         parse_root(test_case),
         Ok(
             ("",
-            Exp::ERoot(Box::new(Exp::ENumber("1".to_string())),
-            Box::new(Exp::EText(TextType::TextNormal, "".to_string()))))
+            Exp::ERoot(Box::new(Exp::ENumber("1".into())),
+            Box::new(Exp::EText(TextType::TextNormal, "".into()))))
         ));
     println!("{:?}", parse_root(test_case));
 }
 
 // ERoot (Exp) (Exp)
-fn parse_root(input: &str) -> IResult<&str, node::Exp> {
+fn parse_root(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut input = input;
     let exp1;
     let exp2;
@@ -620,7 +581,7 @@ fn test_parse_sqrt() {
         parse_sqrt(test_case), 
         Ok(
             ("", 
-            node::Exp::ESqrt(Box::new(node::Exp::ENumber("1".to_string()))))
+            node::Exp::ESqrt(Box::new(node::Exp::ENumber("1".into()))))
         ));
 }
 
@@ -632,8 +593,8 @@ fn test_parse_fraction() {
         Ok(
             ("", 
             node::Exp::EFraction(node::FractionType::NormalFrac, 
-            Box::new(node::Exp::ENumber("1".to_string())), 
-            Box::new(node::Exp::ENumber("2".to_string()))))
+            Box::new(node::Exp::ENumber("1".into())), 
+            Box::new(node::Exp::ENumber("2".into()))))
         ));
     
     let test_case = r#"
@@ -670,7 +631,7 @@ fn parse_fraction_type(input: &str) -> IResult<&str, node::FractionType> {
 }
 
 // EFraction FractionType (Exp) (Exp)
-fn parse_fraction(input: &str) -> IResult<&str, node::Exp> {
+fn parse_fraction(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut input = input;
     let fraction_type;
     let exp1;
@@ -689,7 +650,7 @@ fn parse_fraction(input: &str) -> IResult<&str, node::Exp> {
 }
 
 // ESqrt (Exp)
-fn parse_sqrt(input: &str) -> IResult<&str, node::Exp> {
+fn parse_sqrt(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut input = input;
     let base;
     (input, _) = multispace0(input)?;
@@ -706,13 +667,13 @@ fn test_parse_super() {
         parse_super(test_case), 
         Ok(
             ("", 
-            node::Exp::ESuper(Box::new(node::Exp::ENumber("1".to_string())), 
-            Box::new(node::Exp::ENumber("2".to_string()))))
+            node::Exp::ESuper(Box::new(node::Exp::ENumber("1".into())), 
+            Box::new(node::Exp::ENumber("2".into()))))
         ));
 }
 
 // ESuper (Exp) (Exp)
-fn parse_super(input: &str) -> IResult<&str, node::Exp> {
+fn parse_super(input: &str) -> IResult<&str, node::Exp<'_>> {
     let exp1;
     let exp2;
     let mut input = input;
@@ -737,13 +698,13 @@ fn test_parse_sub() {
         parse_sub(test_case), 
         Ok(
             ("", 
-            Exp::ESub(Box::new(Exp::ENumber("1".to_string())),
-            Box::new(Exp::ENumber("2".to_string()))))
+            Exp::ESub(Box::new(Exp::ENumber("1".into())),
+            Box::new(Exp::ENumber("2".into()))))
         ));
 }
 
 // ESub (Exp) (Exp)
-fn parse_sub(input: &str) -> IResult<&str, node::Exp> {
+fn parse_sub(input: &str) -> IResult<&str, node::Exp<'_>> {
     let exp1;
     let exp2;
     let mut input = input;
@@ -769,14 +730,14 @@ fn test_parse_subsup() {
         parse_subsup(test_case), 
         Ok(
             ("", 
-            node::Exp::ESubsup(Box::new(node::Exp::ENumber("1".to_string())), 
-            Box::new(node::Exp::ENumber("2".to_string())), 
-            Box::new(node::Exp::ENumber("3".to_string()))))
+            node::Exp::ESubsup(Box::new(node::Exp::ENumber("1".into())), 
+            Box::new(node::Exp::ENumber("2".into())), 
+            Box::new(node::Exp::ENumber("3".into()))))
         ));
 }
 
 // ESubsup (Exp) (Exp) (Exp)
-fn parse_subsup(input: &str) -> IResult<&str, node::Exp> {
+fn parse_subsup(input: &str) -> IResult<&str, node::Exp<'_>> {
     let exp1;
     let exp2;
     let exp3;
@@ -800,8 +761,8 @@ fn test_parse_over() {
         Ok(
             ("", 
             Exp::EOver(false,
-            Box::new(Exp::EIdentifier("\\981".to_string())),
-            Box::new(Exp::ESymbol(node::TeXSymbolType::Accent, "\\771".to_string()))))
+            Box::new(Exp::EIdentifier("\u{3d5}".into())),
+            Box::new(Exp::ESymbol(node::TeXSymbolType::Accent, "\u{303}".into()))))
         )
     );
 }
@@ -818,7 +779,7 @@ fn parse_bool(input: &str) -> IResult<&str, bool> {
 }
 
 // (exp) -> exp or EText TextNormal "text"
-fn parse_exp_with_brace(input: &str) -> IResult<&str, Exp> {
+fn parse_exp_with_brace(input: &str) -> IResult<&str, Exp<'_>> {
     let mut output = input;
     let mut exp = Exp::EGrouped(Vec::new()); // for output {}
     (output, _) = multispace0(output)?;
@@ -835,7 +796,7 @@ fn parse_exp_with_brace(input: &str) -> IResult<&str, Exp> {
 }
 
 // EOver false (Exp) (Exp)
-fn parse_over(input: &str) -> IResult<&str, Exp> {
+fn parse_over(input: &str) -> IResult<&str, Exp<'_>> {
     let mut input = input;
     let exp1;
     let exp2;
@@ -851,7 +812,7 @@ fn parse_over(input: &str) -> IResult<&str, Exp> {
 }
 
 // EUnder false (Exp) (Exp)
-fn parse_under(input: &str) -> IResult<&str, node::Exp> {
+fn parse_under(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut input = input;
     let exp1;
     let exp2;
@@ -867,7 +828,7 @@ fn parse_under(input: &str) -> IResult<&str, node::Exp> {
 }
 
 // EUnderover false (Exp) (Exp) (Exp)
-fn parse_under_over(input: &str) -> IResult<&str, Exp> {
+fn parse_under_over(input: &str) -> IResult<&str, Exp<'_>> {
     let mut input = input;
     let exp1;
     let exp2;
@@ -891,7 +852,7 @@ fn test_parse_phantom() {
         parse_phantom(test_case), 
         Ok(
             ("", 
-            node::Exp::EPhantom(Box::new(node::Exp::ENumber("1".to_string()))))
+            node::Exp::EPhantom(Box::new(node::Exp::ENumber("1".into()))))
         ));
 }
 
@@ -904,7 +865,7 @@ fn test_parse_left() {
 }
 
 // Left "\8722"
-fn parse_left(input: &str) -> IResult<&str, node::InEDelimited> {
+fn parse_left(input: &str) -> IResult<&str, node::InEDelimited<'_>> {
     let mut output = input;
     (output, _) = multispace0(output)?;
     (output, _) = tag("Left")(output)?;
@@ -925,8 +886,8 @@ fn test_parse_right(){
         ("", node::InEDelimited::Right(
             node::Exp::EFraction(
                 node::FractionType::NormalFrac,
-                Box::new(node::Exp::ENumber("2".to_string())),
-                Box::new(node::Exp::EIdentifier("x".to_string()))
+                Box::new(node::Exp::ENumber("2".into())),
+                Box::new(node::Exp::EIdentifier("x".into()))
             )
         ))
     );
@@ -938,11 +899,11 @@ fn test_parse_right(){
     let (output, exp) = parse_right(test_case).unwrap();
     assert_eq!(
         (output.trim(), exp), 
-        ("", node::InEDelimited::Right(node::Exp::ENumber("5".to_string())))
+        ("", node::InEDelimited::Right(node::Exp::ENumber("5".into())))
     );
 }
 // Right (Exp)
-fn parse_right(input: &str) -> IResult<&str, node::InEDelimited> {
+fn parse_right(input: &str) -> IResult<&str, node::InEDelimited<'_>> {
     let mut output = input;
     (output, _) = multispace0(output)?;
     (output, _) = tag("Right")(output)?;
@@ -957,7 +918,7 @@ fn parse_right(input: &str) -> IResult<&str, node::InEDelimited> {
 }
 
 // EPhantom (Exp)
-fn parse_phantom(input: &str) -> IResult<&str, node::Exp> {
+fn parse_phantom(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut output = input;
     (output, _) = multispace0(output)?;
     (output, _) = tag("EPhantom")(output)?;
@@ -979,12 +940,12 @@ fn test_parse_boxed() {
         parse_boxed(test_case), 
         Ok(
             ("", 
-            node::Exp::EBoxed(Box::new(node::Exp::ENumber("1".to_string()))))
+            node::Exp::EBoxed(Box::new(node::Exp::ENumber("1".into()))))
         ));
 }
 
 // EBoxed (Exp)
-fn parse_boxed(input: &str) -> IResult<&str, node::Exp> {
+fn parse_boxed(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut output = input;
     (output, _) = multispace0(output)?;
     (output, _) = tag("EBoxed")(output)?;
@@ -1006,7 +967,7 @@ fn test_parse_styled() {
         Ok(
             ("", 
             node::Exp::EStyled(node::TextType::TextNormal,
-            vec![node::Exp::ENumber("1".to_string())]))
+            vec![node::Exp::ENumber("1".into())]))
         ));
 
     let test_case = r#"
@@ -1023,7 +984,7 @@ fn test_parse_styled() {
 }
 
 // EStyled TextType [Exp]
-fn parse_styled(input: &str) -> IResult<&str, node::Exp> {
+fn parse_styled(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut output = input;
     (output, _) = multispace0(output)?;
     (output, _) = tag("EStyled")(output)?;
@@ -1157,13 +1118,13 @@ fn test_parse_scaled() {
             ("", 
             node::Exp::EScaled(
                 node::Rational{numerator: 9, denominator: 5},
-                Box::new(node::Exp::ESymbol(node::TeXSymbolType::Open, "|".to_string()))
+                Box::new(node::Exp::ESymbol(node::TeXSymbolType::Open, "|".into()))
             ))
         ));
 }
 
 // EScaled (Rational) (Exp)
-fn parse_scaled(input: &str) -> IResult<&str, node::Exp> {
+fn parse_scaled(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut output = input;
     (output, _) = multispace0(output)?;
     (output, _) = tag("EScaled")(output)?;
@@ -1193,7 +1154,7 @@ fn test_parse_space() {
         ));
 }
 // ESpace (1 % 9)
-fn parse_space(input: &str) -> IResult<&str, node::Exp> {
+fn parse_space(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut output = input;
     (output, _) = multispace0(output)?;
     (output, _) = tag("ESpace")(output)?;
@@ -1211,7 +1172,7 @@ fn parse_alignment(input: &str) -> IResult<&str, node::Alignment> {
     ))(input)
 }
 
-fn parse_array(input: &str) -> IResult<&str, node::Exp> {
+fn parse_array(input: &str) -> IResult<&str, node::Exp<'_>> {
     let mut input = input;
     (input, _) = multispace0(input)?;
     (input, _) = tag("EArray")(input)?;
@@ -9,10 +9,10 @@ fn test_read_ast(){
     <TAB0|>[<TAB1|>ENumber "213",ESpace {1 % 1},ENumber "1",ESpace {1 % 1}
     "#;
     let exp = read_ast(ast).unwrap();
-    assert_eq!(exp, Exp::EText(TextNormal, "Hello".to_string()));
+    assert_eq!(exp, Exp::EText(TextNormal, "Hello".into()));
 }
 
-pub fn read_ast(ast: &str) -> Result<Exp, String>{
+pub fn read_ast(ast: &str) -> Result<Exp<'_>, String>{
     // TODO: 读取AST
-    Ok(Exp::EText(TextNormal, "Hello".to_string()))
+    Ok(Exp::EText(TextNormal, "Hello".into()))
 }
\ No newline at end of file
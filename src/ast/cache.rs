@@ -0,0 +1,88 @@
+// 有容量上限的LRU缓存, 用于server缓存转换结果
+// map保存值和最近一次访问的序号, order按序号排序, 淘汰时取序号最小的
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+pub struct LruCache<K, V> {
+    capacity: usize,
+    tick: u64,
+    map: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache {
+            capacity,
+            tick: 0,
+            map: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let tick = self.next_tick();
+        let (value, last) = self.map.get_mut(key)?;
+        self.order.remove(last);
+        *last = tick;
+        self.order.insert(tick, key.clone());
+        Some(value.clone())
+    }
+
+    pub fn put(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.next_tick();
+        if let Some((_, last)) = self.map.insert(key.clone(), (value, tick)) {
+            self.order.remove(&last);
+        }
+        self.order.insert(tick, key);
+
+        while self.map.len() > self.capacity {
+            match self.order.pop_first() {
+                Some((_, oldest)) => {
+                    self.map.remove(&oldest);
+                },
+                None => break,
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+#[test]
+fn test_lru_cache() {
+    let mut c = LruCache::new(2);
+    c.put("a", 1);
+    c.put("b", 2);
+    // 访问a后b成为最久未使用的
+    assert_eq!(c.get(&"a"), Some(1));
+    c.put("c", 3);
+    assert_eq!(c.get(&"b"), None);
+    assert_eq!(c.get(&"a"), Some(1));
+    assert_eq!(c.get(&"c"), Some(3));
+
+    // 更新已有的key不会淘汰其他key
+    c.put("c", 4);
+    assert_eq!(c.len(), 2);
+    assert_eq!(c.get(&"c"), Some(4));
+
+    let mut c = LruCache::new(0);
+    assert!(c.is_empty());
+    c.put("a", 1);
+    assert_eq!(c.get(&"a"), None);
+    assert!(c.is_empty());
+}
@@ -0,0 +1,280 @@
+// 读取texmath的json格式(aeson默认编码) -> Exp Node:
+// 构造器: {"tag": "ESub", "contents": [..]}, 只有一个参数时contents不是数组: {"tag": "ENumber", "contents": "1"}
+// 无参数的枚举直接是字符串: "Op", "TextBold", "AlignCenter"
+// Rational: {"numerator": 1, "denominator": 2}
+// InEDelimited: {"Left": "|"} 或 {"Right": Exp}
+use std::borrow::Cow;
+use serde_json::Value;
+use super::node::{self, Alignment, ArrayLines, Exp, FractionType, InEDelimited, Rational, TeXSymbolType, TextType};
+use super::reader::{ReadError, Reader};
+
+pub struct JsonReader;
+
+impl Reader for JsonReader {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn read<'a>(&self, input: &'a str) -> Result<Vec<Exp<'a>>, ReadError> {
+        // 嵌套深度由reader::check_depth按max_depth统一检查, 与native一致,
+        // 这里关闭serde_json的128层限制, 用serde_stacker在需要时扩展栈
+        let mut de = serde_json::Deserializer::from_str(input);
+        de.disable_recursion_limit();
+        let v: Value = serde::Deserialize::deserialize(serde_stacker::Deserializer::new(&mut de))
+            .and_then(|v| de.end().map(|_| v))
+            .map_err(|e| ReadError {
+                position: Some((e.line(), e.column())),
+                ..ReadError::new(format!("Parse error: {}", e))
+            })?;
+        read_value(&v).map_err(ReadError::new)
+    }
+}
+
+fn expect_str<'a>(v: &'a Value, what: &str) -> Result<&'a str, String> {
+    v.as_str().ok_or_else(|| format!("expected {} as string, got {}", what, v))
+}
+
+fn parse_string(v: &Value) -> Result<Cow<'static, str>, String> {
+    Ok(Cow::Owned(expect_str(v, "text")?.to_string()))
+}
+
+fn parse_bool(v: &Value) -> Result<bool, String> {
+    v.as_bool().ok_or_else(|| format!("expected bool, got {}", v))
+}
+
+fn parse_symbol_type(v: &Value) -> Result<TeXSymbolType, String> {
+    let s = expect_str(v, "TeXSymbolType")?;
+    let t = match s {
+        "Ord" => TeXSymbolType::Ord,
+        "Op" => TeXSymbolType::Op,
+        "Bin" => TeXSymbolType::Bin,
+        "Rel" => TeXSymbolType::Rel,
+        "Open" => TeXSymbolType::Open,
+        "Close" => TeXSymbolType::Close,
+        "Pun" => TeXSymbolType::Pun,
+        "Accent" => TeXSymbolType::Accent,
+        "Fence" => TeXSymbolType::Fence,
+        "TOver" => TeXSymbolType::TOver,
+        "TUnder" => TeXSymbolType::TUnder,
+        "Alpha" => TeXSymbolType::Alpha,
+        "BotAccent" => TeXSymbolType::BotAccent,
+        "Rad" => TeXSymbolType::Rad,
+        _ => return Err(format!("unknown TeXSymbolType: {}", s)),
+    };
+    Ok(t)
+}
+
+fn parse_text_type(v: &Value) -> Result<TextType, String> {
+    let s = expect_str(v, "TextType")?;
+    let t = match s {
+        "TextNormal" => TextType::TextNormal,
+        "TextBold" => TextType::TextBold,
+        "TextItalic" => TextType::TextItalic,
+        "TextMonospace" => TextType::TextMonospace,
+        "TextSansSerif" => TextType::TextSansSerif,
+        "TextDoubleStruck" => TextType::TextDoubleStruck,
+        "TextScript" => TextType::TextScript,
+        "TextFraktur" => TextType::TextFraktur,
+        "TextBoldItalic" => TextType::TextBoldItalic,
+        "TextSansSerifBold" => TextType::TextSansSerifBold,
+        "TextSansSerifBoldItalic" => TextType::TextSansSerifBoldItalic,
+        "TextBoldScript" => TextType::TextBoldScript,
+        "TextBoldFraktur" => TextType::TextBoldFraktur,
+        "TextSansSerifItalic" => TextType::TextSansSerifItalic,
+        _ => return Err(format!("unknown TextType: {}", s)),
+    };
+    Ok(t)
+}
+
+fn parse_fraction_type(v: &Value) -> Result<FractionType, String> {
+    let s = expect_str(v, "FractionType")?;
+    let t = match s {
+        "NormalFrac" => FractionType::NormalFrac,
+        "DisplayFrac" => FractionType::DisplayFrac,
+        "InlineFrac" => FractionType::InlineFrac,
+        "NoLineFrac" => FractionType::NoLineFrac,
+        _ => return Err(format!("unknown FractionType: {}", s)),
+    };
+    Ok(t)
+}
+
+fn parse_alignment(v: &Value) -> Result<Alignment, String> {
+    let s = expect_str(v, "Alignment")?;
+    let t = match s {
+        "AlignLeft" => Alignment::AlignLeft,
+        "AlignRight" => Alignment::AlignRight,
+        "AlignCenter" => Alignment::AlignCenter,
+        _ => return Err(format!("unknown Alignment: {}", s)),
+    };
+    Ok(t)
+}
+
+fn parse_rational(v: &Value) -> Result<Rational, String> {
+    let get = |key: &str| {
+        v.get(key)
+            .and_then(|n| n.as_i64())
+            .map(|n| n as i32)
+            .ok_or_else(|| format!("expected Rational with {}, got {}", key, v))
+    };
+    Ok(Rational {
+        numerator: get("numerator")?,
+        denominator: get("denominator")?,
+    })
+}
+
+fn parse_array<'a>(v: &'a Value, what: &str) -> Result<&'a Vec<Value>, String> {
+    v.as_array().ok_or_else(|| format!("expected {} as array, got {}", what, v))
+}
+
+fn parse_exp_list(v: &Value) -> Result<Vec<Exp<'static>>, String> {
+    parse_array(v, "[Exp]")?.iter().map(parse_exp).collect()
+}
+
+fn parse_box(v: &Value) -> Result<Box<Exp<'static>>, String> {
+    Ok(Box::new(parse_exp(v)?))
+}
+
+fn parse_indelimited(v: &Value) -> Result<InEDelimited<'static>, String> {
+    if let Some(s) = v.get("Left") {
+        return Ok(InEDelimited::Left(parse_string(s)?));
+    }
+    if let Some(e) = v.get("Right") {
+        return Ok(InEDelimited::Right(parse_exp(e)?));
+    }
+    Err(format!("expected Left or Right, got {}", v))
+}
+
+fn parse_array_lines(v: &Value) -> Result<ArrayLines<'static>, String> {
+    parse_array(v, "ArrayLine")?.iter().map(parse_exp_list).collect()
+}
+
+// 取出n个参数, 只有一个参数时contents就是参数本身
+fn parse_contents<'a>(tag: &str, contents: Option<&'a Value>, n: usize) -> Result<Vec<&'a Value>, String> {
+    let contents = contents.ok_or_else(|| format!("{}: missing contents", tag))?;
+    if n == 1 {
+        return Ok(vec![contents]);
+    }
+    let args = parse_array(contents, tag)?;
+    if args.len() != n {
+        return Err(format!("{}: expected {} arguments, got {}", tag, n, args.len()));
+    }
+    Ok(args.iter().collect())
+}
+
+fn parse_exp(v: &Value) -> Result<Exp<'static>, String> {
+    node::with_stack(|| parse_exp_inner(v))
+}
+
+fn parse_exp_inner(v: &Value) -> Result<Exp<'static>, String> {
+    let tag = v.get("tag")
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| format!("expected Exp with tag, got {}", v))?;
+    let contents = v.get("contents");
+    let args = |n: usize| parse_contents(tag, contents, n);
+
+    let exp = match tag {
+        "ENumber" => Exp::ENumber(parse_string(args(1)?[0])?),
+        "EIdentifier" => Exp::EIdentifier(parse_string(args(1)?[0])?),
+        "EMathOperator" => Exp::EMathOperator(parse_string(args(1)?[0])?),
+        "EGrouped" => Exp::EGrouped(parse_exp_list(args(1)?[0])?),
+        "ESymbol" => {
+            let a = args(2)?;
+            Exp::ESymbol(parse_symbol_type(a[0])?, parse_string(a[1])?)
+        },
+        "EDelimited" => {
+            let a = args(3)?;
+            let list = parse_array(a[2], "[InEDelimited]")?
+                .iter()
+                .map(parse_indelimited)
+                .collect::<Result<Vec<InEDelimited>, String>>()?;
+            Exp::EDelimited(parse_string(a[0])?, parse_string(a[1])?, list)
+        },
+        "ESpace" => Exp::ESpace(parse_rational(args(1)?[0])?),
+        "ESub" => {
+            let a = args(2)?;
+            Exp::ESub(parse_box(a[0])?, parse_box(a[1])?)
+        },
+        "ESuper" => {
+            let a = args(2)?;
+            Exp::ESuper(parse_box(a[0])?, parse_box(a[1])?)
+        },
+        "ESubsup" => {
+            let a = args(3)?;
+            Exp::ESubsup(parse_box(a[0])?, parse_box(a[1])?, parse_box(a[2])?)
+        },
+        "EOver" => {
+            let a = args(3)?;
+            Exp::EOver(parse_bool(a[0])?, parse_box(a[1])?, parse_box(a[2])?)
+        },
+        "EUnder" => {
+            let a = args(3)?;
+            Exp::EUnder(parse_bool(a[0])?, parse_box(a[1])?, parse_box(a[2])?)
+        },
+        "EUnderover" | "EUnderOver" => {
+            let a = args(4)?;
+            Exp::EUnderOver(parse_bool(a[0])?, parse_box(a[1])?, parse_box(a[2])?, parse_box(a[3])?)
+        },
+        "EPhantom" => Exp::EPhantom(parse_box(args(1)?[0])?),
+        "EBoxed" => Exp::EBoxed(parse_box(args(1)?[0])?),
+        "EFraction" => {
+            let a = args(3)?;
+            Exp::EFraction(parse_fraction_type(a[0])?, parse_box(a[1])?, parse_box(a[2])?)
+        },
+        "ERoot" => {
+            let a = args(2)?;
+            Exp::ERoot(parse_box(a[0])?, parse_box(a[1])?)
+        },
+        "ESqrt" => Exp::ESqrt(parse_box(args(1)?[0])?),
+        "EScaled" => {
+            let a = args(2)?;
+            Exp::EScaled(parse_rational(a[0])?, parse_box(a[1])?)
+        },
+        "EArray" => {
+            let a = args(2)?;
+            let aligns = parse_array(a[0], "[Alignment]")?
+                .iter()
+                .map(parse_alignment)
+                .collect::<Result<Vec<Alignment>, String>>()?;
+            let rows = parse_array(a[1], "[ArrayLine]")?
+                .iter()
+                .map(parse_array_lines)
+                .collect::<Result<Vec<ArrayLines>, String>>()?;
+            Exp::EArray(aligns, rows)
+        },
+        "EText" => {
+            let a = args(2)?;
+            Exp::EText(parse_text_type(a[0])?, parse_string(a[1])?)
+        },
+        "EStyled" => {
+            let a = args(2)?;
+            Exp::EStyled(parse_text_type(a[0])?, parse_exp_list(a[1])?)
+        },
+        _ => return Err(format!("unknown Exp tag: {}", tag)),
+    };
+    Ok(exp)
+}
+
+#[test]
+fn test_json_reader() {
+    use super::ast_reader::read_ast;
+    let native = r#"[EFraction NormalFrac (ESub (EIdentifier "x") (ENumber "1")) (ESymbol Op "\8721"),EDelimited "(" ")" [Left "|",Right (ESpace (1 % 6))],EArray [AlignCenter] [[[EIdentifier "a"]]]]"#;
+    let json = r#"[
+        {"tag": "EFraction", "contents": ["NormalFrac",
+            {"tag": "ESub", "contents": [{"tag": "EIdentifier", "contents": "x"}, {"tag": "ENumber", "contents": "1"}]},
+            {"tag": "ESymbol", "contents": ["Op", "∑"]}]},
+        {"tag": "EDelimited", "contents": ["(", ")", [{"Left": "|"}, {"Right": {"tag": "ESpace", "contents": {"numerator": 1, "denominator": 6}}}]]},
+        {"tag": "EArray", "contents": [["AlignCenter"], [[[{"tag": "EIdentifier", "contents": "a"}]]]]}
+    ]"#;
+    assert_eq!(JsonReader.read(json).unwrap(), read_ast(native).unwrap());
+
+    assert!(JsonReader.read(r#"[{"tag": "ESub", "contents": [{"tag": "EIdentifier", "contents": "x"}]}]"#).is_err());
+    assert_eq!(JsonReader.read("[\n{").unwrap_err().position, Some((2, 1)));
+}
+
+// 根可以是Exp数组或单个Exp
+fn read_value(v: &Value) -> Result<Vec<Exp<'static>>, String> {
+    match v {
+        Value::Array(_) => parse_exp_list(v),
+        _ => Ok(vec![parse_exp(v)?]),
+    }
+}
@@ -0,0 +1,176 @@
+// 用户自定义的符号和宏映射, 写出TeX时先于符号表使用
+// symbol: 单个字符 -> 命令, 如: ℝ -> \R, 在get_math_tex_many中覆盖或扩展符号表
+// exp: native格式的子树 -> 宏, 如: EStyled TextDoubleStruck [EIdentifier "R"] -> \R, 整个子树替换为宏
+//
+// toml格式:
+// [symbols]
+// "ℝ" = "\\R"
+// "U+2264" = "\\leqslant"
+// [[exps]]
+// native = 'EStyled TextDoubleStruck [EIdentifier "R"]'
+// tex = '\R'
+//
+// csv格式, 没有表头, 每行: "symbol"|"exp", 字符或native, 命令
+use std::{collections::HashMap, io, sync::{Arc, Mutex}};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use crate::config;
+use super::node::Exp;
+use super::{reader, shared, symbol_db};
+
+#[derive(Default, Debug)]
+pub struct Mapping {
+    symbols: HashMap<char, String>,
+    exps: Vec<(Exp<'static>, String)>,
+}
+
+#[derive(Deserialize)]
+struct MappingFile {
+    #[serde(default)]
+    symbols: HashMap<String, String>,
+    #[serde(default)]
+    exps: Vec<ExpMapping>,
+}
+
+#[derive(Deserialize)]
+struct ExpMapping {
+    native: String,
+    tex: String,
+}
+
+lazy_static! {
+    // 已加载的映射表, 按config.toml中[convert.mappings]的名字缓存, 进程内只加载一次
+    static ref MAPPINGS: Mutex<HashMap<String, Arc<Mapping>>> = Mutex::new(HashMap::new());
+    static ref EMPTY: Arc<Mapping> = Arc::new(Mapping::default());
+}
+
+#[test]
+fn test_mapping() {
+    let toml = r#"
+[symbols]
+"ℝ" = "\\R"
+"U+2264" = "\\leqslant"
+"\\8469" = "\\N"
+
+[[exps]]
+native = 'EStyled TextDoubleStruck [EIdentifier "R"]'
+tex = '\R'
+"#;
+    let m = Mapping::from_toml(toml).unwrap();
+    assert_eq!(m.symbol('\u{2264}'), Some("\\leqslant"));
+    assert_eq!(m.symbol('\u{211d}'), Some("\\R"));
+    assert_eq!(m.symbol('\u{2115}'), Some("\\N"));
+    assert_eq!(m.symbol('x'), None);
+
+    let styled = Exp::EStyled(crate::ast::node::TextType::TextDoubleStruck, vec![Exp::EIdentifier("R".into())]);
+    assert_eq!(m.exp(&styled), Some("\\R"));
+    assert_eq!(m.exp(&Exp::EIdentifier("R".into())), None);
+
+    let csv = "symbol,d,\\dd\nexp,\"EStyled TextNormal [EIdentifier \"\"d\"\"]\",\\dd\n";
+    let m = Mapping::from_csv(csv.as_bytes()).unwrap();
+    assert_eq!(m.symbol('d'), Some("\\dd"));
+    assert_eq!(m.exp(&Exp::EStyled(crate::ast::node::TextType::TextNormal, vec![Exp::EIdentifier("d".into())])), Some("\\dd"));
+
+    assert!(Mapping::from_toml("[symbols]\nab = \"x\"").is_err());
+    assert!(Mapping::from_toml("[[exps]]\nnative = 'EFoo'\ntex = 'x'").is_err());
+    assert!(Mapping::from_csv("word,a,b\n".as_bytes()).is_err());
+}
+
+impl Mapping {
+    pub fn from_toml(s: &str) -> Result<Mapping, String> {
+        let file: MappingFile = toml::from_str(s).map_err(|e| format!("mapping: {}", e))?;
+        let mut m = Mapping::default();
+        for (key, tex) in file.symbols {
+            m.add_symbol(&key, &tex)?;
+        }
+        for e in file.exps {
+            m.add_exp(&e.native, &e.tex)?;
+        }
+        Ok(m)
+    }
+
+    pub fn from_csv<R: io::Read>(r: R) -> Result<Mapping, String> {
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(r);
+        let mut m = Mapping::default();
+        for result in reader.records() {
+            let record = result.map_err(|e| format!("mapping: {}", e))?;
+            match (record.get(0), record.get(1), record.get(2)) {
+                (Some("symbol"), Some(key), Some(tex)) => m.add_symbol(key, tex)?,
+                (Some("exp"), Some(native), Some(tex)) => m.add_exp(native, tex)?,
+                _ => return Err(format!("mapping: invalid record {:?}", record)),
+            }
+        }
+        Ok(m)
+    }
+
+    // 按扩展名选择格式, .csv为csv, 其他为toml
+    pub fn load(path: &str) -> Result<Mapping, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| format!("mapping: {}: {}", path, e))?;
+        match path.ends_with(".csv") {
+            true => Mapping::from_csv(content.as_bytes()),
+            false => Mapping::from_toml(&content),
+        }
+    }
+
+    // 字符可以是单个字符, U+2264, 或符号表中的\8804
+    pub fn add_symbol(&mut self, key: &str, tex: &str) -> Result<(), String> {
+        let c = symbol_db::parse_char_query(key)
+            .or_else(|| shared::parse_as_unicode_char(key))
+            .ok_or_else(|| format!("mapping: invalid character {:?}", key))?;
+        self.symbols.insert(c, tex.to_string());
+        Ok(())
+    }
+
+    // native为单个表达式, 如: EStyled TextDoubleStruck [EIdentifier "R"]
+    pub fn add_exp(&mut self, native: &str, tex: &str) -> Result<(), String> {
+        let input = format!("[{}]", native);
+        let exps = reader::read_with("native", &input)
+            .map_err(|e| format!("mapping: {:?}: {}", native, e))?;
+        match <[Exp; 1]>::try_from(exps) {
+            Ok([exp]) => {
+                self.exps.push((exp.into_owned(), tex.to_string()));
+                Ok(())
+            },
+            Err(_) => Err(format!("mapping: expect a single expression, got {:?}", native)),
+        }
+    }
+
+    pub fn symbol(&self, c: char) -> Option<&str> {
+        self.symbols.get(&c).map(|s| s.as_str())
+    }
+
+    pub fn exp(&self, e: &Exp) -> Option<&str> {
+        self.exps.iter().find(|(exp, _)| exp == e).map(|(_, tex)| tex.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty() && self.exps.is_empty()
+    }
+}
+
+// 按名字取映射表, 名字对应config.toml中[convert.mappings]的文件, 第一次使用时加载
+pub fn get_mapping(name: &str) -> Result<Arc<Mapping>, String> {
+    let mut mappings = MAPPINGS.lock().map_err(|e| e.to_string())?;
+    if let Some(m) = mappings.get(name) {
+        return Ok(m.clone());
+    }
+    let conf = &config::get_config().convert;
+    let path = conf.mappings.get(name).ok_or_else(|| {
+        format!("unknown mapping: {}, available: {}", name, conf.mapping_names().join(", "))
+    })?;
+    let m = Arc::new(Mapping::load(path)?);
+    mappings.insert(name.to_string(), m.clone());
+    Ok(m)
+}
+
+// 写出时使用的映射表: 选项中的mapping, 没有时使用config.toml中[convert]的mapping, 都没有时为空
+pub fn select(extra: &HashMap<String, String>) -> Result<Arc<Mapping>, String> {
+    match extra.get("mapping").or(config::get_config().convert.mapping.as_ref()) {
+        Some(name) if !name.is_empty() => get_mapping(name),
+        _ => Ok(EMPTY.clone()),
+    }
+}
+
+pub fn empty() -> Arc<Mapping> {
+    EMPTY.clone()
+}
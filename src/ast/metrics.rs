@@ -0,0 +1,148 @@
+// server的运行指标, 以Prometheus文本格式输出:
+// texmath_requests_total{from, to}: 转换次数
+// texmath_errors_total{stage}: 按阶段统计的错误次数, 超时的stage为timeout
+// texmath_convert_duration_seconds{from, to}: 转换耗时直方图
+// texmath_cache_hits_total, texmath_cache_misses_total, texmath_cache_entries: 转换结果缓存
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use lazy_static::lazy_static;
+
+// 直方图的桶上界(秒)
+const LATENCY_BUCKETS: [f64; 10] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()], // 每个桶的计数, 不累计
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|b| secs <= *b) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+#[derive(Default)]
+struct MetricsInner {
+    requests: BTreeMap<(String, String), u64>,
+    errors: BTreeMap<String, u64>,
+    latency: BTreeMap<(String, String), Histogram>,
+    cache_hits: u64,
+    cache_misses: u64,
+    cache_entries: usize,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<MetricsInner>,
+}
+
+lazy_static! {
+    static ref METRICS: Metrics = Metrics::default();
+}
+
+pub fn global() -> &'static Metrics {
+    &METRICS
+}
+
+// label值中的\ " 换行需要转义
+fn escape_label(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Metrics {
+    // 记录一次转换, from/to应为已注册的格式名, 避免任意字符串导致label过多
+    pub fn record_conversion(&self, from: &str, to: &str, elapsed: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        let key = (from.to_string(), to.to_string());
+        *inner.requests.entry(key.clone()).or_insert(0) += 1;
+        inner.latency.entry(key).or_default().observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record_error(&self, stage: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.errors.entry(stage.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn record_cache(&self, hit: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        match hit {
+            true => inner.cache_hits += 1,
+            false => inner.cache_misses += 1,
+        }
+    }
+
+    pub fn set_cache_entries(&self, entries: usize) {
+        self.inner.lock().unwrap().cache_entries = entries;
+    }
+
+    pub fn render(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP texmath_requests_total Number of conversions by input and output format.\n");
+        out.push_str("# TYPE texmath_requests_total counter\n");
+        for ((from, to), n) in &inner.requests {
+            writeln!(out, "texmath_requests_total{{from=\"{}\",to=\"{}\"}} {}", escape_label(from), escape_label(to), n).unwrap();
+        }
+
+        out.push_str("# HELP texmath_errors_total Number of failed requests by stage.\n");
+        out.push_str("# TYPE texmath_errors_total counter\n");
+        for (stage, n) in &inner.errors {
+            writeln!(out, "texmath_errors_total{{stage=\"{}\"}} {}", escape_label(stage), n).unwrap();
+        }
+
+        out.push_str("# HELP texmath_convert_duration_seconds Conversion latency in seconds.\n");
+        out.push_str("# TYPE texmath_convert_duration_seconds histogram\n");
+        for ((from, to), h) in &inner.latency {
+            let labels = format!("from=\"{}\",to=\"{}\"", escape_label(from), escape_label(to));
+            let mut cumulative = 0;
+            for (bound, n) in LATENCY_BUCKETS.iter().zip(h.buckets.iter()) {
+                cumulative += n;
+                writeln!(out, "texmath_convert_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, cumulative).unwrap();
+            }
+            writeln!(out, "texmath_convert_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, h.count).unwrap();
+            writeln!(out, "texmath_convert_duration_seconds_sum{{{}}} {}", labels, h.sum).unwrap();
+            writeln!(out, "texmath_convert_duration_seconds_count{{{}}} {}", labels, h.count).unwrap();
+        }
+
+        out.push_str("# HELP texmath_cache_hits_total Number of conversions served from the cache.\n");
+        out.push_str("# TYPE texmath_cache_hits_total counter\n");
+        writeln!(out, "texmath_cache_hits_total {}", inner.cache_hits).unwrap();
+        out.push_str("# HELP texmath_cache_misses_total Number of conversions not found in the cache.\n");
+        out.push_str("# TYPE texmath_cache_misses_total counter\n");
+        writeln!(out, "texmath_cache_misses_total {}", inner.cache_misses).unwrap();
+        out.push_str("# HELP texmath_cache_entries Number of cached conversion results.\n");
+        out.push_str("# TYPE texmath_cache_entries gauge\n");
+        writeln!(out, "texmath_cache_entries {}", inner.cache_entries).unwrap();
+        out
+    }
+}
+
+#[test]
+fn test_metrics_render() {
+    let m = Metrics::default();
+    m.record_conversion("native", "tex", Duration::from_micros(300));
+    m.record_conversion("native", "tex", Duration::from_millis(20));
+    m.record_error("read");
+    m.record_cache(false);
+    m.record_cache(true);
+    m.set_cache_entries(1);
+
+    let out = m.render();
+    assert!(out.contains("texmath_requests_total{from=\"native\",to=\"tex\"} 2\n"));
+    assert!(out.contains("texmath_errors_total{stage=\"read\"} 1\n"));
+    assert!(out.contains("texmath_convert_duration_seconds_bucket{from=\"native\",to=\"tex\",le=\"0.0005\"} 1\n"));
+    assert!(out.contains("texmath_convert_duration_seconds_bucket{from=\"native\",to=\"tex\",le=\"0.025\"} 2\n"));
+    assert!(out.contains("texmath_convert_duration_seconds_count{from=\"native\",to=\"tex\"} 2\n"));
+    assert!(out.contains("texmath_cache_hits_total 1\n"));
+    assert!(out.contains("texmath_cache_misses_total 1\n"));
+    assert!(out.contains("texmath_cache_entries 1\n"));
+}
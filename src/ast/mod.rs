@@ -1,9 +1,20 @@
 pub mod node;
+pub mod writer;
 pub mod tex_writer;
+pub mod tex_pretty;
+pub mod unicode_writer;
+pub mod speech_writer;
 
 mod tex_unicode;
+pub mod symbol_db;
+pub mod mapping;
+pub mod reader;
 pub mod ast_reader;
+pub mod json_reader;
 pub mod shared;
 pub mod judge;
 pub mod server;
+pub mod metrics;
+pub mod cache;
+pub mod openapi;
 pub mod ast_reader_2;
\ No newline at end of file
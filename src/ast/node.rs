@@ -54,9 +54,11 @@ Alignment = AlignLeft | AlignRight | AlignCenter
  */
 
 
+use std::borrow::Cow;
+
 // 定义所有的节点
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, schemars::JsonSchema)]
 pub enum TeXSymbolType {
     Ord,
     Op,
@@ -115,46 +117,196 @@ pub struct Rational {
 }
 
 #[derive(PartialEq, Debug, Clone)]
-pub enum InEDelimited {
-    Left(String),
-    Right(Exp),
+pub enum InEDelimited<'a> {
+    Left(Cow<'a, str>),
+    Right(Exp<'a>),
 }
 
-pub type ArrayLines = Vec<Vec<Exp>>;
+pub type ArrayLines<'a> = Vec<Vec<Exp<'a>>>;
 
+// 字符串借用读取时的输入, 没有转义的字符串不需要分配; 需要比输入活得更久时用into_owned
 #[derive(PartialEq, Debug, Clone)]
-pub enum Exp{
-    EGrouped(Vec<Exp>), // -> EGrouped[ ]
-    EDelimited(String, String, Vec<InEDelimited>), // -> EDelimited[ ]
-    EArray(Vec<Alignment>, Vec<ArrayLines>), // -> EArray[ ]
+pub enum Exp<'a>{
+    EGrouped(Vec<Exp<'a>>), // -> EGrouped[ ]
+    EDelimited(Cow<'a, str>, Cow<'a, str>, Vec<InEDelimited<'a>>), // -> EDelimited[ ]
+    EArray(Vec<Alignment>, Vec<ArrayLines<'a>>), // -> EArray[ ]
 
-    ENumber(String),
-    EIdentifier(String),
+    ENumber(Cow<'a, str>),
+    EIdentifier(Cow<'a, str>),
 
-    EMathOperator(String),
-    ESymbol(TeXSymbolType, String),
+    EMathOperator(Cow<'a, str>),
+    ESymbol(TeXSymbolType, Cow<'a, str>),
 
     ESpace(Rational),
 
     // super and sub
-    ESub(Box<Exp>, Box<Exp>),
-    ESubsup(Box<Exp>, Box<Exp>, Box<Exp>),
-    ESuper(Box<Exp>, Box<Exp>),
+    ESub(Box<Exp<'a>>, Box<Exp<'a>>),
+    ESubsup(Box<Exp<'a>>, Box<Exp<'a>>, Box<Exp<'a>>),
+    ESuper(Box<Exp<'a>>, Box<Exp<'a>>),
     
     // over and under
-    EOver(bool, Box<Exp>, Box<Exp>),
-    EUnder(bool, Box<Exp>, Box<Exp>),
-    EUnderOver(bool, Box<Exp>, Box<Exp>, Box<Exp>),
+    EOver(bool, Box<Exp<'a>>, Box<Exp<'a>>),
+    EUnder(bool, Box<Exp<'a>>, Box<Exp<'a>>),
+    EUnderOver(bool, Box<Exp<'a>>, Box<Exp<'a>>, Box<Exp<'a>>),
     // fraction
-    EFraction(FractionType, Box<Exp>, Box<Exp>),
-    ERoot(Box<Exp>, Box<Exp>),
-    ESqrt(Box<Exp>),
+    EFraction(FractionType, Box<Exp<'a>>, Box<Exp<'a>>),
+    ERoot(Box<Exp<'a>>, Box<Exp<'a>>),
+    ESqrt(Box<Exp<'a>>),
 
-    EPhantom(Box<Exp>),
-    EBoxed(Box<Exp>),
+    EPhantom(Box<Exp<'a>>),
+    EBoxed(Box<Exp<'a>>),
 
     // 文本
-    EScaled(Rational, Box<Exp>),
-    EText(TextType, String),
-    EStyled(TextType, Vec<Exp>),
-}
\ No newline at end of file
+    EScaled(Rational, Box<Exp<'a>>),
+    EText(TextType, Cow<'a, str>),
+    EStyled(TextType, Vec<Exp<'a>>),
+}
+// 默认的最大嵌套深度: 读取时为输入中括号的嵌套层数, 写出时为Exp的嵌套层数
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+// 递归读取/写出每层都会占用较多的栈(debug下约10KB), 递归的入口通过with_stack调用,
+// 剩余栈空间不足时在堆上分配新的栈段, 不依赖调用线程的栈大小
+pub fn with_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(64 * 1024, 1024 * 1024, f)
+}
+
+impl<'a> Exp<'a> {
+    // 节点的名字, 与native格式的构造器名相同
+    pub fn name(&self) -> &'static str {
+        match self {
+            Exp::EGrouped(_) => "EGrouped",
+            Exp::EDelimited(..) => "EDelimited",
+            Exp::EArray(..) => "EArray",
+            Exp::ENumber(_) => "ENumber",
+            Exp::EIdentifier(_) => "EIdentifier",
+            Exp::EMathOperator(_) => "EMathOperator",
+            Exp::ESymbol(..) => "ESymbol",
+            Exp::ESpace(_) => "ESpace",
+            Exp::ESub(..) => "ESub",
+            Exp::ESubsup(..) => "ESubsup",
+            Exp::ESuper(..) => "ESuper",
+            Exp::EOver(..) => "EOver",
+            Exp::EUnder(..) => "EUnder",
+            Exp::EUnderOver(..) => "EUnderOver",
+            Exp::EFraction(..) => "EFraction",
+            Exp::ERoot(..) => "ERoot",
+            Exp::ESqrt(_) => "ESqrt",
+            Exp::EPhantom(_) => "EPhantom",
+            Exp::EBoxed(_) => "EBoxed",
+            Exp::EScaled(..) => "EScaled",
+            Exp::EText(..) => "EText",
+            Exp::EStyled(..) => "EStyled",
+        }
+    }
+
+    // 直接子表达式
+    pub fn children(&self) -> Vec<&Exp<'a>> {
+        match self {
+            Exp::EGrouped(v) | Exp::EStyled(_, v) => v.iter().collect(),
+            Exp::EDelimited(_, _, v) => v.iter().filter_map(|e| match e {
+                InEDelimited::Right(e) => Some(e),
+                InEDelimited::Left(_) => None,
+            }).collect(),
+            Exp::EArray(_, rows) => rows.iter().flatten().flatten().collect(),
+            Exp::ESub(a, b) | Exp::ESuper(a, b) | Exp::EOver(_, a, b) | Exp::EUnder(_, a, b)
+            | Exp::EFraction(_, a, b) | Exp::ERoot(a, b) => vec![a, b],
+            Exp::ESubsup(a, b, d) | Exp::EUnderOver(_, a, b, d) => vec![a, b, d],
+            Exp::ESqrt(a) | Exp::EPhantom(a) | Exp::EBoxed(a) | Exp::EScaled(_, a) => vec![a],
+            Exp::ENumber(_) | Exp::EIdentifier(_) | Exp::EMathOperator(_) | Exp::ESymbol(_, _)
+            | Exp::ESpace(_) | Exp::EText(_, _) => vec![],
+        }
+    }
+
+    // 取出子表达式, 剩下的节点不再包含子表达式
+    fn into_children(self) -> Vec<Exp<'a>> {
+        match self {
+            Exp::EGrouped(v) | Exp::EStyled(_, v) => v,
+            Exp::EDelimited(_, _, v) => v.into_iter().filter_map(|e| match e {
+                InEDelimited::Right(e) => Some(e),
+                InEDelimited::Left(_) => None,
+            }).collect(),
+            Exp::EArray(_, rows) => rows.into_iter().flatten().flatten().collect(),
+            Exp::ESub(a, b) | Exp::ESuper(a, b) | Exp::EOver(_, a, b) | Exp::EUnder(_, a, b)
+            | Exp::EFraction(_, a, b) | Exp::ERoot(a, b) => vec![*a, *b],
+            Exp::ESubsup(a, b, d) | Exp::EUnderOver(_, a, b, d) => vec![*a, *b, *d],
+            Exp::ESqrt(a) | Exp::EPhantom(a) | Exp::EBoxed(a) | Exp::EScaled(_, a) => vec![*a],
+            _ => vec![],
+        }
+    }
+
+    // 复制借用的字符串, 得到不依赖输入的表达式
+    pub fn into_owned(self) -> Exp<'static> {
+        with_stack(|| self.into_owned_inner())
+    }
+
+    fn into_owned_inner(self) -> Exp<'static> {
+        let own = |s: Cow<'a, str>| Cow::Owned(s.into_owned());
+        let boxed = |e: Box<Exp<'a>>| Box::new(e.into_owned());
+        let list = |v: Vec<Exp<'a>>| v.into_iter().map(Exp::into_owned).collect();
+        match self {
+            Exp::EGrouped(v) => Exp::EGrouped(list(v)),
+            Exp::EDelimited(l, r, v) => Exp::EDelimited(own(l), own(r), v.into_iter().map(|e| match e {
+                InEDelimited::Left(s) => InEDelimited::Left(own(s)),
+                InEDelimited::Right(e) => InEDelimited::Right(e.into_owned()),
+            }).collect()),
+            Exp::EArray(aligns, rows) => Exp::EArray(aligns, rows.into_iter()
+                .map(|row| row.into_iter().map(list).collect()).collect()),
+            Exp::ENumber(s) => Exp::ENumber(own(s)),
+            Exp::EIdentifier(s) => Exp::EIdentifier(own(s)),
+            Exp::EMathOperator(s) => Exp::EMathOperator(own(s)),
+            Exp::ESymbol(t, s) => Exp::ESymbol(t, own(s)),
+            Exp::ESpace(r) => Exp::ESpace(r),
+            Exp::ESub(a, b) => Exp::ESub(boxed(a), boxed(b)),
+            Exp::ESubsup(a, b, d) => Exp::ESubsup(boxed(a), boxed(b), boxed(d)),
+            Exp::ESuper(a, b) => Exp::ESuper(boxed(a), boxed(b)),
+            Exp::EOver(f, a, b) => Exp::EOver(f, boxed(a), boxed(b)),
+            Exp::EUnder(f, a, b) => Exp::EUnder(f, boxed(a), boxed(b)),
+            Exp::EUnderOver(f, a, b, d) => Exp::EUnderOver(f, boxed(a), boxed(b), boxed(d)),
+            Exp::EFraction(t, a, b) => Exp::EFraction(t, boxed(a), boxed(b)),
+            Exp::ERoot(a, b) => Exp::ERoot(boxed(a), boxed(b)),
+            Exp::ESqrt(a) => Exp::ESqrt(boxed(a)),
+            Exp::EPhantom(a) => Exp::EPhantom(boxed(a)),
+            Exp::EBoxed(a) => Exp::EBoxed(boxed(a)),
+            Exp::EScaled(r, a) => Exp::EScaled(r, boxed(a)),
+            Exp::EText(t, s) => Exp::EText(t, own(s)),
+            Exp::EStyled(t, v) => Exp::EStyled(t, list(v)),
+        }
+    }
+}
+
+#[test]
+fn test_into_owned() {
+    let input = String::from("x");
+    let e = Exp::ESqrt(Box::new(Exp::EIdentifier(Cow::Borrowed(&input))));
+    let owned = e.clone().into_owned();
+    drop(input);
+    assert!(matches!(&owned, Exp::ESqrt(a) if matches!(&**a, Exp::EIdentifier(Cow::Owned(s)) if s == "x")));
+}
+
+#[test]
+fn test_depth() {
+    let x = Exp::EIdentifier("x".into());
+    assert_eq!(depth(&[]), 0);
+    assert_eq!(depth(std::slice::from_ref(&x)), 1);
+    let e = Exp::ESub(Box::new(x.clone()), Box::new(Exp::EGrouped(vec![x.clone()])));
+    assert_eq!(depth(&[x, e]), 3);
+}
+
+// 嵌套深度, 用显式栈遍历, 不会因为嵌套过深而栈溢出
+pub fn depth(exps: &[Exp]) -> usize {
+    let mut max = 0;
+    let mut stack: Vec<(&Exp, usize)> = exps.iter().map(|e| (e, 1)).collect();
+    while let Some((e, d)) = stack.pop() {
+        max = max.max(d);
+        stack.extend(e.children().into_iter().map(|c| (c, d + 1)));
+    }
+    max
+}
+
+// 逐层拆开后释放, 默认的drop对过深的表达式会递归到栈溢出
+pub fn drop_deep(exps: Vec<Exp>) {
+    let mut stack = exps;
+    while let Some(e) = stack.pop() {
+        stack.extend(e.into_children());
+    }
+}
@@ -0,0 +1,163 @@
+// server的OpenAPI 3.0文档, 由GET /openapi.json返回
+// 请求/响应的schema由server.rs中的ServerRequest/ServerResponse/ServerError等类型派生(schemars),
+// from/to/dialect/mapping的可选值在生成时从已注册的reader/writer和config.toml中读取
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Map, Value};
+
+use crate::ast;
+use crate::ast::server::{Formats, ServerRequest, ServerResponse, SymbolInfo};
+use crate::config;
+
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "texmath-rust",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/convert": {
+                "post": {
+                    "summary": "Convert a single expression",
+                    "requestBody": {
+                        "required": true,
+                        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ServerRequest"}}},
+                    },
+                    "responses": {
+                        "200": response_ref("Converted output"),
+                        "400": response_ref("Invalid request, unknown format, dialect, mapping or unmapped policy"),
+                        "413": response_ref("Request body too large"),
+                        "415": response_ref("Content-Type is not application/json"),
+                        "422": response_ref("Input could not be read or written"),
+                        "500": response_ref("Internal error"),
+                        "503": response_ref("Conversion timed out"),
+                    },
+                },
+            },
+            "/convert/batch": {
+                "post": {
+                    "summary": "Convert a JSON array or NDJSON stream of requests",
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": {"schema": {"type": "array", "items": {"$ref": "#/components/schemas/ServerRequest"}}},
+                            "application/x-ndjson": {"schema": {"$ref": "#/components/schemas/ServerRequest"}},
+                        },
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "One response per request, in input order",
+                            "content": {
+                                "application/json": {"schema": {"type": "array", "items": {"$ref": "#/components/schemas/ServerResponse"}}},
+                                "application/x-ndjson": {"schema": {"$ref": "#/components/schemas/ServerResponse"}},
+                            },
+                        },
+                        "400": response_ref("Body is not a JSON array"),
+                        "413": response_ref("Request body too large or more than max_batch_items requests"),
+                    },
+                },
+            },
+            "/formats": {
+                "get": {
+                    "summary": "List supported formats, packages and dialects",
+                    "responses": {
+                        "200": {
+                            "description": "Server capabilities",
+                            "content": {"application/json": {"schema": {"$ref": "#/components/schemas/Formats"}}},
+                        },
+                    },
+                },
+            },
+            "/symbols": {
+                "get": {
+                    "summary": "Look up symbols by character, command or package",
+                    "parameters": [
+                        query_param("char", "A single character or U+XXXX; returns the commands that render it under packages"),
+                        query_param("command", "A command such as \\leqslant; the leading backslash may be omitted"),
+                        query_param("package", "Returns the symbols the package provides"),
+                        query_param("packages", "Comma separated packages used with char, in priority order; base commands come first"),
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Matching symbols, base first for char queries",
+                            "content": {"application/json": {"schema": {"type": "array", "items": {"$ref": "#/components/schemas/Symbol"}}}},
+                        },
+                        "400": response_ref("Not exactly one of char, command and package, or an invalid char"),
+                        "500": response_ref("Symbol tables could not be loaded"),
+                    },
+                },
+            },
+            "/openapi.json": {"get": {"summary": "This document", "responses": {"200": {
+                "description": "OpenAPI 3.0 document",
+                "content": {"application/json": {"schema": {"type": "object"}}},
+            }}}},
+            "/healthz": {"get": {"summary": "Liveness probe", "responses": {"200": text_response("ok")}}},
+            "/readyz": {"get": {"summary": "Readiness probe", "responses": {
+                "200": text_response("Symbol tables loaded"),
+                "503": text_response("Symbol tables not loaded"),
+            }}},
+            "/metrics": {"get": {"summary": "Prometheus metrics", "responses": {"200": text_response("Prometheus text format")}}},
+        },
+        "components": {"schemas": schemas()},
+    })
+}
+
+// components.schemas: 从类型派生, 再补上运行时才知道的可选值和&'static str字段的可选值
+fn schemas() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    gen.subschema_for::<ServerRequest>();
+    gen.subschema_for::<ServerResponse>();
+    gen.subschema_for::<Formats>();
+    gen.subschema_for::<SymbolInfo>();
+    // openapi3的visitor(如$ref不能有兄弟字段)只作用于根schema, 这里对每个定义分别应用
+    let mut definitions = gen.take_definitions();
+    for schema in definitions.values_mut() {
+        for visitor in gen.visitors_mut() {
+            visitor.visit_schema(schema);
+        }
+    }
+    let mut schemas: Map<String, Value> = definitions.into_iter()
+        .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap()))
+        .collect();
+
+    let conf = &config::get_config().convert;
+    set_enum(&mut schemas, "ServerRequest", "from", ast::reader::reader_names());
+    set_enum(&mut schemas, "ServerRequest", "to", ast::writer::writer_names());
+    set_enum(&mut schemas, "ServerRequest", "dialect", conf.dialect_names());
+    set_enum(&mut schemas, "ServerRequest", "mapping", conf.mapping_names());
+    set_enum(&mut schemas, "ServerError", "stage", vec!["request", "read", "write", "internal"]);
+    set_enum(&mut schemas, "ServerError", "code", vec![
+        "invalid_request", "unknown_format", "parse_error", "too_deep",
+        "write_error", "internal_error", "timeout", "too_many_items",
+    ]);
+    set_enum(&mut schemas, "Warning", "kind", vec!["style", "text_style", "scaled", "accent", "symbol", "delimiter", "space"]);
+    Value::Object(schemas)
+}
+
+fn set_enum<T: serde::Serialize>(schemas: &mut Map<String, Value>, schema: &str, property: &str, values: Vec<T>) {
+    schemas[schema]["properties"][property]["enum"] = json!(values);
+}
+
+fn response_ref(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/ServerResponse"}}},
+    })
+}
+
+fn query_param(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "schema": {"type": "string"},
+        "description": description,
+    })
+}
+
+fn text_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": {"text/plain": {"schema": {"type": "string"}}},
+    })
+}
@@ -0,0 +1,168 @@
+// 输入格式的统一接口: 每种输入(native, json...)实现Reader, 注册到READERS
+// server的from字段通过格式名在READERS中查找, 未注册的格式直接报错
+use std::collections::HashMap;
+use lazy_static::lazy_static;
+use super::node::{self, Exp};
+use super::{ast_reader, json_reader};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReadErrorKind {
+    Parse, // 语法错误
+    TooDeep, // 嵌套超过max_depth
+}
+
+// 读取错误, position为出错位置(行, 列), 都从1开始, 无法定位时为None
+#[derive(Debug, PartialEq)]
+pub struct ReadError {
+    pub kind: ReadErrorKind,
+    pub message: String,
+    pub position: Option<(usize, usize)>,
+}
+
+impl ReadError {
+    pub fn new(message: String) -> ReadError {
+        ReadError { kind: ReadErrorKind::Parse, message, position: None }
+    }
+
+    // 由剩余未读取的输入计算出错位置
+    pub fn at_rest(message: String, input: &str, rest: &str) -> ReadError {
+        let offset = input.len() - rest.len();
+        let before = &input[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = match before.rfind('\n') {
+            Some(i) => before[i + 1..].chars().count() + 1,
+            None => before.chars().count() + 1,
+        };
+        ReadError { kind: ReadErrorKind::Parse, message, position: Some((line, column)) }
+    }
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "{} at line {}, column {}", self.message, line, column),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+pub trait Reader: Send + Sync {
+    // 格式名, 即server的from字段
+    fn name(&self) -> &'static str;
+    fn read<'a>(&self, input: &'a str) -> Result<Vec<Exp<'a>>, ReadError>;
+}
+
+lazy_static! {
+    static ref READERS: HashMap<&'static str, Box<dyn Reader>> = {
+        let readers: Vec<Box<dyn Reader>> = vec![
+            Box::new(ast_reader::NativeReader),
+            Box::new(json_reader::JsonReader),
+        ];
+        readers.into_iter().map(|r| (r.name(), r)).collect()
+    };
+}
+
+pub fn get_reader(name: &str) -> Option<&'static dyn Reader> {
+    READERS.get(name).map(|r| r.as_ref())
+}
+
+// 所有已注册的格式名, 按名字排序
+pub fn reader_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = READERS.keys().copied().collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_read_with() {
+    let expected = vec![Exp::EIdentifier("x".into())];
+    assert_eq!(read_with("native", r#"[EIdentifier "x"]"#).unwrap(), expected);
+    assert_eq!(read_with("json", r#"[{"tag":"EIdentifier","contents":"x"}]"#).unwrap(), expected);
+    assert!(read_with("mathml", "<math/>").is_err());
+
+    let err = read_with("native", "[EIdentifier \"x\",\n  EFoo]").unwrap_err();
+    assert_eq!(err.position, Some((2, 3)));
+}
+
+// 按格式名读取, 格式不存在时返回错误, 嵌套超过node::DEFAULT_MAX_DEPTH时返回TooDeep
+pub fn read_with<'a>(name: &str, input: &'a str) -> Result<Vec<Exp<'a>>, ReadError> {
+    read_with_limit(name, input, node::DEFAULT_MAX_DEPTH)
+}
+
+#[test]
+fn test_check_depth() {
+    assert!(check_depth(r#"[ESqrt (ESqrt (EIdentifier "x"))]"#, 3).is_ok());
+    // 引号内的括号不计
+    assert!(check_depth(r#"[EText TextNormal "(((("]"#, 1).is_ok());
+
+    let err = check_depth(r#"[ESqrt (ESqrt (EIdentifier "x"))]"#, 2).unwrap_err();
+    assert_eq!(err.kind, ReadErrorKind::TooDeep);
+    assert_eq!(err.position, Some((1, 15)));
+}
+
+// 检查括号(), [], {}的嵌套深度, 引号内的字符不计
+// 在递归读取前拒绝过深的输入, 避免栈溢出
+pub fn check_depth(input: &str, max_depth: usize) -> Result<(), ReadError> {
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => {
+                depth += 1;
+                if depth > max_depth {
+                    let msg = format!("nesting deeper than {}", max_depth);
+                    return Err(ReadError {
+                        kind: ReadErrorKind::TooDeep,
+                        ..ReadError::at_rest(msg, input, &input[i..])
+                    });
+                }
+            },
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_read_too_deep() {
+    let chain = |d: usize| "[EGrouped ".repeat(d) + "[EIdentifier \"x\"]" + &"]".repeat(d + 1);
+    let input = chain(500);
+    let exps = read_with("native", &input).unwrap();
+    assert_eq!(node::depth(&exps), 501);
+
+    let err = read_with("native", &chain(100_000)).unwrap_err();
+    assert_eq!(err.kind, ReadErrorKind::TooDeep);
+    assert_eq!(err.position, Some((1, 10 * node::DEFAULT_MAX_DEPTH + 1)));
+
+    // json与native使用同一个深度限制, 不受serde_json的128层限制
+    let json = |d: usize| "[{\"tag\":\"EGrouped\",\"contents\":".repeat(d) + "[]" + &"}]".repeat(d);
+    let input = json(300);
+    let exps = read_with("json", &input).unwrap();
+    assert_eq!(node::depth(&exps), 300);
+    let err = read_with("json", &json(100_000)).unwrap_err();
+    assert_eq!(err.kind, ReadErrorKind::TooDeep);
+    assert_eq!(err.position, Some((1, 30 * node::DEFAULT_MAX_DEPTH / 2 + 1)));
+}
+
+// 同read_with, 读取前先检查嵌套深度
+pub fn read_with_limit<'a>(name: &str, input: &'a str, max_depth: usize) -> Result<Vec<Exp<'a>>, ReadError> {
+    let reader = match get_reader(name) {
+        Some(r) => r,
+        None => return Err(ReadError::new(format!("unknown input format: {}, available: {}", name, reader_names().join(", ")))),
+    };
+    check_depth(input, max_depth)?;
+    reader.read(input)
+}
+
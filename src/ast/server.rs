@@ -1,70 +1,742 @@
 use axum::{
-    routing::post,
+    routing::{get, post},
     Router,
-    extract::Json,
+    extract::{rejection::{JsonRejection, QueryRejection, StringRejection}, DefaultBodyLimit, Json, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
 };
+use lazy_static::lazy_static;
 use std::net::SocketAddr;
-use std::ptr::eq;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
 
 use crate::ast;
+use crate::ast::cache::LruCache;
+use crate::ast::metrics;
+use crate::ast::writer::{WriterOutput, WriterWarning};
+use crate::config;
 
-pub async fn run_server(addr: String, port: u16) {
-    let app = Router::new().route("/convert", post(convert_handler));
-    println!("Listening on: {}:{}", addr, port);
-    let addr = format!("{}:{}", addr, port).parse::<SocketAddr>().unwrap();
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-}
+lazy_static! {
+    // 同时进行的转换数, 包括批量转换中的每一项
+    static ref CONVERT_PERMITS: Arc<Semaphore> = Arc::new(Semaphore::new(config::get_config().limits.max_concurrency));
 
-async fn convert_handler(Json(req): Json<ServerRequest>) -> Json<ServerResponse> {
-    let mut envs = std::collections::HashMap::new();
-    envs.insert("amsmath".to_string(), true);
-    envs.insert("amssymb".to_string(), true);
-    envs.insert("mathbb".to_string(), true);
-    Json(native_to_tex(req.text.as_str(), &envs, req.equation))
+    // 转换结果缓存, capacity为0时不缓存
+    static ref CONVERT_CACHE: Option<Mutex<LruCache<CacheKey, WriterOutput>>> = match config::get_config().cache.capacity {
+        0 => None,
+        capacity => Some(Mutex::new(LruCache::new(capacity))),
+    };
 }
 
-#[derive(serde::Deserialize)]
-struct ServerRequest {
+// 缓存的key: 输入, 格式和所有影响输出的选项
+#[derive(Hash, PartialEq, Eq, Clone)]
+struct CacheKey {
+    text: String,
     from: String,
     to: String,
-    text: String,
+    packages: Vec<String>,
     equation: bool,
+    inline_delimiters: (String, String),
+    display_delimiters: (String, String),
+    extra: Vec<(String, String)>,
 }
 
-#[derive(serde::Serialize)]
-struct ServerResponse {
-    output: String,
-    error: String,
-}
-fn native_to_tex(native: &str, envs: &std::collections::HashMap<String, bool>, equation: bool) -> ServerResponse{
-    let ast = ast::ast_reader::read_ast(native);
-    match ast {
-        Ok(ast) => {
-            let tex = match equation {
-                true => ast::tex_writer::write_tex_equation(ast),
-                false => ast::tex_writer::write_tex_with_md(ast, envs)
-            };
-            match tex {
-                Ok(tex) => {
-                    ServerResponse {
-                        output: tex,
-                        error: "".to_string(),
-                    }
-                }
-                Err(e) => {
-                    ServerResponse {
-                        output: "".to_string(),
-                        error: "write_tex: ".to_string() + e.to_string().as_str(),
+impl CacheKey {
+    fn new(text: &str, from: &str, to: &str, opts: &ast::writer::WriterOptions) -> CacheKey {
+        // 宏包的顺序是优先级, 不排序
+        let packages: Vec<String> = opts.envs.iter()
+            .filter(|(_, enabled)| **enabled)
+            .map(|(p, _)| p.clone())
+            .collect();
+        let mut extra: Vec<(String, String)> = opts.extra.iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        extra.sort();
+        CacheKey {
+            text: text.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            packages,
+            equation: opts.equation,
+            inline_delimiters: opts.inline_delimiters.clone(),
+            display_delimiters: opts.display_delimiters.clone(),
+            extra,
+        }
+    }
+}
+
+// 只缓存成功的结果
+fn convert_cached(text: &str, from: &str, to: &str, opts: &ast::writer::WriterOptions) -> Result<WriterOutput, ServerError> {
+    let cache = match CONVERT_CACHE.as_ref() {
+        Some(cache) => cache,
+        None => return convert(text, from, to, opts),
+    };
+    let key = CacheKey::new(text, from, to, opts);
+    let cached = cache.lock().unwrap().get(&key);
+    metrics::global().record_cache(cached.is_some());
+    if let Some(output) = cached {
+        return Ok(output);
+    }
+
+    let output = convert(text, from, to, opts)?;
+    let mut c = cache.lock().unwrap();
+    c.put(key, output.clone());
+    metrics::global().set_cache_entries(c.len());
+    Ok(output)
+}
+
+pub async fn run_server(addr: String, port: u16) -> std::io::Result<()> {
+    let app = Router::new()
+        .route("/convert", post(convert_handler))
+        .route("/convert/batch", post(convert_batch_handler))
+        .route("/formats", get(formats_handler))
+        .route("/symbols", get(symbols_handler))
+        .route("/openapi.json", get(openapi_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/metrics", get(metrics_handler))
+        .layer(DefaultBodyLimit::max(config::get_config().limits.max_body_bytes));
+    println!("Listening on: {}:{}", addr, port);
+    let addr = format!("{}:{}", addr, port).parse::<SocketAddr>()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+}
+
+// 收到Ctrl+C或SIGTERM后不再接收新连接, 等待进行中的请求完成后退出
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    println!("Shutting down");
+}
+
+// 在blocking线程池中转换, 受max_concurrency和timeout_ms限制
+async fn run_convert(req: ServerRequest) -> Result<WriterOutput, ServerError> {
+    let timeout = Duration::from_millis(config::get_config().limits.timeout_ms);
+    run_convert_with(req, CONVERT_PERMITS.clone(), timeout).await
+}
+
+// 等待并发数的时间不计入超时, 拿到permit后才开始计时, 否则排队的请求会没有运行就超时
+// 超时后立即返回503, 但blocking线程会继续运行到转换结束, 期间一直占用并发数
+async fn run_convert_with(req: ServerRequest, permits: Arc<Semaphore>, timeout: Duration) -> Result<WriterOutput, ServerError> {
+    let permit = permits.acquire_owned().await
+        .map_err(|e| ServerError::internal(e.to_string()))?;
+    let handle = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        convert_request(&req)
+    });
+    match tokio::time::timeout(timeout, handle).await {
+        Ok(Ok(res)) => res,
+        Ok(Err(e)) => {
+            metrics::global().record_error("internal");
+            Err(ServerError::internal(e.to_string()))
+        },
+        Err(_) => {
+            metrics::global().record_error("timeout");
+            let msg = format!("conversion timed out after {} ms", timeout.as_millis());
+            Err(ServerError::new(StatusCode::SERVICE_UNAVAILABLE, "internal", "timeout", msg))
+        }
+    }
+}
+
+// 可用的输入/输出格式, 宏包和方言
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub(crate) struct Formats {
+    readers: Vec<&'static str>,
+    writers: Vec<&'static str>,
+    packages: Vec<String>,
+    default_dialect: String,
+    dialects: std::collections::BTreeMap<String, config::DialectConfig>,
+    mappings: Vec<String>,
+}
+
+fn formats() -> Formats {
+    let conf = &config::get_config().convert;
+    Formats {
+        readers: ast::reader::reader_names(),
+        writers: ast::writer::writer_names(),
+        packages: ast::tex_unicode::package_names(),
+        default_dialect: conf.dialect.clone(),
+        dialects: conf.dialect_names().into_iter()
+            .filter_map(|name| conf.get_dialect(&name).map(|d| (name, d)))
+            .collect(),
+        mappings: conf.mapping_names(),
+    }
+}
+
+// 宏包列表需要加载符号表, 在blocking线程池中进行
+async fn formats_handler() -> Response {
+    match tokio::task::spawn_blocking(formats).await {
+        Ok(f) => Json(f).into_response(),
+        Err(e) => ServerError::internal(e.to_string()).into_response(),
+    }
+}
+
+// 符号查询, char, command, package三者选一:
+// char: 字符或U+2264, 返回packages(逗号分隔)下可以输出该字符的命令
+// command: 命令, 如: \leqslant, 返回对应的字符和类型
+// package: 宏包提供的符号
+#[derive(serde::Deserialize)]
+struct SymbolQuery {
+    #[serde(default)]
+    char: Option<String>,
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    package: Option<String>,
+    #[serde(default)]
+    packages: Option<String>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+#[schemars(rename = "Symbol")]
+pub(crate) struct SymbolInfo {
+    #[schemars(example = "example_code_point")]
+    code_point: String,
+    char: String,
+    command: String,
+    #[serde(rename = "type")]
+    #[schemars(with = "ast::node::TeXSymbolType")]
+    symbol_type: String,
+    #[schemars(description = "env column of the table, e.g. base, amssymb or \"mathabx -amssymb\"")]
+    package: String,
+    description: String,
+}
+
+fn example_code_point() -> &'static str {
+    "U+2264"
+}
+
+fn symbols(q: &SymbolQuery) -> Result<Vec<SymbolInfo>, ServerError> {
+    use ast::symbol_db::{format_code_point, parse_char_query, PackageOrder, SymbolDb};
+
+    let db = SymbolDb::global().map_err(ServerError::internal)?;
+    let found = match (&q.char, &q.command, &q.package) {
+        (Some(c), None, None) => {
+            let c = parse_char_query(c).ok_or_else(|| {
+                ServerError::request(StatusCode::BAD_REQUEST, format!("invalid char: {}, expect a single character or U+XXXX", c))
+            })?;
+            let packages: Vec<String> = q.packages.as_deref().unwrap_or("").split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect();
+            db.commands_for(c, &PackageOrder::new(&packages))
+        },
+        (None, Some(cmd), None) => db.by_command(cmd),
+        (None, None, Some(name)) => db.package(name),
+        _ => return Err(ServerError::request(StatusCode::BAD_REQUEST, "expect exactly one of char, command and package".to_string())),
+    };
+    Ok(found.into_iter().map(|s| SymbolInfo {
+        code_point: format_code_point(s.code_point),
+        char: s.code_point.to_string(),
+        command: s.command.clone(),
+        symbol_type: format!("{:?}", s.symbol_type),
+        package: s.package.clone(),
+        description: s.description.clone(),
+    }).collect())
+}
+
+// 第一次查询需要加载符号表, 在blocking线程池中进行
+async fn symbols_handler(query: Result<Query<SymbolQuery>, QueryRejection>) -> Response {
+    let q = match query {
+        Ok(Query(q)) => q,
+        Err(rejection) => return ServerError::request(rejection.status(), rejection.body_text()).into_response(),
+    };
+    match tokio::task::spawn_blocking(move || symbols(&q)).await {
+        Ok(Ok(found)) => Json(found).into_response(),
+        Ok(Err(e)) => e.into_response(),
+        Err(e) => ServerError::internal(e.to_string()).into_response(),
+    }
+}
+
+async fn openapi_handler() -> Json<serde_json::Value> {
+    Json(ast::openapi::document())
+}
+
+async fn healthz_handler() -> &'static str {
+    "ok"
+}
+
+// 符号表加载完成后才可以接收请求, 加载在blocking线程池中进行
+async fn readyz_handler() -> Response {
+    match tokio::task::spawn_blocking(ast::tex_unicode::tables_loaded).await {
+        Ok(true) => "ready".into_response(),
+        _ => (StatusCode::SERVICE_UNAVAILABLE, "symbol tables not loaded").into_response(),
+    }
+}
+
+async fn metrics_handler() -> Response {
+    let body = metrics::global().render();
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+// write_exp中的panic作为500返回
+async fn convert_handler(payload: Result<Json<ServerRequest>, JsonRejection>) -> Response {
+    let req = match payload {
+        Ok(Json(req)) => req,
+        Err(rejection) => {
+            metrics::global().record_error("request");
+            return ServerError::request(rejection.status(), rejection.body_text()).into_response();
+        }
+    };
+    match run_convert(req).await {
+        Ok(output) => Json(ServerResponse::from(output)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+// 批量转换: body为ServerRequest数组, 或NDJSON(每行一个ServerRequest, content-type为application/x-ndjson)
+// 每一项在blocking线程池中并行转换, 按输入顺序返回, 输入是NDJSON时也以NDJSON返回
+// 单项的错误放在该项的error中, 整体状态码为200; body不是数组时返回400
+// 每一项分别受并发数和超时限制, 项数超过max_batch_items时返回413
+// 一个批量请求同时等待或进行的转换不超过max_concurrency, 避免大的批量请求占满等待队列, 阻塞/convert
+async fn convert_batch_handler(headers: HeaderMap, body: Result<String, StringRejection>) -> Response {
+    let body = match body {
+        Ok(body) => body,
+        Err(rejection) => {
+            metrics::global().record_error("request");
+            return ServerError::request(rejection.status(), rejection.body_text()).into_response();
+        }
+    };
+    let ndjson = headers.get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("application/x-ndjson"))
+        .unwrap_or(false);
+
+    let items: Vec<Result<ServerRequest, String>> = if ndjson {
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| e.to_string()))
+            .collect()
+    } else {
+        match serde_json::from_str::<Vec<serde_json::Value>>(&body) {
+            Ok(values) => values.into_iter()
+                .map(|v| serde_json::from_value(v).map_err(|e| e.to_string()))
+                .collect(),
+            Err(e) => {
+                metrics::global().record_error("request");
+                return ServerError::request(StatusCode::BAD_REQUEST, format!("expected an array of requests: {}", e)).into_response();
+            }
+        }
+    };
+
+    let limits = &config::get_config().limits;
+    if items.len() > limits.max_batch_items {
+        metrics::global().record_error("request");
+        let msg = format!("batch has {} requests, at most {} allowed", items.len(), limits.max_batch_items);
+        return ServerError::new(StatusCode::PAYLOAD_TOO_LARGE, "request", "too_many_items", msg).into_response();
+    }
+
+    let batch_permits = Arc::new(Semaphore::new(limits.max_concurrency));
+    let handles: Vec<_> = items.into_iter()
+        .map(|item| {
+            let batch_permits = batch_permits.clone();
+            tokio::spawn(async move {
+                match item {
+                    Ok(req) => {
+                        let _permit = batch_permits.acquire_owned().await
+                            .map_err(|e| ServerError::internal(e.to_string()))?;
+                        run_convert(req).await
+                    },
+                    Err(e) => {
+                        metrics::global().record_error("request");
+                        Err(ServerError::request(StatusCode::BAD_REQUEST, e))
                     }
                 }
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        let res = match handle.await {
+            Ok(res) => res,
+            Err(e) => {
+                metrics::global().record_error("internal");
+                Err(ServerError::internal(e.to_string()))
             }
+        };
+        results.push(match res {
+            Ok(output) => ServerResponse::from(output),
+            Err(e) => ServerResponse::from(e),
+        });
+    }
+
+    if ndjson {
+        let lines: Vec<String> = results.iter()
+            .map(|r| serde_json::to_string(r).unwrap())
+            .collect();
+        ([(header::CONTENT_TYPE, "application/x-ndjson")], lines.join("\n") + "\n").into_response()
+    } else {
+        Json(results).into_response()
+    }
+}
+
+// 请求中没有指定的选项使用config.toml中dialect的配置
+fn convert_request(req: &ServerRequest) -> Result<WriterOutput, ServerError> {
+    let conf = &config::get_config().convert;
+    let dialect_name = req.dialect.as_deref().unwrap_or(conf.dialect.as_str());
+    let dialect = match conf.get_dialect(dialect_name) {
+        Some(d) => d,
+        None => {
+            metrics::global().record_error("request");
+            let msg = format!("unknown dialect: {}", dialect_name);
+            return Err(ServerError::request(StatusCode::BAD_REQUEST, msg));
         }
-        Err(e) => {
-            ServerResponse {
-                output: "".to_string(),
-                error: "read_ast: ".to_string() + e.to_string().as_str(),
-            }
+    };
+
+    let packages = req.packages.as_ref().unwrap_or(&dialect.packages);
+    let mut opts = ast::writer::WriterOptions {
+        envs: packages.iter().map(|p| (p.clone(), true)).collect(),
+        equation: req.equation,
+        inline_delimiters: dialect.inline,
+        display_delimiters: dialect.display,
+        ..Default::default()
+    };
+    if let Some(name) = &req.mapping {
+        if !name.is_empty() && !conf.mappings.contains_key(name) {
+            metrics::global().record_error("request");
+            let msg = format!("unknown mapping: {}", name);
+            return Err(ServerError::request(StatusCode::BAD_REQUEST, msg));
+        }
+        opts.extra.insert("mapping".to_string(), name.clone());
+    }
+    if let Some(unmapped) = &req.unmapped {
+        if let Err(msg) = config::Unmapped::parse(unmapped) {
+            metrics::global().record_error("request");
+            return Err(ServerError::request(StatusCode::BAD_REQUEST, msg));
+        }
+        opts.extra.insert("unmapped".to_string(), unmapped.clone());
+    }
+    if let Some(delimiters) = &req.delimiters {
+        match req.equation {
+            true => opts.display_delimiters = delimiters.clone(),
+            false => opts.inline_delimiters = delimiters.clone(),
         }
     }
-}
\ No newline at end of file
+
+    // 没有指定to时保持原来的markdown输出
+    let to = match req.to.as_str() {
+        "" => "markdown",
+        to => to,
+    };
+    let start = Instant::now();
+    let res = convert_cached(req.text.as_str(), req.from.as_str(), to, &opts);
+
+    // 未注册的格式名统一记为unknown
+    let from_label = if ast::reader::get_reader(&req.from).is_some() { req.from.as_str() } else { "unknown" };
+    let to_label = if ast::writer::get_writer(to).is_some() { to } else { "unknown" };
+    metrics::global().record_conversion(from_label, to_label, start.elapsed());
+    if let Err(e) = &res {
+        metrics::global().record_error(e.stage);
+    }
+    res
+}
+
+// packages: 可用的宏包, 如: ["amsmath", "amssymb"], 多个宏包提供同一个字符时靠前的优先
+// dialect: 目标方言, 对应config.toml中的[convert.dialects.<name>]
+// delimiters: 公式的界定符, 如: ["$", "$"], equation为true时替换独立公式的界定符
+// mapping: 用户自定义的映射表, 对应config.toml中的[convert.mappings], 空字符串表示不使用
+// unmapped: 符号表中没有的字符的处理方式: text, command, char, error, 默认为config.toml中[convert]的unmapped
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub(crate) struct ServerRequest {
+    from: String,
+    #[serde(default)]
+    #[schemars(description = "Defaults to markdown when empty")]
+    to: String,
+    text: String,
+    #[serde(default)]
+    equation: bool,
+    #[serde(default)]
+    #[schemars(description = "In priority order: when several packages provide a character, the first one wins. Defaults to the packages of the dialect")]
+    packages: Option<Vec<String>>,
+    #[serde(default)]
+    #[schemars(description = "A [convert.dialects] entry of config.toml")]
+    dialect: Option<String>,
+    #[serde(default)]
+    #[schemars(with = "Option<[String; 2]>", description = "Replaces the display delimiters when equation is true, otherwise the inline ones")]
+    delimiters: Option<(String, String)>,
+    #[serde(default)]
+    #[schemars(description = "A [convert.mappings] entry of config.toml, applied before the symbol table. Defaults to convert.mapping, an empty string disables it")]
+    mapping: Option<String>,
+    #[serde(default)]
+    #[schemars(with = "Option<config::Unmapped>", description = "Characters without a math command: \\text{}, a textcomp/tipa command, \\char\"XXXX, or a write_error. Defaults to convert.unmapped")]
+    unmapped: Option<String>,
+}
+
+// packages: TeX输出需要的宏包, 为空时省略
+// warnings: TeX输出中没有如实转换的节点, 为空时省略
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub(crate) struct ServerResponse {
+    output: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(description = "Packages the TeX output needs, e.g. amsmath. Omitted when empty")]
+    packages: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schemars(description = "Nodes the TeX output did not convert faithfully. Omitted when empty")]
+    warnings: Vec<WriterWarning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ServerError>,
+}
+
+impl From<WriterOutput> for ServerResponse {
+    fn from(output: WriterOutput) -> Self {
+        ServerResponse { output: output.text, packages: output.packages, warnings: output.warnings, error: None }
+    }
+}
+
+impl From<ServerError> for ServerResponse {
+    fn from(e: ServerError) -> Self {
+        ServerResponse { output: "".to_string(), packages: Vec::new(), warnings: Vec::new(), error: Some(e) }
+    }
+}
+
+// 结构化的错误:
+// stage: request(请求体), read(读取输入), write(写出), internal(内部错误)
+// code: invalid_request, unknown_format, parse_error, too_deep, write_error, internal_error, timeout
+// position: 读取错误的位置, 行列都从1开始
+#[derive(serde::Serialize, schemars::JsonSchema, Debug)]
+pub(crate) struct ServerError {
+    #[serde(skip)]
+    status: StatusCode,
+    stage: &'static str,
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position: Option<ErrorPosition>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema, Debug)]
+struct ErrorPosition {
+    #[schemars(range(min = 1))]
+    line: usize,
+    #[schemars(range(min = 1))]
+    column: usize,
+}
+
+impl ServerError {
+    fn new(status: StatusCode, stage: &'static str, code: &'static str, message: String) -> ServerError {
+        ServerError { status, stage, code, message, position: None }
+    }
+
+    fn request(status: StatusCode, message: String) -> ServerError {
+        ServerError::new(status, "request", "invalid_request", message)
+    }
+
+    fn internal(message: String) -> ServerError {
+        ServerError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", "internal_error", message)
+    }
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        let status = self.status;
+        let body = ServerResponse::from(self);
+        (status, Json(body)).into_response()
+    }
+}
+
+fn convert(text: &str, from: &str, to: &str, opts: &ast::writer::WriterOptions) -> Result<WriterOutput, ServerError> {
+    if ast::reader::get_reader(from).is_none() {
+        let msg = format!("unknown input format: {}, available: {}", from, ast::reader::reader_names().join(", "));
+        return Err(ServerError::new(StatusCode::BAD_REQUEST, "read", "unknown_format", msg));
+    }
+    if ast::writer::get_writer(to).is_none() {
+        let msg = format!("unknown output format: {}, available: {}", to, ast::writer::writer_names().join(", "));
+        return Err(ServerError::new(StatusCode::BAD_REQUEST, "write", "unknown_format", msg));
+    }
+
+    let max_depth = config::get_config().limits.max_depth;
+    let ast = ast::reader::read_with_limit(from, text, max_depth).map_err(|e| {
+        let code = match e.kind {
+            ast::reader::ReadErrorKind::Parse => "parse_error",
+            ast::reader::ReadErrorKind::TooDeep => "too_deep",
+        };
+        ServerError {
+            position: e.position.map(|(line, column)| ErrorPosition { line, column }),
+            ..ServerError::new(StatusCode::UNPROCESSABLE_ENTITY, "read", code, e.message)
+        }
+    })?;
+    ast::writer::write_output_with(to, ast, opts)
+        .map_err(|e| ServerError::new(StatusCode::UNPROCESSABLE_ENTITY, "write", "write_error", e))
+}
+
+#[test]
+fn test_convert() {
+    let opts = ast::writer::WriterOptions::default();
+    assert_eq!(convert("[EIdentifier \"x\"]", "native", "tex", &opts).unwrap().text, "x");
+    let output = convert("[EBoxed (EIdentifier \"x\")]", "native", "tex", &opts).unwrap();
+    assert_eq!(output.packages, vec!["amsmath"]);
+
+    let e = convert("x", "mathml", "tex", &opts).unwrap_err();
+    assert_eq!((e.status, e.stage, e.code), (StatusCode::BAD_REQUEST, "read", "unknown_format"));
+
+    let e = convert("[EIdentifier \"x\",\n EFoo]", "native", "tex", &opts).unwrap_err();
+    assert_eq!((e.status, e.stage, e.code), (StatusCode::UNPROCESSABLE_ENTITY, "read", "parse_error"));
+    let p = e.position.unwrap();
+    assert_eq!((p.line, p.column), (2, 2));
+}
+
+#[test]
+fn test_convert_request() {
+    let req: ServerRequest = serde_json::from_str(r#"{"from": "native", "text": "[EIdentifier \"x\"]"}"#).unwrap();
+    assert_eq!(convert_request(&req).unwrap().text, " \\( x \\) ");
+
+    let req: ServerRequest = serde_json::from_str(r#"{"from": "native", "to": "tex", "text": "[EIdentifier \"x\"]", "equation": true, "delimiters": ["$$", "$$"]}"#).unwrap();
+    assert_eq!(convert_request(&req).unwrap().text, "$$x$$");
+
+    let req: ServerRequest = serde_json::from_str(r#"{"from": "native", "text": "[]", "dialect": "troff"}"#).unwrap();
+    assert_eq!(convert_request(&req).unwrap_err().status, StatusCode::BAD_REQUEST);
+
+    let req: ServerRequest = serde_json::from_str(r#"{"from": "native", "text": "[]", "mapping": "no-such-mapping"}"#).unwrap();
+    assert_eq!(convert_request(&req).unwrap_err().status, StatusCode::BAD_REQUEST);
+
+    let req: ServerRequest = serde_json::from_str(r#"{"from": "native", "to": "tex", "text": "[ESymbol Ord \"\\8364\"]", "unmapped": "char"}"#).unwrap();
+    assert_eq!(convert_request(&req).unwrap().text, "\\char\"20AC");
+
+    let req: ServerRequest = serde_json::from_str(r#"{"from": "native", "text": "[]", "unmapped": "raw"}"#).unwrap();
+    assert_eq!(convert_request(&req).unwrap_err().status, StatusCode::BAD_REQUEST);
+}
+
+// 调用批量转换, 返回状态码, content-type和body
+#[cfg(test)]
+async fn call_batch(content_type: &str, body: &str) -> (StatusCode, String, String) {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+    let res = convert_batch_handler(headers, Ok(body.to_string())).await;
+    let status = res.status();
+    let content_type = res.headers().get(header::CONTENT_TYPE).unwrap().to_str().unwrap().to_string();
+    let bytes = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    (status, content_type, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_convert_batch() {
+    // 按输入顺序返回, 单项的错误放在该项的error中
+    let body = r#"[
+        {"from": "native", "to": "tex", "text": "[EIdentifier \"a\"]"},
+        {"from": "native", "to": "tex", "text": "[EFoo]"},
+        {"from": "mathml", "to": "tex", "text": "x"},
+        {"text": "no from"},
+        {"from": "native", "to": "tex", "text": "[EIdentifier \"b\"]"}
+    ]"#;
+    let (status, content_type, body) = call_batch("application/json", body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(content_type.starts_with("application/json"));
+    let items: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+    assert_eq!(items.len(), 5);
+    assert_eq!(items[0]["output"], "a");
+    assert!(items[0].get("error").is_none());
+    assert_eq!(items[1]["error"]["code"], "parse_error");
+    assert_eq!(items[2]["error"]["code"], "unknown_format");
+    assert_eq!(items[3]["error"]["code"], "invalid_request");
+    assert_eq!(items[4]["output"], "b");
+
+    let (status, _, _) = call_batch("application/json", r#"{"from": "native"}"#).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_convert_batch_too_many_items() {
+    // 超过max_batch_items时整体返回413, 不转换任何一项
+    let max = config::get_config().limits.max_batch_items;
+    let body = format!("[{}]", vec![r#"{"from": "native", "to": "tex", "text": "[]"}"#; max + 1].join(","));
+    let (status, _, body) = call_batch("application/json", &body).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    let res: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(res["error"]["code"], "too_many_items");
+}
+
+#[tokio::test]
+async fn test_convert_batch_ndjson() {
+    // 每行一个请求, 空行忽略, 以NDJSON返回
+    let body = "{\"from\": \"native\", \"to\": \"tex\", \"text\": \"[EIdentifier \\\"a\\\"]\"}\n\nnot json\n{\"from\": \"native\", \"to\": \"tex\", \"text\": \"[ENumber \\\"1\\\"]\"}\n";
+    let (status, content_type, body) = call_batch("application/x-ndjson", body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/x-ndjson");
+    let items: Vec<serde_json::Value> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0]["output"], "a");
+    assert_eq!(items[1]["error"]["code"], "invalid_request");
+    assert_eq!(items[2]["output"], "1");
+}
+
+#[tokio::test]
+async fn test_run_convert_timeout_starts_after_permit() {
+    let req: ServerRequest = serde_json::from_str(r#"{"from": "native", "to": "tex", "text": "[EIdentifier \"a\"]"}"#).unwrap();
+    // 等待permit的时间超过timeout, 拿到permit后仍然正常转换
+    let permits = Arc::new(Semaphore::new(1));
+    let held = permits.clone().acquire_owned().await.unwrap();
+    let task = tokio::spawn(run_convert_with(req, permits, Duration::from_millis(50)));
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    drop(held);
+    assert_eq!(task.await.unwrap().unwrap().text, "a");
+}
+
+// openapi.rs中的schema需要与这里的类型保持一致
+#[test]
+fn test_openapi_document() {
+    let doc = ast::openapi::document();
+    let schemas = &doc["components"]["schemas"];
+    let property_names = |name: &str| {
+        let mut names: Vec<String> = schemas[name]["properties"].as_object().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    };
+    let keys = |v: &serde_json::Value| {
+        let mut names: Vec<String> = v.as_object().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    };
+
+    let err = ServerError {
+        position: Some(ErrorPosition { line: 1, column: 1 }),
+        ..ServerError::internal("x".to_string())
+    };
+    let warning = WriterWarning { path: "0:EStyled".to_string(), kind: "style", lost: "TextBoldFraktur".to_string(), emitted: "\\mathfrak".to_string() };
+    let resp = ServerResponse { packages: vec!["amsmath".to_string()], warnings: vec![warning], ..ServerResponse::from(err) };
+    let resp = serde_json::to_value(resp).unwrap();
+    assert_eq!(keys(&resp), property_names("ServerResponse"));
+    assert_eq!(keys(&resp["error"]), property_names("ServerError"));
+    assert_eq!(keys(&resp["warnings"][0]), property_names("Warning"));
+    // 为空时省略的字段不是必需的
+    assert_eq!(schemas["ServerResponse"]["required"], serde_json::json!(["output"]));
+
+    let formats = serde_json::to_value(formats()).unwrap();
+    assert_eq!(keys(&formats), property_names("Formats"));
+
+    let symbol = SymbolInfo {
+        code_point: "U+2264".to_string(), char: "\u{2264}".to_string(), command: "\\leq".to_string(),
+        symbol_type: "Rel".to_string(), package: "base".to_string(), description: "".to_string(),
+    };
+    assert_eq!(keys(&serde_json::to_value(symbol).unwrap()), property_names("Symbol"));
+    let params: Vec<&str> = doc["paths"]["/symbols"]["get"]["parameters"].as_array().unwrap().iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(params, vec!["char", "command", "package", "packages"]);
+
+    // 每个属性都能被ServerRequest接受
+    let req = r#"{"from": "native", "to": "tex", "text": "", "equation": true, "packages": [], "dialect": "latex", "delimiters": ["$", "$"], "mapping": "", "unmapped": "text"}"#;
+    assert_eq!(keys(&serde_json::from_str(req).unwrap()), property_names("ServerRequest"));
+    assert!(serde_json::from_str::<ServerRequest>(req).is_ok());
+    let from = schemas["ServerRequest"]["properties"]["from"]["enum"].as_array().unwrap();
+    assert!(from.contains(&serde_json::json!("native")));
+}
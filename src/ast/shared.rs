@@ -3,7 +3,11 @@ use ahash::AHasher;
 use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
 use crate::ast::node::{Alignment, Exp, InEDelimited, Rational, TeXSymbolType, TextType};
+use crate::ast::mapping::Mapping;
+use crate::config::{CombiningConfig, Unmapped};
 use crate::ast::tex_unicode::get_math_tex_many;
+use crate::ast::symbol_db::PackageOrder;
+use crate::ast::writer::{Envs, Packages};
 
 
 // null exp:
@@ -25,7 +29,7 @@ pub fn is_null_exp(e: &Exp) -> bool{
 }
 #[test]
 fn test_get_diacriticals(){
-    let case = "\\8254";
+    let case = "\u{203E}";
     let res = get_diacriticals(case);
     println!("case: {:?}, res: {:?}", case, res);
     assert_eq!(res, Some("\\bar".to_string()));
@@ -37,27 +41,9 @@ fn test_get_diacriticals(){
 }
 
 // 转换uncode码点为对应的命令:
-// ‾ -> \bar, ‾ = \u{203E} = \8254
+// ‾ -> \bar, ‾ = \u{203E}
 pub fn get_diacriticals(s: &str) -> Option<String>{
-    return match s.len() {
-        1 => {
-            // 如果是一个字符, 则直接查表
-            let key = DIACRITICALS_TABLE.get(s)?;
-            Some(key.to_string())
-        },
-        _ => {
-            // 如果是多个字符, 则先转换为unicode码点, 再查表
-            match parse_as_unicode_char(s) {
-                Some(c) => {
-                    let key = DIACRITICALS_TABLE.get(c.to_string().as_str())?;
-                    Some(key.to_string())
-                },
-                None => {
-                    return None;
-                }
-            }
-        }
-    }
+    DIACRITICALS_TABLE.get(s).map(|cmd| cmd.to_string())
 }
 
 pub fn is_below(s: &str) -> bool {
@@ -148,7 +134,7 @@ fn test_parse_unicode_escape(){
     println!("{:?}", parse_as_unicode_char("\\65024"));
 }
 
-// 转换为unicode对应的字符:
+// 转换符号表中以\+十进制码点存储的字符:
 // "\\8481" -> "℡"
 pub fn parse_as_unicode_char(s: &str) -> Option<char> {
     let code_point = u32::from_str_radix(s.trim_start_matches('\\'), 10).ok()?;
@@ -324,9 +310,9 @@ pub fn is_all_right(exp_list: &Vec<InEDelimited>) -> bool{
 
 // 把字符串的每一个字符转换为unicode escape
 // 需要同时处理转义字符和utf8码点\d{4}
-pub fn escape_text_as_tex(s: &str, envs: &HashMap<String, bool>) -> String{
-    let (res, _) = get_math_tex_many(s, envs);
-    return res
+pub fn escape_text_as_tex(s: &str, packages: &PackageOrder, mapping: &Mapping, combining: &CombiningConfig, unmapped: Unmapped, used: &mut Packages) -> Result<String, String>{
+    let (res, _) = get_math_tex_many(s, packages, mapping, combining, unmapped, used)?;
+    Ok(res)
 }
 
 // check if all exp is standard height:
@@ -357,23 +343,142 @@ pub fn is_all_standard_height(exp: &Vec<InEDelimited>) -> bool{
     return true;
 }
 
-// EScale
-pub fn get_scaler_cmd(rational: &Rational) -> Option<String>{
+#[test]
+fn test_get_scaler_cmd(){
+    let r = |n: i32, d: i32| Rational { numerator: n, denominator: d };
+    assert_eq!(get_scaler_cmd(&r(6, 5), &TeXSymbolType::Open), Some("\\bigl".to_string()));
+    assert_eq!(get_scaler_cmd(&r(9, 5), &TeXSymbolType::Close), Some("\\Bigr".to_string()));
+    assert_eq!(get_scaler_cmd(&r(2, 1), &TeXSymbolType::Fence), Some("\\biggm".to_string()));
+    assert_eq!(get_scaler_cmd(&r(3, 1), &TeXSymbolType::Ord), Some("\\Bigg".to_string()));
+    assert_eq!(get_scaler_cmd(&r(4, 1), &TeXSymbolType::Open), None);
+    assert_eq!(get_scale_factor(&r(3, 2)), "1.5");
+    assert_eq!(get_scale_factor(&r(10, 3)), "3.333");
+}
+
+// EScale: 能容纳倍数的最小的\big..\Bigg, 超过\Bigg时返回None
+// 按符号的类型加后缀: Open -> \bigl, Close -> \bigr, Rel/Fence/Bin -> \bigm, 其他 -> \big
+pub fn get_scaler_cmd(rational: &Rational, symbol_type: &TeXSymbolType) -> Option<String>{
     let need_width = rational.numerator as f64 / rational.denominator as f64;
     // 6/5 -> \big
     // 9/5 -> \Big
     // 12/5 -> \bigg
     // 15/5 -> \Bigg
-    if need_width <= 1.2 {
-        return Some("\\big".to_string());
+    let cmd = if need_width <= 1.2 {
+        "\\big"
     }else if need_width <= 1.8 {
-        return Some("\\Big".to_string());
+        "\\Big"
     }else if need_width <= 2.4 {
-        return Some("\\bigg".to_string());
+        "\\bigg"
     }else if need_width <= 3.0 {
-        return Some("\\Bigg".to_string());
+        "\\Bigg"
+    }else {
+        return None;
+    };
+    let suffix = match symbol_type {
+        TeXSymbolType::Open => "l",
+        TeXSymbolType::Close => "r",
+        TeXSymbolType::Rel | TeXSymbolType::Fence | TeXSymbolType::Bin => "m",
+        _ => "",
+    };
+    Some(format!("{}{}", cmd, suffix))
+}
+
+// \scalebox的倍数: 能写成有限小数时精确输出, 否则四舍五入到0.001, 如: 3/2 -> 1.5
+pub fn get_scale_factor(rational: &Rational) -> String{
+    match decimal(rational.numerator as i64, rational.denominator as i64) {
+        Some(res) => res.trim_end_matches('.').to_string(),
+        None => format!("{}", (rational.numerator as f64 / rational.denominator as f64 * 1000.0).round() / 1000.0),
     }
-    return None;
+}
+
+#[test]
+fn test_get_space_command(){
+    let space = |n: i32, d: i32, amsmath: bool| get_space_command(amsmath, &Rational { numerator: n, denominator: d });
+    assert_eq!(space(1, 6, true), Some(("\\,".to_string(), true)));
+    assert_eq!(space(2, 9, true), Some(("\\:".to_string(), true)));
+    assert_eq!(space(-2, 9, true), Some(("\\negmedspace".to_string(), true)));
+    assert_eq!(space(-2, 9, false), Some(("\\mskip -4mu\\relax".to_string(), true)));
+    assert_eq!(space(5, -18, true), Some(("\\negthickspace".to_string(), true)));
+    assert_eq!(space(1, 2, false), Some(("\\enspace".to_string(), true)));
+    assert_eq!(space(3, 1, false), Some(("\\hspace{3em}".to_string(), true)));
+    assert_eq!(space(7, 18, true), Some(("\\mspace{7mu}".to_string(), true)));
+    assert_eq!(space(7, 18, false), Some(("\\mskip 7mu\\relax".to_string(), true)));
+    // 不是整数mu时不取整
+    assert_eq!(space(1, 40, true), Some(("\\hspace{0.025em}".to_string(), true)));
+    assert_eq!(space(-1, 8, true), Some(("\\hspace{-0.125em}".to_string(), true)));
+    assert_eq!(space(1, 7, true), Some(("\\mspace{2.571mu}".to_string(), false)));
+    assert_eq!(space(0, 1, true), Some(("".to_string(), true)));
+    assert_eq!(space(1, 0, true), None);
+}
+
+// ESpace的宽度(单位em)对应的空格命令, 1em = 18mu, 按有理数精确计算, 不取整
+// return: (命令, 是否精确), 宽度为0时命令为空, 分母为0时返回None
+// 整数mu: 有名字的空格, 整数em用\hspace, 其他用\mspace(没有amsmath时用\mskip Nmu\relax, \relax结束\mskip的参数)
+// 不是整数mu: 能写成有限小数时用\hspace{Xem}, 否则四舍五入到0.001mu, 不精确
+pub fn get_space_command(amsmath: bool, width: &Rational) -> Option<(String, bool)>{
+    let (mut num, mut den) = (width.numerator as i64, width.denominator as i64);
+    if den == 0 {
+        return None;
+    }
+    if den < 0 {
+        (num, den) = (-num, -den);
+    }
+    let mu = |n: String| match amsmath {
+        true => format!("\\mspace{{{}mu}}", n),
+        false => format!("\\mskip {}mu\\relax", n),
+    };
+
+    if (num * 18) % den == 0 {
+        let cmd = match (num * 18 / den, amsmath) {
+            (0, _) => "".to_string(),
+            (-3, _) => "\\!".to_string(),
+            (-4, true) => "\\negmedspace".to_string(),
+            (-5, true) => "\\negthickspace".to_string(),
+            (3, _) => "\\,".to_string(),
+            (4, _) => "\\:".to_string(),
+            (5, _) => "\\;".to_string(),
+            (9, _) => "\\enspace".to_string(),
+            (18, _) => "\\quad".to_string(),
+            (36, _) => "\\qquad".to_string(),
+            (n, _) if n % 18 == 0 => format!("\\hspace{{{}em}}", n / 18),
+            (n, _) => mu(n.to_string()),
+        };
+        return Some((cmd, true));
+    }
+    if let Some(em) = decimal(num, den) {
+        return Some((format!("\\hspace{{{}em}}", em), true));
+    }
+    let n = (num * 18 * 1000) as f64 / den as f64;
+    Some((mu(format!("{}", n.round() / 1000.0)), false))
+}
+
+// 分母只有因子2和5时, 有理数写成有限小数: 1/40 -> 0.025, 分母需要为正数
+fn decimal(num: i64, den: i64) -> Option<String>{
+    if den <= 0 {
+        return None;
+    }
+    let mut rest = den;
+    for p in [2, 5] {
+        while rest % p == 0 {
+            rest /= p;
+        }
+    }
+    if rest != 1 {
+        return None;
+    }
+    let mut res = format!("{}{}.", if num < 0 { "-" } else { "" }, num.abs() / den);
+    let mut r = num.abs() % den;
+    while r != 0 {
+        r *= 10;
+        res.push_str(&(r / den).to_string());
+        r %= den;
+    }
+    Some(res)
+}
+
+// 可以读取但无法在LaTeX中作为重音输出的命令
+pub fn is_unavailable_diacritical(cmd: &str) -> bool{
+    cmd == "\\overbracket" || cmd == "\\underbracket"
 }
 
 pub fn get_diacritical_cmd(pos: &Position, s: &str) -> Option<String>{
@@ -381,7 +486,7 @@ pub fn get_diacritical_cmd(pos: &Position, s: &str) -> Option<String>{
 
     match cmd {
         Some(cmd) => {
-            if cmd == "\\overbracket" || cmd == "\\underbracket" {
+            if is_unavailable_diacritical(&cmd) {
                 // -- We want to parse these but we can't represent them in LaTeX
                 // unavailable :: [T.Text]
                 // unavailable = ["\\overbracket", "\\underbracket"]
@@ -407,7 +512,7 @@ pub fn get_diacritical_cmd(pos: &Position, s: &str) -> Option<String>{
     return None;
 }
 #[warn(unused_variables)]
-pub fn get_style_latex_cmd(style: &TextType, _envs: &HashMap<String, bool>) -> String{
+pub fn get_style_latex_cmd(style: &TextType, _envs: &Envs) -> String{
     // TODO: 处理环境, 有些环境可能不支持某些style, 如mathbfit
     // 现在仅仅将它转化为标准的LaTeX命令
     match style{
@@ -432,6 +537,47 @@ pub fn get_style_latex_cmd(style: &TextType, _envs: &HashMap<String, bool>) -> S
     }
 }
 
+#[test]
+fn test_command_package(){
+    assert_eq!(command_package("\\boxed"), Some("amsmath"));
+    assert_eq!(command_package("\\mathbb"), Some("amssymb"));
+    assert_eq!(command_package("\\mathbfit"), Some("unicode-math"));
+    assert_eq!(command_package("\\overbracket"), Some("mathtools"));
+    assert_eq!(command_package("\\alpha"), None);
+}
+
+// 写出时直接输出(不经过符号表)的命令需要的宏包, 符号表中的命令按env列记录
+pub fn command_package(cmd: &str) -> Option<&'static str>{
+    match cmd {
+        "\\text" | "\\boxed" | "\\binom" | "\\genfrac" | "\\overset" | "\\underset"
+        | "\\xrightarrow" | "\\xleftarrow" | "\\operatorname" | "\\mspace"
+        | "\\negmedspace" | "\\negthickspace" | "\\dddot" | "\\ddddot" => Some("amsmath"),
+        "\\mathbb" | "\\mathfrak" => Some("amssymb"),
+        "\\mathbfit" | "\\mathbfsf" | "\\mathbfsfit" | "\\mathbfsfup" | "\\mathbffrak" | "\\mathbfscr" => Some("unicode-math"),
+        "\\overbracket" | "\\underbracket" => Some("mathtools"),
+        "\\scalebox" => Some("graphicx"),
+        _ => XARROWS.iter().find(|(_, xarrow, _)| *xarrow == cmd).map(|(_, _, package)| *package),
+    }
+}
+
+// 环境需要的宏包, 如: pmatrix, cases, aligned需要amsmath
+pub fn environment_package(name: &str) -> Option<&'static str>{
+    match name {
+        "matrix" | "pmatrix" | "bmatrix" | "Bmatrix" | "vmatrix" | "Vmatrix" | "cases" | "aligned" => Some("amsmath"),
+        _ => None,
+    }
+}
+
+// get_style_latex_cmd中没有对应命令, 退化为相近样式的style, 如: TextBoldFraktur -> \mathfrak
+pub fn is_degraded_style(style: &TextType) -> bool{
+    matches!(style, TextType::TextSansSerifBold | TextType::TextSansSerifItalic | TextType::TextBoldFraktur | TextType::TextBoldScript)
+}
+
+// get_text_cmd中没有对应命令, 退化为\text的style, 如: TextDoubleStruck
+pub fn is_degraded_text(t: &TextType) -> bool{
+    matches!(t, TextType::TextScript | TextType::TextFraktur | TextType::TextDoubleStruck | TextType::TextBoldFraktur | TextType::TextBoldScript)
+}
+
 // 获取\text的cmd, 有可能有多个cmd
 // 第二个返回值是cmd的个数, 添加{}的个数
 pub fn get_text_cmd(t: &TextType) -> (String, u8){
@@ -449,18 +595,61 @@ pub fn get_text_cmd(t: &TextType) -> (String, u8){
     }
 }
 
-// xarrow = ESymbol Op "\\8594" -> "\\xrightarrow"
-// xarrow = ESymbol Op "\\8592" -> "\\xleftarrow"
-pub fn get_xarrow(e: &Exp) -> Option<String>{
-    return match e {
+// 可伸缩的箭头: (字符, 命令, 宏包), 长箭头和短箭头使用同一个命令
+// amsmath: \xrightarrow, \xleftarrow; mathtools: 其他箭头和鱼叉; extarrows: \xlongequal; extpfeil: 双头箭头
+const XARROWS: [(char, &str, &str); 24] = [
+    ('\u{2192}', "\\xrightarrow", "amsmath"),
+    ('\u{27F6}', "\\xrightarrow", "amsmath"),
+    ('\u{2190}', "\\xleftarrow", "amsmath"),
+    ('\u{27F5}', "\\xleftarrow", "amsmath"),
+    ('\u{2194}', "\\xleftrightarrow", "mathtools"),
+    ('\u{27F7}', "\\xleftrightarrow", "mathtools"),
+    ('\u{21D2}', "\\xRightarrow", "mathtools"),
+    ('\u{27F9}', "\\xRightarrow", "mathtools"),
+    ('\u{21D0}', "\\xLeftarrow", "mathtools"),
+    ('\u{27F8}', "\\xLeftarrow", "mathtools"),
+    ('\u{21D4}', "\\xLeftrightarrow", "mathtools"),
+    ('\u{27FA}', "\\xLeftrightarrow", "mathtools"),
+    ('\u{21AA}', "\\xhookrightarrow", "mathtools"),
+    ('\u{21A9}', "\\xhookleftarrow", "mathtools"),
+    ('\u{21A6}', "\\xmapsto", "mathtools"),
+    ('\u{27FC}', "\\xmapsto", "mathtools"),
+    ('\u{21C0}', "\\xrightharpoonup", "mathtools"),
+    ('\u{21C1}', "\\xrightharpoondown", "mathtools"),
+    ('\u{21BC}', "\\xleftharpoonup", "mathtools"),
+    ('\u{21BD}', "\\xleftharpoondown", "mathtools"),
+    ('\u{21CC}', "\\xrightleftharpoons", "mathtools"),
+    ('\u{21CB}', "\\xleftrightharpoons", "mathtools"),
+    ('=', "\\xlongequal", "extarrows"),
+    ('\u{21A0}', "\\xtwoheadrightarrow", "extpfeil"),
+];
+
+#[test]
+fn test_get_xarrow(){
+    let arrow = |t: TeXSymbolType, s: &str| get_xarrow(&Exp::ESymbol(t, s.into()));
+    assert_eq!(arrow(TeXSymbolType::Op, "\u{2192}"), Some(("\\xrightarrow", "amsmath")));
+    assert_eq!(arrow(TeXSymbolType::Op, "\u{27F9}"), Some(("\\xRightarrow", "mathtools")));
+    assert_eq!(arrow(TeXSymbolType::Op, "="), Some(("\\xlongequal", "extarrows")));
+    assert_eq!(arrow(TeXSymbolType::Ord, "\u{2192}"), None);
+    assert_eq!(arrow(TeXSymbolType::Op, "\u{2192}\u{2192}"), None);
+    // Rel是固定大小的符号, 如\overset{def}{=}, 不改写为可伸缩的箭头
+    assert_eq!(arrow(TeXSymbolType::Rel, "="), None);
+    assert_eq!(arrow(TeXSymbolType::Rel, "\u{2192}"), None);
+    assert_eq!(command_package("\\xmapsto"), Some("mathtools"));
+}
+
+// xarrow = ESymbol Op "\u{2192}" -> ("\\xrightarrow", "amsmath"), 见XARROWS
+// reader用Op表示可伸缩的箭头, Rel是固定大小的符号(如\overset{def}{=}), 不改写
+// return: (命令, 需要的宏包)
+pub fn get_xarrow(e: &Exp) -> Option<(&'static str, &'static str)>{
+    match e {
         Exp::ESymbol(TeXSymbolType::Op, s) => {
-            return if s == "\\8594" {
-                Some("\\xrightarrow".to_string())
-            } else if s == "\\8592" {
-                Some("\\xleftarrow".to_string())
-            } else {
-                None
+            let mut chars = s.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
             }
+            XARROWS.iter().find(|(ch, _, _)| *ch == c).map(|(_, cmd, package)| (*cmd, *package))
         },
         _ => None,
     }
@@ -533,3 +722,25 @@ pub enum Position{
     Under,
     Over,
 }
+
+pub fn pretty_print_hex(output: String) -> String{
+    // 第一行显示hex, 第二行显示字符:
+    // 40 41 42
+    // @  A  B
+
+    // 把0D 0A替换成0A
+
+    let output = output.replace("\r\n", "\n");
+    let mut hex = String::new();
+    let mut cs = String::new();
+    for c in output.chars() {
+        hex.push_str(&format!("{:02x} ", c as u8));
+        match c {
+            '\n' => cs.push_str("\\n "),
+            '\t' => cs.push_str("\\t "),
+            '\r' => cs.push_str("\\r "),
+            _ => cs.push_str(&format!("{}  ", c)),
+        }
+    }
+    format!("{}\n{}", hex, cs)
+}
@@ -0,0 +1,503 @@
+// Exp -> 朗读文本, 用于屏幕阅读器的无障碍描述:
+// \frac{x+1}{2} -> the fraction with numerator x plus 1 and denominator 2
+// \sum_{i=1}^{n} a_i -> the sum from i equals 1 to n of a sub i
+// 所有的措辞都来自PhraseTable, 替换PhraseTable即可输出其他语言
+use std::collections::HashMap;
+use super::node::{self, Exp, FractionType, InEDelimited, TeXSymbolType, TextType};
+use super::shared;
+use super::writer::{Writer, WriterOptions};
+
+// 措辞表:
+// phrases: 句式模板, {0} {1} {2}为参数占位符, 如: "frac" -> "the fraction with numerator {0} and denominator {1}"
+// words: 符号和运算符的读法, 如: "+" -> "plus", "sin" -> "sine"
+pub struct PhraseTable {
+    phrases: HashMap<String, String>,
+    words: HashMap<String, String>,
+}
+
+impl PhraseTable {
+    pub fn english() -> PhraseTable {
+        let mut t = PhraseTable {
+            phrases: HashMap::new(),
+            words: HashMap::new(),
+        };
+        for (k, v) in ENGLISH_PHRASES {
+            t.set_phrase(k, v);
+        }
+        for (k, v) in ENGLISH_WORDS {
+            t.set_word(k, v);
+        }
+        t
+    }
+
+    pub fn set_phrase(&mut self, key: &str, template: &str) {
+        self.phrases.insert(key.to_string(), template.to_string());
+    }
+
+    pub fn set_word(&mut self, symbol: &str, word: &str) {
+        self.words.insert(symbol.to_string(), word.to_string());
+    }
+
+    // 从csv文件加载措辞, 覆盖或扩展已有的条目, 每行: "phrase"|"word", key, value
+    pub fn extend_from_csv(&mut self, path: &str) -> Result<(), String> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path(path)
+            .map_err(|e| format!("extend_from_csv: {}", e))?;
+        for result in reader.records() {
+            let record = result.map_err(|e| format!("extend_from_csv: {}", e))?;
+            let (kind, key, value) = match (record.get(0), record.get(1), record.get(2)) {
+                (Some(kind), Some(key), Some(value)) => (kind, key, value),
+                _ => return Err(format!("extend_from_csv: invalid record {:?}", record)),
+            };
+            match kind {
+                "phrase" => self.set_phrase(key, value),
+                "word" => self.set_word(key, value),
+                _ => return Err(format!("extend_from_csv: unknown kind {}", kind)),
+            }
+        }
+        Ok(())
+    }
+
+    // 用参数填充句式模板
+    fn phrase(&self, key: &str, args: &[&str]) -> String {
+        let template = match self.phrases.get(key) {
+            Some(template) => template,
+            None => {
+                // 没有模板时直接拼接参数
+                return args.join(" ");
+            }
+        };
+        // 从左到右扫描一次, 参数中的{1}等不会再被替换
+        let mut res = String::new();
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find('{') {
+            res.push_str(&rest[..start]);
+            let placeholder = rest[start + 1..].find('}')
+                .and_then(|end| rest[start + 1..start + 1 + end].parse::<usize>().ok().map(|i| (i, end)))
+                .and_then(|(i, end)| args.get(i).map(|arg| (arg, end)));
+            match placeholder {
+                Some((arg, end)) => {
+                    res.push_str(arg);
+                    rest = &rest[start + end + 2..];
+                },
+                None => {
+                    res.push('{');
+                    rest = &rest[start + 1..];
+                },
+            }
+        }
+        res.push_str(rest);
+        res
+    }
+
+    fn word(&self, symbol: &str) -> Option<&str> {
+        self.words.get(symbol).map(|s| s.as_str())
+    }
+}
+
+const ENGLISH_PHRASES: &[(&str, &str)] = &[
+    ("frac", "the fraction with numerator {0} and denominator {1}"),
+    ("binom", "the binomial coefficient {0} choose {1}"),
+    ("sqrt", "the square root of {0}"),
+    ("root", "the root of index {0} of {1}"),
+    ("sub", "{0} sub {1}"),
+    ("super", "{0} to the power {1}"),
+    ("squared", "{0} squared"),
+    ("cubed", "{0} cubed"),
+    ("prime", "{0} prime"),
+    ("subsup", "{0} sub {1} to the power {2}"),
+    ("limits_under", "the {0} over {1} of"),
+    ("limits_over", "the {0} up to {1} of"),
+    ("limits_underover", "the {0} from {1} to {2} of"),
+    ("accent", "{0} {1}"),
+    ("over", "{0} with {1} above"),
+    ("under", "{0} with {1} below"),
+    ("underover", "{0} with {1} below and {2} above"),
+    ("abs", "the absolute value of {0}"),
+    ("norm", "the norm of {0}"),
+    ("delimited", "{0} {1} {2}"),
+    ("boxed", "boxed {0}"),
+    ("array", "the array with {0} rows, {1}"),
+    ("row", "row {0}: {1}"),
+    ("cell_sep", ", "),
+    ("row_sep", "; "),
+    ("style", "{0} {1}"),
+    ("text", "{0}"),
+];
+
+const ENGLISH_WORDS: &[(&str, &str)] = &[
+    // 运算符和关系符
+    ("+", "plus"), ("-", "minus"), ("\u{2212}", "minus"), ("\u{B1}", "plus or minus"),
+    ("\u{2213}", "minus or plus"), ("\u{D7}", "times"), ("\u{22C5}", "times"), ("\u{B7}", "times"),
+    ("*", "times"), ("\u{2217}", "times"), ("/", "divided by"), ("\u{F7}", "divided by"),
+    ("=", "equals"), ("\u{2260}", "is not equal to"), ("<", "is less than"), (">", "is greater than"),
+    ("\u{2264}", "is less than or equal to"), ("\u{2265}", "is greater than or equal to"),
+    ("\u{2A7D}", "is less than or equal to"), ("\u{2A7E}", "is greater than or equal to"),
+    ("\u{2248}", "is approximately equal to"), ("\u{2261}", "is identical to"),
+    ("\u{223C}", "is similar to"), ("\u{2245}", "is congruent to"), ("\u{221D}", "is proportional to"),
+    ("\u{2208}", "is an element of"), ("\u{2209}", "is not an element of"),
+    ("\u{2282}", "is a subset of"), ("\u{2286}", "is a subset of or equal to"),
+    ("\u{222A}", "union"), ("\u{2229}", "intersection"), ("\u{2192}", "approaches"),
+    ("\u{21D2}", "implies"), ("\u{21D4}", "if and only if"), ("\u{2194}", "left right arrow"),
+    ("\u{2190}", "left arrow"), ("\u{21A6}", "maps to"), ("\u{2200}", "for all"), ("\u{2203}", "there exists"),
+    ("\u{2227}", "and"), ("\u{2228}", "or"), ("\u{AC}", "not"), ("!", "factorial"),
+    (",", "comma"), (";", "semicolon"), (":", "colon"), (".", "point"),
+    // 大型运算符
+    ("\u{2211}", "sum"), ("\u{220F}", "product"), ("\u{2210}", "coproduct"), ("\u{222B}", "integral"),
+    ("\u{222C}", "double integral"), ("\u{222D}", "triple integral"), ("\u{222E}", "contour integral"),
+    ("\u{22C3}", "union"), ("\u{22C2}", "intersection"),
+    // 其他符号
+    ("\u{221E}", "infinity"), ("\u{2202}", "partial"), ("\u{2207}", "nabla"), ("\u{2205}", "the empty set"),
+    ("\u{2032}", "prime"), ("\u{2033}", "double prime"), ("\u{2026}", "dot dot dot"), ("\u{22EF}", "dot dot dot"),
+    ("\u{2220}", "angle"), ("\u{B0}", "degrees"), ("%", "percent"),
+    // 界定符
+    ("(", "open paren"), (")", "close paren"), ("[", "open bracket"), ("]", "close bracket"),
+    ("{", "open brace"), ("}", "close brace"), ("\u{27E8}", "open angle"), ("\u{27E9}", "close angle"),
+    ("|", "vertical bar"), ("\u{2223}", "vertical bar"), ("\u{2016}", "double vertical bar"), ("\u{2225}", "double vertical bar"),
+    ("\u{230A}", "left floor"), ("\u{230B}", "right floor"), ("\u{2308}", "left ceiling"), ("\u{2309}", "right ceiling"),
+    // 重音
+    ("^", "hat"), ("\u{2C6}", "hat"), ("\u{302}", "hat"), ("~", "tilde"), ("\u{2DC}", "tilde"), ("\u{303}", "tilde"),
+    ("\u{AF}", "bar"), ("\u{203E}", "bar"), ("\u{304}", "bar"), ("\u{305}", "bar"), ("\u{2D9}", "dot"),
+    ("\u{307}", "dot"), ("\u{A8}", "double dot"), ("\u{308}", "double dot"), ("\u{20D7}", "vector"),
+    ("\u{23DE}", "overbrace"), ("\u{23DF}", "underbrace"), ("_", "underline"), ("\u{332}", "underline"),
+    // 希腊字母
+    ("\u{3B1}", "alpha"), ("\u{3B2}", "beta"), ("\u{3B3}", "gamma"), ("\u{3B4}", "delta"), ("\u{3B5}", "epsilon"),
+    ("\u{3F5}", "epsilon"), ("\u{3B6}", "zeta"), ("\u{3B7}", "eta"), ("\u{3B8}", "theta"), ("\u{3D1}", "theta"),
+    ("\u{3B9}", "iota"), ("\u{3BA}", "kappa"), ("\u{3BB}", "lambda"), ("\u{3BC}", "mu"), ("\u{3BD}", "nu"),
+    ("\u{3BE}", "xi"), ("\u{3BF}", "omicron"), ("\u{3C0}", "pi"), ("\u{3C1}", "rho"), ("\u{3C3}", "sigma"),
+    ("\u{3C4}", "tau"), ("\u{3C5}", "upsilon"), ("\u{3C6}", "phi"), ("\u{3D5}", "phi"), ("\u{3C7}", "chi"),
+    ("\u{3C8}", "psi"), ("\u{3C9}", "omega"),
+    ("\u{393}", "capital gamma"), ("\u{394}", "capital delta"), ("\u{398}", "capital theta"),
+    ("\u{39B}", "capital lambda"), ("\u{39E}", "capital xi"), ("\u{3A0}", "capital pi"),
+    ("\u{3A3}", "capital sigma"), ("\u{3A6}", "capital phi"), ("\u{3A8}", "capital psi"), ("\u{3A9}", "capital omega"),
+    // 函数名
+    ("sin", "sine"), ("cos", "cosine"), ("tan", "tangent"), ("cot", "cotangent"), ("sec", "secant"),
+    ("csc", "cosecant"), ("arcsin", "arc sine"), ("arccos", "arc cosine"), ("arctan", "arc tangent"),
+    ("sinh", "hyperbolic sine"), ("cosh", "hyperbolic cosine"), ("tanh", "hyperbolic tangent"),
+    ("log", "log"), ("ln", "natural log"), ("exp", "exponential"), ("lim", "limit"), ("max", "maximum"),
+    ("min", "minimum"), ("sup", "supremum"), ("inf", "infimum"), ("det", "determinant"), ("gcd", "greatest common divisor"),
+    // 样式
+    ("TextBold", "bold"), ("TextItalic", "italic"), ("TextMonospace", "monospace"), ("TextSansSerif", "sans-serif"),
+    ("TextDoubleStruck", "double-struck"), ("TextScript", "script"), ("TextFraktur", "fraktur"),
+    ("TextBoldItalic", "bold italic"), ("TextSansSerifBold", "bold sans-serif"),
+    ("TextSansSerifBoldItalic", "bold italic sans-serif"), ("TextBoldScript", "bold script"),
+    ("TextBoldFraktur", "bold fraktur"), ("TextSansSerifItalic", "italic sans-serif"),
+];
+
+pub struct SpeechWriterContext<'a> {
+    words: Vec<String>, // 输出的短语
+    table: &'a PhraseTable,
+}
+
+impl<'a> SpeechWriterContext<'a> {
+    fn push_text(&mut self, s: &str) {
+        let s = s.trim();
+        if !s.is_empty() {
+            self.words.push(s.to_string());
+        }
+    }
+}
+
+// 单独朗读一个子表达式
+fn speak(table: &PhraseTable, exps: &[Exp]) -> Result<String, String> {
+    let mut c = SpeechWriterContext { words: Vec::new(), table };
+    for exp in exps {
+        write_exp(&mut c, exp)?;
+    }
+    Ok(c.words.join(" "))
+}
+
+// 朗读一个字符串中的每个字符: "αβ" -> "alpha beta"
+fn speak_chars(table: &PhraseTable, s: &str) -> String {
+    let decoded: String = s.chars()
+        .filter(|c| !('\u{2061}'..='\u{2064}').contains(c))
+        .collect();
+    if let Some(w) = table.word(&decoded) {
+        return w.to_string();
+    }
+    decoded.chars()
+        .map(|c| {
+            let key = c.to_string();
+            table.word(&key).map(|w| w.to_string()).unwrap_or(key)
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// 大型运算符的名字: ∑ -> sum, lim -> limit
+fn operator_name(table: &PhraseTable, e: &Exp) -> Result<String, String> {
+    match e {
+        Exp::ESymbol(_, s) | Exp::EMathOperator(s) => Ok(speak_chars(table, s)),
+        _ => speak(table, std::slice::from_ref(e)),
+    }
+}
+
+fn is_accent(e: &Exp) -> bool {
+    matches!(e, Exp::ESymbol(TeXSymbolType::Accent, _)
+        | Exp::ESymbol(TeXSymbolType::TOver, _)
+        | Exp::ESymbol(TeXSymbolType::TUnder, _)
+        | Exp::ESymbol(TeXSymbolType::BotAccent, _))
+}
+
+fn write_super(c: &mut SpeechWriterContext, base: &str, e: &Exp) -> Result<(), String> {
+    let table = c.table;
+    let exponent = speak(table, std::slice::from_ref(e))?;
+    let key = match exponent.as_str() {
+        "2" => "squared",
+        "3" => "cubed",
+        s if Some(s) == table.word("\u{2032}") => "prime",
+        _ => "super",
+    };
+    c.push_text(&table.phrase(key, &[base, &exponent]));
+    Ok(())
+}
+
+// EUnder/EOver/EUnderOver:
+// 基是大型运算符时读作极限(from ... to ... of), convertible的非运算符读作上下标, 否则读作上方/下方
+fn write_under_over(c: &mut SpeechWriterContext, convertible: bool, b: &Exp, under: Option<&Exp>, over: Option<&Exp>) -> Result<(), String> {
+    let table = c.table;
+    let speak_one = |e: &Exp| speak(table, std::slice::from_ref(e));
+
+    if shared::is_operator(b) {
+        let name = operator_name(table, b)?;
+        let res = match (under, over) {
+            (Some(u), Some(o)) => table.phrase("limits_underover", &[&name, &speak_one(u)?, &speak_one(o)?]),
+            (Some(u), None) => table.phrase("limits_under", &[&name, &speak_one(u)?]),
+            (None, Some(o)) => table.phrase("limits_over", &[&name, &speak_one(o)?]),
+            (None, None) => name,
+        };
+        c.push_text(&res);
+        return Ok(());
+    }
+
+    let base = speak_one(b)?;
+    let res = match (under, over) {
+        (None, Some(o)) if is_accent(o) => table.phrase("accent", &[&base, &speak_one(o)?]),
+        (Some(u), None) if is_accent(u) => table.phrase("accent", &[&base, &speak_one(u)?]),
+        (Some(u), Some(o)) if convertible => table.phrase("subsup", &[&base, &speak_one(u)?, &speak_one(o)?]),
+        (Some(u), None) if convertible => table.phrase("sub", &[&base, &speak_one(u)?]),
+        (None, Some(o)) if convertible => {
+            return write_super(c, &base, o);
+        },
+        (Some(u), Some(o)) => table.phrase("underover", &[&base, &speak_one(u)?, &speak_one(o)?]),
+        (Some(u), None) => table.phrase("under", &[&base, &speak_one(u)?]),
+        (None, Some(o)) => table.phrase("over", &[&base, &speak_one(o)?]),
+        (None, None) => base,
+    };
+    c.push_text(&res);
+    Ok(())
+}
+
+fn write_exp(c: &mut SpeechWriterContext, exp: &Exp) -> Result<(), String> {
+    node::with_stack(|| write_exp_inner(c, exp))
+}
+
+fn write_exp_inner(c: &mut SpeechWriterContext, exp: &Exp) -> Result<(), String> {
+    let table = c.table;
+    let speak_one = |e: &Exp| speak(table, std::slice::from_ref(e));
+    match exp {
+        Exp::ENumber(s) => {
+            c.push_text(s);
+        },
+
+        Exp::EIdentifier(s) | Exp::ESymbol(_, s) | Exp::EMathOperator(s) => {
+            c.push_text(&speak_chars(table, s));
+        },
+
+        Exp::ESpace(_) | Exp::EPhantom(_) => {},
+
+        Exp::EGrouped(exp_list) => {
+            for e in exp_list {
+                write_exp(c, e)?;
+            }
+        },
+
+        Exp::EDelimited(open, close, exp_list) => {
+            let mut inner = Vec::new();
+            for e in exp_list {
+                match e {
+                    InEDelimited::Left(delim) => inner.push(speak_chars(table, delim)),
+                    InEDelimited::Right(e) => inner.push(speak_one(e)?),
+                }
+            }
+            let inner = inner.join(" ");
+            let res = match (&**open, &**close) {
+                ("|", "|") | ("\u{2223}", "\u{2223}") => table.phrase("abs", &[&inner]),
+                ("\u{2016}", "\u{2016}") | ("\u{2225}", "\u{2225}") => table.phrase("norm", &[&inner]),
+                _ => table.phrase("delimited", &[&speak_chars(table, open), &inner, &speak_chars(table, close)]),
+            };
+            c.push_text(&res);
+        },
+
+        Exp::ESub(b, e) => {
+            if shared::is_operator(b) {
+                return write_under_over(c, true, b, Some(e), None);
+            }
+            c.push_text(&table.phrase("sub", &[&speak_one(b)?, &speak_one(e)?]));
+        },
+
+        Exp::ESuper(b, e) => {
+            if shared::is_operator(b) {
+                return write_under_over(c, true, b, None, Some(e));
+            }
+            let base = speak_one(b)?;
+            write_super(c, &base, e)?;
+        },
+
+        Exp::ESubsup(b, e1, e2) => {
+            if shared::is_operator(b) {
+                return write_under_over(c, true, b, Some(e1), Some(e2));
+            }
+            c.push_text(&table.phrase("subsup", &[&speak_one(b)?, &speak_one(e1)?, &speak_one(e2)?]));
+        },
+
+        Exp::EOver(convertible, b, e) => {
+            write_under_over(c, *convertible, b, None, Some(e))?;
+        },
+
+        Exp::EUnder(convertible, b, e) => {
+            write_under_over(c, *convertible, b, Some(e), None)?;
+        },
+
+        Exp::EUnderOver(convertible, b, e1, e2) => {
+            write_under_over(c, *convertible, b, Some(e1), Some(e2))?;
+        },
+
+        Exp::EFraction(frac_type, num, den) => {
+            let key = match frac_type {
+                FractionType::NoLineFrac => "binom",
+                _ => "frac",
+            };
+            c.push_text(&table.phrase(key, &[&speak_one(num)?, &speak_one(den)?]));
+        },
+
+        Exp::ESqrt(e) => {
+            c.push_text(&table.phrase("sqrt", &[&speak_one(e)?]));
+        },
+
+        Exp::ERoot(index, e) => {
+            c.push_text(&table.phrase("root", &[&speak_one(index)?, &speak_one(e)?]));
+        },
+
+        Exp::EBoxed(e) => {
+            c.push_text(&table.phrase("boxed", &[&speak_one(e)?]));
+        },
+
+        Exp::EScaled(_, e) => {
+            write_exp(c, e)?;
+        },
+
+        Exp::EArray(_, rows) => {
+            let mut spoken_rows = Vec::new();
+            for (i, row) in rows.iter().enumerate() {
+                let mut cells = Vec::new();
+                for cell in row {
+                    cells.push(speak(table, cell)?);
+                }
+                let cells = cells.join(&table.phrase("cell_sep", &[]));
+                spoken_rows.push(table.phrase("row", &[&(i + 1).to_string(), &cells]));
+            }
+            let spoken_rows = spoken_rows.join(&table.phrase("row_sep", &[]));
+            c.push_text(&table.phrase("array", &[&rows.len().to_string(), &spoken_rows]));
+        },
+
+        Exp::EText(_, s) => {
+            c.push_text(&table.phrase("text", &[s]));
+        },
+
+        Exp::EStyled(text_type, exp_list) => {
+            let inner = speak(table, exp_list)?;
+            if *text_type == TextType::TextNormal {
+                c.push_text(&inner);
+            } else {
+                let style = format!("{:?}", text_type);
+                let style = table.word(&style).unwrap_or(&style).to_string();
+                c.push_text(&table.phrase("style", &[&style, &inner]));
+            }
+        },
+    }
+    Ok(())
+}
+
+#[test]
+fn test_write_speech() {
+    let table = PhraseTable::english();
+
+    // \frac{x+1}{2}
+    let exps = vec![Exp::EFraction(FractionType::NormalFrac,
+        Box::new(Exp::EGrouped(vec![
+            Exp::EIdentifier("x".into()),
+            Exp::ESymbol(TeXSymbolType::Bin, "+".into()),
+            Exp::ENumber("1".into()),
+        ])),
+        Box::new(Exp::ENumber("2".into())))];
+    assert_eq!(write_speech(exps, &table).unwrap(), "the fraction with numerator x plus 1 and denominator 2");
+
+    // \sum_{i=1}^{n} a_i
+    let exps = vec![
+        Exp::EUnderOver(true,
+            Box::new(Exp::ESymbol(TeXSymbolType::Op, "\u{2211}".into())),
+            Box::new(Exp::EGrouped(vec![
+                Exp::EIdentifier("i".into()),
+                Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+                Exp::ENumber("1".into()),
+            ])),
+            Box::new(Exp::EIdentifier("n".into()))),
+        Exp::ESub(Box::new(Exp::EIdentifier("a".into())), Box::new(Exp::EIdentifier("i".into()))),
+    ];
+    assert_eq!(write_speech(exps, &table).unwrap(), "the sum from i equals 1 to n of a sub i");
+
+    // x^2, \hat{x}, |x|
+    let exps = vec![
+        Exp::ESuper(Box::new(Exp::EIdentifier("x".into())), Box::new(Exp::ENumber("2".into()))),
+        Exp::EOver(false, Box::new(Exp::EIdentifier("y".into())), Box::new(Exp::ESymbol(TeXSymbolType::Accent, "^".into()))),
+        Exp::EDelimited("|".into(), "|".into(), vec![InEDelimited::Right(Exp::EIdentifier("z".into()))]),
+    ];
+    assert_eq!(write_speech(exps, &table).unwrap(), "x squared y hat the absolute value of z");
+}
+
+#[test]
+fn test_phrase_table_override() {
+    let mut table = PhraseTable::english();
+    table.set_phrase("sqrt", "{0}的平方根");
+    table.set_word("+", "加");
+    let exps = vec![Exp::ESqrt(Box::new(Exp::EGrouped(vec![
+        Exp::EIdentifier("x".into()),
+        Exp::ESymbol(TeXSymbolType::Bin, "+".into()),
+        Exp::ENumber("1".into()),
+    ])))];
+    assert_eq!(write_speech(exps, &table).unwrap(), "x 加 1的平方根");
+}
+
+#[test]
+fn test_phrase_placeholder_in_arg() {
+    let table = PhraseTable::english();
+    assert_eq!(table.phrase("frac", &["{1}", "y"]), "the fraction with numerator {1} and denominator y");
+    let exps = vec![Exp::EFraction(FractionType::NormalFrac,
+        Box::new(Exp::EText(TextType::TextNormal, "{1}".into())),
+        Box::new(Exp::EIdentifier("y".into())))];
+    assert_eq!(write_speech(exps, &table).unwrap(), "the fraction with numerator {1} and denominator y");
+}
+
+// 把Exp转换为朗读文本
+pub fn write_speech(exps: Vec<Exp>, table: &PhraseTable) -> Result<String, String> {
+    speak(table, &exps)
+}
+
+// extra选项phrases: 措辞表csv路径, 在英文措辞表的基础上覆盖
+pub struct SpeechWriter;
+
+impl Writer for SpeechWriter {
+    fn name(&self) -> &'static str {
+        "speech"
+    }
+
+    fn write(&self, exps: Vec<Exp>, opts: &WriterOptions) -> Result<String, String> {
+        let mut table = PhraseTable::english();
+        if let Some(path) = opts.extra.get("phrases") {
+            table.extend_from_csv(path)?;
+        }
+        write_speech(exps, &table)
+    }
+}
@@ -0,0 +1,333 @@
+// 符号库: 按码点, 命令, 宏包查询符号
+// 主体来自tex_cmd_table.csv的每一行: env(宏包), 字符, 类型, 命令, 描述
+// 样式字母, 重音和数学算子仍由tex_unicode.rs, shared.rs中的表提供, 这里统一对外
+use std::{collections::HashMap, hash::BuildHasherDefault, io};
+use ahash::AHasher;
+use lazy_static::lazy_static;
+use crate::config;
+use super::node::{TeXSymbolType, TextType};
+use super::{shared, tex_unicode};
+
+// 不依赖宏包的定界符
+pub const BASE_DELIMITERS: [char; 17] = ['.', '(', ')', '[', ']', '|', '\u{2016}', '{', '}'
+                                        , '\u{2309}', '\u{2308}', '\u{2329}', '\u{232A}'
+                                        , '\u{230B}', '\u{230A}', '\u{231C}', '\u{231D}'];
+
+// 宏包的优先级: 宏包 -> 在列表中的位置, 重复的名字以第一次出现为准
+// 一个请求的宏包列表不变, 构造一次后用于该请求的所有查询
+#[derive(Debug, Clone, Default)]
+pub struct PackageOrder {
+    rank: HashMap<String, usize, BuildHasherDefault<AHasher>>,
+    len: usize,
+}
+
+impl PackageOrder {
+    pub fn new(packages: &[String]) -> PackageOrder {
+        let mut rank: HashMap<String, usize, BuildHasherDefault<AHasher>> = HashMap::with_hasher(BuildHasherDefault::<AHasher>::default());
+        for (i, p) in packages.iter().enumerate() {
+            rank.entry(p.clone()).or_insert(i);
+        }
+        PackageOrder { rank, len: packages.len() }
+    }
+
+    fn contains(&self, p: &str) -> bool {
+        self.rank.contains_key(p)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Symbol {
+    pub code_point: char,
+    pub command: String,
+    // 表中的env列, 如: base, amssymb, "mathabx -amssymb"
+    pub package: String,
+    pub symbol_type: TeXSymbolType,
+    pub description: String,
+}
+
+impl Symbol {
+    // 在给定的宏包下是否可用, base总是可用
+    // env中有多项时每一项都要满足: 普通名字需要在packages中, -开头的名字需要不在packages中
+    pub fn available(&self, packages: &PackageOrder) -> bool {
+        if self.package == "base" {
+            return true;
+        }
+        self.package.split_whitespace().all(|p| match p.strip_prefix('-') {
+            Some(p) => !packages.contains(p),
+            None => packages.contains(p),
+        })
+    }
+
+    // 使用这一行时需要加载的宏包: env中不带-的名字, base不需要宏包
+    pub fn required_packages(&self) -> Vec<&str> {
+        self.package.split_whitespace()
+            .filter(|p| *p != "base" && !p.starts_with('-'))
+            .collect()
+    }
+
+    // 排序用的优先级, 越小越优先: base最先, 然后按需要的宏包在packages中的位置,
+    // 需要多个宏包时取最靠前的一个, 只有排除条件的(如: -fourier)排在最后
+    pub fn priority(&self, packages: &PackageOrder) -> usize {
+        if self.package == "base" {
+            return 0;
+        }
+        self.package.split_whitespace()
+            .filter_map(|p| packages.rank.get(p).copied())
+            .min()
+            .map(|i| i + 1)
+            .unwrap_or(packages.len + 1)
+    }
+}
+
+pub struct SymbolDb {
+    symbols: Vec<Symbol>,
+    by_code_point: HashMap<char, Vec<usize>, BuildHasherDefault<AHasher>>,
+    by_command: HashMap<String, Vec<usize>, BuildHasherDefault<AHasher>>,
+}
+
+lazy_static! {
+    static ref SYMBOL_DB: Result<SymbolDb, String> = SymbolDb::load(&config::get_config().table_dir);
+}
+
+#[test]
+fn test_symbol_db() {
+    let csv = r#""base","\8804","Rel","\leq","r: less-than-or-equal"
+"base","\8804","Rel","\le","r: less-than-or-equal"
+"unicode-math","\8804","Rel","\leq","r: less-than-or-equal"
+"amssymb","\10877","Rel","\leqslant","LESS-THAN OR SLANTED EQUAL TO"
+"mathabx -amssymb","\10877","Rel","\leqslant","LESS-THAN OR SLANTED EQUAL TO"
+"base","(","Open","(","LEFT PARENTHESIS"
+"#;
+    let db = SymbolDb::from_reader(csv.as_bytes()).unwrap();
+    assert_eq!(db.symbols().len(), 6);
+
+    let none = PackageOrder::default();
+    let cmds: Vec<&str> = db.commands_for('\u{2264}', &none).iter().map(|s| s.command.as_str()).collect();
+    assert_eq!(cmds, vec!["\\leq", "\\le"]);
+    // 同一优先级取表中靠前的
+    assert_eq!(db.resolve('\u{2264}', &none).map(|s| s.command.as_str()), Some("\\leq"));
+    assert_eq!(db.by_code_point('\u{2264}').len(), 3);
+
+    let leqslant = db.by_command("leqslant");
+    assert_eq!(leqslant.len(), 2);
+    assert_eq!((leqslant[0].code_point, &leqslant[0].symbol_type), ('\u{2a7d}', &TeXSymbolType::Rel));
+
+    // mathabx -amssymb: 只有mathabx而没有amssymb时可用
+    let mathabx = PackageOrder::new(&["mathabx".to_string()]);
+    let both = PackageOrder::new(&["mathabx".to_string(), "amssymb".to_string()]);
+    assert_eq!(db.commands_for('\u{2a7d}', &mathabx)[0].package, "mathabx -amssymb");
+    assert_eq!(db.commands_for('\u{2a7d}', &both)[0].package, "amssymb");
+    assert!(db.commands_for('\u{2a7d}', &none).is_empty());
+    assert_eq!(db.commands_for('\u{2a7d}', &mathabx)[0].required_packages(), vec!["mathabx"]);
+    assert!(db.commands_for('\u{2264}', &none)[0].required_packages().is_empty());
+
+    assert_eq!(db.package("amssymb").len(), 1);
+    assert_eq!(db.package("mathabx").len(), 1);
+    assert_eq!(db.packages(), vec!["amssymb", "unicode-math"]);
+
+    assert!(db.is_delimiter('(', &none));
+    assert!(!db.is_delimiter('\u{2264}', &none));
+
+    assert!(SymbolDb::from_reader(r#""base","x","Foo","x","""#.as_bytes()).is_err());
+
+    // 多个宏包都提供时按packages的顺序
+    let csv = r#""base","\915","Alpha","\Gamma",""
+"-slantedGreek","\915","Alpha","\Gamma",""
+"unicode-math","\915","Alpha","\upGamma",""
+"-fourier","\120548","Alpha","\mathit{\Gamma}",""
+"fourier","\120548","Alpha","\varGamma",""
+"unicode-math","\120548","Alpha","\mitGamma",""
+"yhmath mathabx fourier","\9180","TOver","\wideparen",""
+"#;
+    let db = SymbolDb::from_reader(csv.as_bytes()).unwrap();
+    let packages = |names: &[&str]| PackageOrder::new(&names.iter().map(|s| s.to_string()).collect::<Vec<String>>());
+    let resolve = |c: char, names: &[&str]| db.resolve(c, &packages(names)).map(|s| s.command.as_str());
+    assert_eq!(resolve('\u{393}', &["unicode-math"]), Some("\\Gamma"));
+    assert_eq!(resolve('\u{1d6e4}', &[]), Some("\\mathit{\\Gamma}"));
+    assert_eq!(resolve('\u{1d6e4}', &["unicode-math"]), Some("\\mitGamma"));
+    assert_eq!(resolve('\u{1d6e4}', &["fourier", "unicode-math"]), Some("\\varGamma"));
+    assert_eq!(resolve('\u{1d6e4}', &["unicode-math", "fourier"]), Some("\\mitGamma"));
+    assert_eq!(resolve('\u{23dc}', &["yhmath", "fourier"]), None);
+    assert_eq!(resolve('\u{23dc}', &["yhmath", "fourier", "mathabx"]), Some("\\wideparen"));
+}
+
+impl SymbolDb {
+    // 全局符号库, 从config.toml的table_dir加载, 加载失败时返回错误
+    pub fn global() -> Result<&'static SymbolDb, String> {
+        SYMBOL_DB.as_ref().map_err(|e| e.clone())
+    }
+
+    pub fn load(dir: &str) -> Result<SymbolDb, String> {
+        let path = std::path::Path::new(dir).join("tex_cmd_table.csv");
+        let f = std::fs::File::open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        SymbolDb::from_reader(f)
+    }
+
+    // tex_cmd_table.csv格式, 没有表头
+    pub fn from_reader<R: io::Read>(r: R) -> Result<SymbolDb, String> {
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(r);
+        let mut db = SymbolDb {
+            symbols: Vec::new(),
+            by_code_point: HashMap::with_hasher(BuildHasherDefault::<AHasher>::default()),
+            by_command: HashMap::with_hasher(BuildHasherDefault::<AHasher>::default()),
+        };
+        for (line, result) in reader.records().enumerate() {
+            let record = result.map_err(|e| e.to_string())?;
+            let field = |i: usize| record.get(i).ok_or_else(|| format!("line {}: missing column {}", line + 1, i + 1));
+
+            let unicode = field(1)?;
+            let code_point = if unicode.starts_with('\\') && unicode.len() > 1 {
+                shared::parse_as_unicode_char(unicode)
+            } else {
+                unicode.chars().next()
+            }.ok_or_else(|| format!("line {}: invalid character {:?}", line + 1, unicode))?;
+            let category = field(2)?;
+            let symbol_type = parse_symbol_type(category)
+                .ok_or_else(|| format!("line {}: unknown category {:?}", line + 1, category))?;
+
+            let symbol = Symbol {
+                code_point,
+                command: field(3)?.to_string(),
+                package: field(0)?.to_string(),
+                symbol_type,
+                description: field(4)?.to_string(),
+            };
+            let index = db.symbols.len();
+            db.by_code_point.entry(symbol.code_point).or_default().push(index);
+            db.by_command.entry(symbol.command.clone()).or_default().push(index);
+            db.symbols.push(symbol);
+        }
+        Ok(db)
+    }
+
+    // 表中所有符号, 按表中的顺序
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    // 码点对应的所有符号, 不考虑宏包
+    pub fn by_code_point(&self, c: char) -> Vec<&Symbol> {
+        self.lookup(self.by_code_point.get(&c))
+    }
+
+    // 在给定宏包下可以输出c的命令, 按优先级排序, 同一优先级按表中的顺序
+    // 如: ≤ -> \leq, \le
+    pub fn commands_for(&self, c: char, packages: &PackageOrder) -> Vec<&Symbol> {
+        let mut res: Vec<&Symbol> = self.available(c, packages).collect();
+        res.sort_by_key(|s| s.priority(packages));
+        res
+    }
+
+    // 写出c时使用的命令, 即commands_for的第一个, 不分配也不排序
+    // tex_cmd_table中同一个宏包有些字符有多个命令, 顺序靠前的是常用的, 如: \leq, \le
+    pub fn resolve(&self, c: char, packages: &PackageOrder) -> Option<&Symbol> {
+        // min_by_key在相等时返回最后一个, 加上表中的位置取第一个
+        self.available(c, packages)
+            .enumerate()
+            .min_by_key(|(i, s)| (s.priority(packages), *i))
+            .map(|(_, s)| s)
+    }
+
+    // 在给定宏包下可用的符号, 按表中的顺序
+    fn available<'a, 'p>(&'a self, c: char, packages: &'p PackageOrder) -> impl Iterator<Item = &'a Symbol> + 'p where 'a: 'p {
+        self.by_code_point.get(&c).into_iter().flatten()
+            .map(|&i| &self.symbols[i])
+            .filter(move |s| s.available(packages))
+    }
+
+    // 命令对应的符号, 可以省略开头的\, 如: leqslant -> ⩽ Rel
+    pub fn by_command(&self, cmd: &str) -> Vec<&Symbol> {
+        match self.by_command.get(cmd) {
+            Some(indices) => self.lookup(Some(indices)),
+            None => self.lookup(self.by_command.get(&format!("\\{}", cmd))),
+        }
+    }
+
+    // 宏包提供的符号, 包括"mathabx -amssymb"这类组合条件中需要该宏包的
+    pub fn package(&self, name: &str) -> Vec<&Symbol> {
+        self.symbols.iter()
+            .filter(|s| s.package.split_whitespace().any(|p| p == name))
+            .collect()
+    }
+
+    // 表中出现的宏包名, 不包括base和"mathabx -amssymb"这类组合条件
+    pub fn packages(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.symbols.iter()
+            .map(|s| s.package.as_str())
+            .filter(|p| !matches!(*p, "base" | "literal") && !p.contains(' ') && !p.starts_with('-'))
+            .map(|p| p.to_string())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    // 是否可以作为\left, \right的定界符: 基本定界符, 或给定宏包下类型为Open/Close的符号
+    pub fn is_delimiter(&self, c: char, packages: &PackageOrder) -> bool {
+        BASE_DELIMITERS.contains(&c)
+            || self.available(c, packages)
+                .any(|s| matches!(s.symbol_type, TeXSymbolType::Open | TeXSymbolType::Close))
+    }
+
+    // 样式字母, 如: TextDoubleStruck + R -> ℝ
+    pub fn styled(&self, t: &TextType, c: char) -> Option<char> {
+        tex_unicode::lookup_text_unicode(t, c)
+    }
+
+    // 样式字母对应的命令, 如: ℝ -> \mathbb{R}
+    pub fn styled_command(&self, c: char) -> Option<String> {
+        tex_unicode::lookup_styled_command(c)
+    }
+
+    // 重音符号对应的命令, 如: ^ -> \hat
+    pub fn accent(&self, c: char) -> Option<String> {
+        shared::get_diacriticals(c.to_string().as_str())
+    }
+
+    // 是否为\sin这类内置的数学算子
+    pub fn is_math_operator(&self, name: &str) -> bool {
+        shared::is_mathoperator(name)
+    }
+
+    fn lookup(&self, indices: Option<&Vec<usize>>) -> Vec<&Symbol> {
+        indices.map(|v| v.iter().map(|&i| &self.symbols[i]).collect()).unwrap_or_default()
+    }
+}
+
+// 查询参数中的字符: 单个字符, 或U+2264这样的码点
+pub fn parse_char_query(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => {
+            let hex = s.strip_prefix("U+").or_else(|| s.strip_prefix("u+"))?;
+            u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+        }
+    }
+}
+
+// U+2264
+pub fn format_code_point(c: char) -> String {
+    format!("U+{:04X}", c as u32)
+}
+
+fn parse_symbol_type(s: &str) -> Option<TeXSymbolType> {
+    let t = match s {
+        "Ord" => TeXSymbolType::Ord,
+        "Op" => TeXSymbolType::Op,
+        "Bin" => TeXSymbolType::Bin,
+        "Rel" => TeXSymbolType::Rel,
+        "Open" => TeXSymbolType::Open,
+        "Close" => TeXSymbolType::Close,
+        "Pun" => TeXSymbolType::Pun,
+        "Accent" => TeXSymbolType::Accent,
+        "Fence" => TeXSymbolType::Fence,
+        "TOver" => TeXSymbolType::TOver,
+        "TUnder" => TeXSymbolType::TUnder,
+        "Alpha" => TeXSymbolType::Alpha,
+        "BotAccent" => TeXSymbolType::BotAccent,
+        "Rad" => TeXSymbolType::Rad,
+        _ => return None,
+    };
+    Some(t)
+}
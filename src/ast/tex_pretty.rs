@@ -0,0 +1,531 @@
+// 多行TeX美化输出
+// 把write_exp输出的紧凑TeX重新排版, 输出的token序列与紧凑模式完全一致, 只改变空白:
+// 1. \\ 之后换行
+// 2. \begin{...} \end{...} 之间的内容缩进
+// 3. 尽可能对齐 & 分隔的列
+// 4. 超长的行在运算符前折行
+// \text{...}等文本模式的参数原样输出, 因为其中的空白是有意义的
+
+// 美化选项
+#[derive(Debug, Clone)]
+pub struct PrettyOptions {
+    pub indent: usize,       // 每一级缩进的空格数
+    pub max_width: usize,    // 超过此宽度的行会在运算符前折行, 0表示不折行
+    pub align_columns: bool, // 是否对齐 & 分隔的列
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        PrettyOptions {
+            indent: 2,
+            max_width: 80,
+            align_columns: true,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum TokKind {
+    ControlWord,   // \alpha
+    ControlSymbol, // \, \\ \{
+    Space,         // 空格, 换行
+    BeginGroup,    // {
+    EndGroup,      // }
+    Align,         // &
+    Other,         // 其他单个字符
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Tok<'a> {
+    kind: TokKind,
+    text: &'a str,
+}
+
+#[test]
+fn test_tokenize() {
+    let toks = tokenize("\\alpha x\\\\ \\,{a}&");
+    let kinds: Vec<TokKind> = toks.iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, vec![
+        TokKind::ControlWord, TokKind::Space, TokKind::Other, TokKind::ControlSymbol,
+        TokKind::Space, TokKind::ControlSymbol, TokKind::BeginGroup, TokKind::Other,
+        TokKind::EndGroup, TokKind::Align,
+    ]);
+    assert_eq!(toks[0].text, "\\alpha");
+    assert_eq!(toks[3].text, "\\\\");
+}
+
+// 按TeX的规则切分token, 连续空白合并为一个Space
+fn tokenize(s: &str) -> Vec<Tok<'_>> {
+    let mut toks = Vec::new();
+    let mut iter = s.char_indices().peekable();
+    while let Some((start, c)) = iter.next() {
+        let mut end = start + c.len_utf8();
+        let kind = match c {
+            '\\' => {
+                match iter.next() {
+                    Some((i, n)) if n.is_ascii_alphabetic() => {
+                        end = i + 1;
+                        while let Some(&(j, m)) = iter.peek() {
+                            if !m.is_ascii_alphabetic() {
+                                break;
+                            }
+                            end = j + 1;
+                            iter.next();
+                        }
+                        TokKind::ControlWord
+                    },
+                    Some((i, n)) => {
+                        end = i + n.len_utf8();
+                        TokKind::ControlSymbol
+                    },
+                    None => TokKind::Other,
+                }
+            },
+            c if c.is_whitespace() => {
+                while let Some(&(j, m)) = iter.peek() {
+                    if !m.is_whitespace() {
+                        break;
+                    }
+                    end = j + m.len_utf8();
+                    iter.next();
+                }
+                TokKind::Space
+            },
+            '{' => TokKind::BeginGroup,
+            '}' => TokKind::EndGroup,
+            '&' => TokKind::Align,
+            _ => TokKind::Other,
+        };
+        toks.push(Tok { kind, text: &s[start..end] });
+    }
+    toks
+}
+
+// 参数需要原样输出的命令(文本模式)
+fn is_verbatim_cmd(cmd: &str) -> bool {
+    cmd.starts_with("\\text") || cmd == "\\mbox"
+}
+
+// 带列格式参数的环境: \begin{array}{ccc}
+fn has_column_spec(env: &str) -> bool {
+    env == "array" || env == "subarray" || env == "tabular"
+}
+
+// 可以在前面折行的运算符
+fn is_break_operator(tok: &Tok) -> bool {
+    match tok.kind {
+        TokKind::Other => matches!(tok.text, "+" | "-" | "=" | "<" | ">"),
+        TokKind::ControlWord => matches!(tok.text,
+            "\\leq" | "\\geq" | "\\le" | "\\ge" | "\\neq" | "\\ne" | "\\approx" | "\\equiv"
+            | "\\sim" | "\\simeq" | "\\cong" | "\\propto" | "\\pm" | "\\mp" | "\\times"
+            | "\\cdot" | "\\div" | "\\to" | "\\rightarrow" | "\\leftarrow" | "\\Rightarrow"
+            | "\\Leftarrow" | "\\Leftrightarrow" | "\\iff" | "\\implies" | "\\mapsto"
+            | "\\in" | "\\notin" | "\\subset" | "\\subseteq" | "\\supset" | "\\supseteq"
+            | "\\cup" | "\\cap" | "\\ll" | "\\gg" | "\\leqslant" | "\\geqslant"),
+        _ => false,
+    }
+}
+
+// 找到与toks[open]处的{匹配的}的位置
+fn matching_group_end(toks: &[Tok], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, t) in toks.iter().enumerate().skip(open) {
+        match t.kind {
+            TokKind::BeginGroup => depth += 1,
+            TokKind::EndGroup => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+// 跳过空白, 返回下一个非空白token的位置
+fn skip_space(toks: &[Tok], mut i: usize) -> usize {
+    while i < toks.len() && toks[i].kind == TokKind::Space {
+        i += 1;
+    }
+    i
+}
+
+fn concat(toks: &[Tok]) -> String {
+    toks.iter().map(|t| t.text).collect()
+}
+
+// \begin{name}之后, 找到匹配的\end{name}
+// return: (环境名, body开始位置, \end所在位置, \end{name}之后的位置)
+fn match_environment(toks: &[Tok], begin: usize) -> Option<(String, usize, usize, usize)> {
+    let open = skip_space(toks, begin + 1);
+    if open >= toks.len() || toks[open].kind != TokKind::BeginGroup {
+        return None;
+    }
+    let close = matching_group_end(toks, open)?;
+    let name = concat(&toks[open + 1..close]);
+
+    let mut depth = 0;
+    let mut i = close + 1;
+    while i < toks.len() {
+        if toks[i].kind == TokKind::ControlWord {
+            if toks[i].text == "\\begin" {
+                depth += 1;
+            } else if toks[i].text == "\\end" {
+                if depth == 0 {
+                    let open = skip_space(toks, i + 1);
+                    if open >= toks.len() || toks[open].kind != TokKind::BeginGroup {
+                        return None;
+                    }
+                    let end_close = matching_group_end(toks, open)?;
+                    return Some((name, close + 1, i, end_close + 1));
+                }
+                depth -= 1;
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+// 按分隔符切分, 只在{}和\begin \end的最外层切分
+fn split_top_level<'a, 't>(toks: &'t [Tok<'a>], is_sep: impl Fn(&Tok) -> bool) -> Vec<&'t [Tok<'a>]> {
+    let mut parts = Vec::new();
+    let mut group_depth = 0;
+    let mut env_depth = 0;
+    let mut start = 0;
+    for (i, t) in toks.iter().enumerate() {
+        match t.kind {
+            TokKind::BeginGroup => group_depth += 1,
+            TokKind::EndGroup => group_depth -= 1,
+            TokKind::ControlWord if t.text == "\\begin" => env_depth += 1,
+            TokKind::ControlWord if t.text == "\\end" => env_depth -= 1,
+            _ => {
+                if group_depth == 0 && env_depth == 0 && is_sep(t) {
+                    parts.push(&toks[start..i]);
+                    start = i + 1;
+                }
+            },
+        }
+    }
+    parts.push(&toks[start..]);
+    parts
+}
+
+// 逐行构建输出, 负责处理token之间的空白和折行
+struct LineBuilder {
+    lines: Vec<String>,
+    pending_space: bool,     // 原文中有空白, 如果后面还有token则输出一个空格
+    after_control_word: bool, // 上一个token是控制词, 后面紧跟字母时必须有空格
+    breaks: Vec<usize>,      // 当前行中可以折行的位置
+    wrap_width: usize,       // 0表示不折行
+    indent: String,
+}
+
+impl LineBuilder {
+    fn new(wrap_width: usize, indent: String) -> LineBuilder {
+        LineBuilder {
+            lines: vec![String::new()],
+            pending_space: false,
+            after_control_word: false,
+            breaks: Vec::new(),
+            wrap_width,
+            indent,
+        }
+    }
+
+    fn current(&mut self) -> &mut String {
+        self.lines.last_mut().unwrap()
+    }
+
+    fn push_space(&mut self) {
+        self.pending_space = true;
+    }
+
+    fn push_str(&mut self, s: &str, breakable: bool) {
+        if s.is_empty() {
+            return;
+        }
+        let starts_alpha = s.chars().next().unwrap().is_ascii_alphabetic();
+        let need_space = (self.pending_space || (self.after_control_word && starts_alpha))
+            && !self.current().is_empty();
+        if need_space {
+            if breakable && self.wrap_width > 0 {
+                let pos = self.current().len();
+                self.breaks.push(pos);
+            }
+            self.current().push(' ');
+        }
+        self.current().push_str(s);
+        self.pending_space = false;
+        self.after_control_word = false;
+        self.wrap();
+    }
+
+    fn push_tok(&mut self, tok: &Tok) {
+        self.push_str(tok.text, is_break_operator(tok));
+        self.after_control_word = tok.kind == TokKind::ControlWord;
+    }
+
+    // 在最后一个可折行的位置折行
+    fn wrap(&mut self) {
+        if self.wrap_width == 0 || self.current().chars().count() <= self.wrap_width {
+            return;
+        }
+        if let Some(pos) = self.breaks.pop() {
+            let rest = self.current()[pos + 1..].to_string();
+            self.current().truncate(pos);
+            let line = self.indent.clone() + &rest;
+            self.lines.push(line);
+            self.breaks.clear();
+        }
+    }
+
+    fn newline(&mut self) {
+        self.lines.push(String::new());
+        self.pending_space = false;
+        self.after_control_word = false;
+        self.breaks.clear();
+    }
+
+    // 追加多行内容: 第一行接在当前行后面, 其余各行另起
+    fn push_lines(&mut self, lines: Vec<String>) {
+        for (i, line) in lines.into_iter().enumerate() {
+            if i > 0 {
+                self.newline();
+            }
+            self.push_str(&line, false);
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        if self.lines.len() > 1 && self.current().is_empty() {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
+
+struct Formatter<'o> {
+    opts: &'o PrettyOptions,
+}
+
+impl<'o> Formatter<'o> {
+    fn indent(&self) -> String {
+        " ".repeat(self.opts.indent)
+    }
+
+    // 格式化一段token序列, 返回的各行不带外层缩进
+    fn format_seq(&self, toks: &[Tok], width: usize) -> Vec<String> {
+        let mut lb = LineBuilder::new(width, self.indent());
+        let mut i = 0;
+        while i < toks.len() {
+            let t = &toks[i];
+            match t.kind {
+                TokKind::Space => {
+                    lb.push_space();
+                },
+                TokKind::ControlWord if t.text == "\\begin" => {
+                    if let Some((name, body_start, end_pos, next)) = match_environment(toks, i) {
+                        let lines = self.format_environment(&name, &toks[i..body_start], &toks[body_start..end_pos], &toks[end_pos..next], width);
+                        lb.push_lines(lines);
+                        lb.after_control_word = false;
+                        i = next;
+                        continue;
+                    }
+                    lb.push_tok(t);
+                },
+                TokKind::ControlWord if is_verbatim_cmd(t.text) => {
+                    lb.push_tok(t);
+                    // \text*{...}: 参数原样输出
+                    let mut j = i + 1;
+                    while j < toks.len() && toks[j].kind == TokKind::Other && toks[j].text == "*" {
+                        lb.push_tok(&toks[j]);
+                        j += 1;
+                    }
+                    if j < toks.len() && toks[j].kind == TokKind::BeginGroup {
+                        if let Some(end) = matching_group_end(toks, j) {
+                            lb.push_str(&concat(&toks[j..=end]), false);
+                            i = end + 1;
+                            continue;
+                        }
+                    }
+                    i = j;
+                    continue;
+                },
+                TokKind::ControlSymbol if t.text == "\\\\" => {
+                    lb.push_tok(t);
+                    lb.newline();
+                },
+                TokKind::BeginGroup => {
+                    if let Some(end) = matching_group_end(toks, i) {
+                        let inner = self.format_seq(&toks[i + 1..end], 0);
+                        let mut lines = vec!["{".to_string()];
+                        append_lines(&mut lines, inner, "");
+                        append_lines(&mut lines, vec!["}".to_string()], "");
+                        lb.push_lines(lines);
+                        i = end + 1;
+                        continue;
+                    }
+                    lb.push_tok(t);
+                },
+                _ => {
+                    lb.push_tok(t);
+                },
+            }
+            i += 1;
+        }
+        lb.finish()
+    }
+
+    // \begin{name}[{cols}] body \end{name}
+    fn format_environment(&self, name: &str, begin: &[Tok], body: &[Tok], end: &[Tok], width: usize) -> Vec<String> {
+        let mut head = concat(&begin.iter().filter(|t| t.kind != TokKind::Space).copied().collect::<Vec<Tok>>());
+        let mut body = body;
+        if has_column_spec(name) {
+            let open = skip_space(body, 0);
+            if open < body.len() && body[open].kind == TokKind::BeginGroup {
+                if let Some(close) = matching_group_end(body, open) {
+                    head.push_str(&concat(&body[open..=close]));
+                    body = &body[close + 1..];
+                }
+            }
+        }
+        let tail: String = end.iter().filter(|t| t.kind != TokKind::Space).map(|t| t.text).collect();
+
+        let mut lines = vec![head];
+        let indent = self.indent();
+        let inner_width = width.saturating_sub(self.opts.indent);
+        for line in self.format_rows(body, inner_width) {
+            if line.is_empty() {
+                lines.push(line);
+            } else {
+                lines.push(indent.clone() + &line);
+            }
+        }
+        lines.push(tail);
+        lines
+    }
+
+    // exp1 & exp2 \\
+    // exp3 & exp4
+    fn format_rows(&self, body: &[Tok], width: usize) -> Vec<String> {
+        let mut rows = split_top_level(body, |t| t.kind == TokKind::ControlSymbol && t.text == "\\\\");
+        // 最后一行之后的\\后面只有空白
+        let trailing_break = rows.len() > 1 && rows.last().unwrap().iter().all(|t| t.kind == TokKind::Space);
+        if trailing_break {
+            rows.pop();
+        }
+        if rows.len() == 1 && rows[0].iter().all(|t| t.kind == TokKind::Space) {
+            return Vec::new();
+        }
+
+        let cells: Vec<Vec<Vec<String>>> = rows.iter().map(|row| {
+            split_top_level(row, |t| t.kind == TokKind::Align)
+                .iter()
+                .map(|cell| self.format_seq(cell, 0))
+                .collect()
+        }).collect();
+
+        // 所有单元格都是单行时才对齐
+        let single_line = cells.iter().all(|row| row.iter().all(|c| c.len() == 1));
+        let mut widths = Vec::<usize>::new();
+        if self.opts.align_columns && single_line {
+            for row in &cells {
+                for (j, c) in row.iter().enumerate() {
+                    let w = c[0].chars().count();
+                    if j >= widths.len() {
+                        widths.push(w);
+                    } else if widths[j] < w {
+                        widths[j] = w;
+                    }
+                }
+            }
+            let total: usize = widths.iter().sum::<usize>() + 3 * widths.len();
+            if width > 0 && total > width {
+                widths.clear();
+            }
+        }
+
+        let mut lines = Vec::new();
+        for (i, row) in cells.into_iter().enumerate() {
+            let n = row.len();
+            let mut row_lines = vec![String::new()];
+            for (j, mut cell) in row.into_iter().enumerate() {
+                if j + 1 < n && !widths.is_empty() {
+                    let w = cell[0].chars().count();
+                    cell[0].push_str(&" ".repeat(widths[j] - w));
+                }
+                let sep = if j == 0 { "" } else { " & " };
+                append_lines(&mut row_lines, cell, sep);
+            }
+            if i + 1 < rows.len() || trailing_break {
+                append_lines(&mut row_lines, vec!["\\\\".to_string()], " ");
+            }
+            for line in row_lines {
+                lines.push(line.trim_end_matches(' ').to_string());
+            }
+        }
+        lines
+    }
+}
+
+// 第一行用sep接在最后一行后面, 其余各行另起
+fn append_lines(lines: &mut Vec<String>, other: Vec<String>, sep: &str) {
+    for (i, line) in other.into_iter().enumerate() {
+        if i == 0 {
+            let last = lines.last_mut().unwrap();
+            if last.is_empty() || line.is_empty() {
+                last.push_str(sep.trim());
+                if !last.is_empty() && !line.is_empty() && !sep.trim().is_empty() {
+                    last.push(' ');
+                }
+            } else {
+                last.push_str(sep);
+            }
+            last.push_str(&line);
+        } else {
+            lines.push(line);
+        }
+    }
+}
+
+#[test]
+fn test_pretty_print_matrix() {
+    let tex = "A = \\begin{pmatrix}\n1 & 23 \\\\\n456 & 7\n\\end{pmatrix}";
+    let res = pretty_print(tex, &PrettyOptions::default());
+    assert_eq!(res, "A = \\begin{pmatrix}\n  1   & 23 \\\\\n  456 & 7\n\\end{pmatrix}");
+    assert_eq!(significant_tokens(tex), significant_tokens(&res));
+}
+
+#[test]
+fn test_pretty_print_nested() {
+    let tex = "f(x) = \\begin{cases}\n1 & \\text{if  x} \\\\\n\\begin{array}{cc}\na & b\n\\end{array} & x^{2}\n\\end{cases}";
+    let res = pretty_print(tex, &PrettyOptions::default());
+    assert!(res.contains("\\text{if  x}"));
+    assert!(res.contains("\n    a & b\n"));
+    assert_eq!(significant_tokens(tex), significant_tokens(&res));
+}
+
+#[test]
+fn test_pretty_print_wrap() {
+    let tex = "a + b + c + d = \\alpha x + \\beta y";
+    let opts = PrettyOptions { max_width: 12, ..PrettyOptions::default() };
+    let res = pretty_print(tex, &opts);
+    assert!(res.lines().count() > 1);
+    assert!(res.lines().all(|l| l.chars().count() <= 14));
+    assert_eq!(significant_tokens(tex), significant_tokens(&res));
+}
+
+#[cfg(test)]
+fn significant_tokens(s: &str) -> Vec<String> {
+    tokenize(s).iter().filter(|t| t.kind != TokKind::Space).map(|t| t.text.to_string()).collect()
+}
+
+// 美化TeX输出, 结果与输入的token序列一致
+pub fn pretty_print(tex: &str, opts: &PrettyOptions) -> String {
+    let toks = tokenize(tex);
+    let f = Formatter { opts };
+    f.format_seq(&toks, opts.max_width).join("\n")
+}
@@ -1,64 +1,10 @@
 use std::{collections::HashMap, hash::{BuildHasherDefault, Hash}};
 use lazy_static::lazy_static;
 use ahash::AHasher;
-use crate::config;
-use super::{node::{self, Exp}, shared::{escape_latex, parse_as_unicode_char}};
+use unicode_normalization::char::{compose, decompose_canonical, is_combining_mark};
+use crate::config::{self, CombiningConfig, Negation, Unmapped};
+use super::{node::{self, Exp}, shared::{command_package, escape_latex, get_diacriticals, is_unavailable_diacritical, parse_as_unicode_char}, mapping::Mapping, symbol_db::{PackageOrder, Symbol, SymbolDb}, writer::{Envs, Packages}};
 
-#[test]
-fn test_spilt_as_char() {
-    let s = "地点 ";
-    let res = spilt_as_char(s);
-    println!("{:?}", res);
-}
-
-fn spilt_as_char(s: &str) -> Vec<char>{
-    // println!("{:?}", s);
-    let mut res = Vec::new();
-    let mut i = 0;
-    while i < s.chars().count() {
-        let c = s.chars().nth(i).unwrap();
-        if c == '\\' {
-            // 以\开头的情况有:
-            // 1. \n \t \r等转义字符 -> Escape
-            // 2. \d{1~5} unicode码点 -> Unicode
-            // 3. \" \\ 等引号内转义字符
-            // ! WARING: 可能会出现i+1越界的情况, 主要是\后面没有字符的情况, 实际上是非法的
-            let next = s.chars().nth(i + 1).unwrap();
-            if next.is_ascii_digit() {
-                let mut j = i + 1;
-                while j < s.len() && s.chars().nth(j).unwrap().is_ascii_digit() {
-                    j += 1;
-                }
-                // \d{1~5} -> \12345
-                let num = s.get(i + 1..j).unwrap().parse::<u32>().unwrap();
-                if let Some(unicode) = std::char::from_u32(num) {
-                    res.push(unicode);
-                }else{
-                    panic!("invalid unicode: {:?}", num);
-                }
-                i = j;
-            }else{
-                if next == 'n' || next == 't' || next == 'r' {
-                    // \n \t \r \f \v
-                    match next {
-                        'n' => res.push('\n'),
-                        't' => res.push('\t'),
-                        'r' => res.push('\r'),
-                        _ => panic!("unknown escape char: {:?}", next)
-                    }
-                }else{
-                    // 引号内的转义字符
-                    res.push(next);
-                }
-                i += 2;
-            }
-        }else{
-            res.push(c);
-            i += 1;
-        }
-    }
-    res
-}
 #[test]
 fn test_escapse_text(){
     let s = r#"@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_""#;
@@ -69,8 +15,7 @@ fn test_escapse_text(){
 // 转义EText中的字符: \text{...} 里面的字符转义
 pub fn escapse_text(s: &str) -> String{
     let mut res = String::new();
-    let chars = spilt_as_char(s);
-    for c in chars {
+    for c in s.chars() {
         res.push_str(&escape_text_char(&c));
     }
     res
@@ -91,8 +36,7 @@ fn escape_text_char(c: &char) -> String{
 // 转义文本中的字符: \text{...} 里面的字符提出到markdown环境的转义
 pub fn escaped_text_md(s: &str) -> String{
     let mut res = String::new();
-    let chars = spilt_as_char(s);
-    for c in chars {
+    for c in s.chars() {
         res.push_str(&escape_md_char(&c));
     }
     res
@@ -110,45 +54,114 @@ fn escape_md_char(c: &char) -> String{
 
 #[test]
 fn test_get_math_tex_many(){
-    let s = "a\\n\\t\\r\\8722\\177\\8747,test\\65024";
-    let mut envs = HashMap::new();
+    let s = "a\n\t\r\u{2212}\u{b1}\u{222b},test\u{fe00}";
+    let mut envs = Envs::new();
     envs.insert("amsmath".to_string(), true);
     envs.insert("amssymb".to_string(), true);
-    let res = get_math_tex_many(s, &envs);
+    let res = get_math_tex_many(s, &enabled_packages(&envs), &Mapping::default(), &CombiningConfig::default(), Unmapped::Text, &mut Packages::new()).unwrap();
     dbg!(&res);
     println!("{:?}", res.0.as_bytes());
     assert_eq!(res.0, "a\n\t\r-\\pm\\int,test");
 
-    let s = "C\\160\\8203";
+    let s = "C\u{a0}\u{200b}";
     let want = "C~\\hspace{0pt}";
-    let res = get_math_tex_many(s, &envs);
+    let res = get_math_tex_many(s, &enabled_packages(&envs), &Mapping::default(), &CombiningConfig::default(), Unmapped::Text, &mut Packages::new()).unwrap();
     dbg!(&res);
     assert_eq!(res.0, want);
 
-    let s = "\\8202";
+    let s = "\u{200a}";
     let want = "\\,";
-    let res = get_math_tex_many(s, &envs);
+    let res = get_math_tex_many(s, &enabled_packages(&envs), &Mapping::default(), &CombiningConfig::default(), Unmapped::Text, &mut Packages::new()).unwrap();
     dbg!(&res);
     assert_eq!(res.0, want);
 
-    let s = "\\8203";
+    let s = "\u{200b}";
     let want = "\\hspace{0pt}";
-    let res = get_math_tex_many(s, &envs);
+    let res = get_math_tex_many(s, &enabled_packages(&envs), &Mapping::default(), &CombiningConfig::default(), Unmapped::Text, &mut Packages::new()).unwrap();
     dbg!(&res);
     assert_eq!(res.0, want);
+
+    // 用户映射优先于符号表
+    let mut mapping = Mapping::default();
+    mapping.add_symbol("\u{b1}", "\\plusminus").unwrap();
+    let res = get_math_tex_many("a\u{b1}1", &enabled_packages(&envs), &mapping, &CombiningConfig::default(), Unmapped::Text, &mut Packages::new()).unwrap();
+    assert_eq!(res.0, "a\\plusminus1");
+
+    // 记录用到的符号所在的宏包
+    let mut used = Packages::new();
+    get_math_tex_many("\u{2214}\u{b1}", &enabled_packages(&envs), &Mapping::default(), &CombiningConfig::default(), Unmapped::Text, &mut used).unwrap();
+    assert_eq!(used.into_iter().collect::<Vec<String>>(), vec!["amssymb"]);
+}
+
+#[test]
+fn test_get_math_tex_combining(){
+    let mut envs = Envs::new();
+    envs.insert("amsmath".to_string(), true);
+    let tex = |s: &str, combining: &CombiningConfig| get_math_tex_many(s, &enabled_packages(&envs), &Mapping::default(), combining, Unmapped::Text, &mut Packages::new()).unwrap();
+    let default = CombiningConfig::default();
+    assert_eq!(tex("x\u{302}", &default), ("\\hat{x}".to_string(), 1));
+    assert_eq!(tex("n\u{303}+1", &default), ("\\tilde{n}+1".to_string(), 3));
+    assert_eq!(tex("=\u{338}", &default).0, "\\neq");
+    assert_eq!(tex("\u{2208}\u{338}", &default).0, "\\notin");
+    assert_eq!(tex("a\u{338}", &default).0, "\\not a");
+    assert_eq!(tex("\u{e9}", &default).0, "\\acute{e}");
+    assert_eq!(tex("\u{1d6}", &default).0, "\\bar{\\ddot{u}}");
+    // 变体选择符不是组合重音, 仍然忽略
+    assert_eq!(tex("x\u{302}\u{fe00}", &default).0, "\\hat{x}");
+
+    let not = CombiningConfig { negation: Negation::Not, ..CombiningConfig::default() };
+    assert_eq!(tex("=\u{338}", &not).0, "\\not=");
+    assert_eq!(tex("\u{2208}\u{338}", &not).0, "\\not\\in");
+
+    let off = CombiningConfig { accents: false, decompose: false, ..CombiningConfig::default() };
+    assert_ne!(tex("x\u{302}", &off).0, "\\hat{x}");
+    assert_eq!(tex("\u{e9}", &off).0, "\\text{\u{e9}}");
+    assert_eq!(tex("=\u{338}", &off).0, "\\neq");
+}
+
+#[test]
+fn test_get_math_tex_unmapped(){
+    let envs = Envs::new();
+    let tex = |s: &str, unmapped: Unmapped| {
+        let mut used = Packages::new();
+        get_math_tex_many(s, &enabled_packages(&envs), &Mapping::default(), &CombiningConfig::default(), unmapped, &mut used)
+            .map(|(res, _)| (res, used.into_iter().collect::<Vec<String>>()))
+    };
+    // ASCII字符不受影响, 连续的未映射字符合并到一个\text{}
+    assert_eq!(tex("x\u{20AC}\u{4e2d}\u{6587}", Unmapped::Text).unwrap(), ("x\\text{\u{20AC}\u{4e2d}\u{6587}}".to_string(), vec!["amsmath".to_string()]));
+    assert_eq!(tex("\u{20AC}\u{4e2d}", Unmapped::Command).unwrap(), ("\\text{\\texteuro}\\char\"4E2D".to_string(), vec!["amsmath".to_string(), "textcomp".to_string()]));
+    assert_eq!(tex("\u{20B9}", Unmapped::Char).unwrap().0, "\\char\"20B9");
+    assert!(tex("\u{20B9}", Unmapped::Error).is_err());
+    assert_eq!(tex("a+b", Unmapped::Error).unwrap().0, "a+b");
+
+    // 组合字符和否定的基字符同样按unmapped输出: ё = е + U+0308
+    assert_eq!(tex("\u{435}\u{308}", Unmapped::Text).unwrap().0, "\\ddot{\\text{\u{435}}}");
+    assert_eq!(tex("\u{451}", Unmapped::Char).unwrap().0, "\\ddot{\\char\"0435}");
+    assert_eq!(tex("\u{435}\u{338}", Unmapped::Char).unwrap().0, "\\not\\char\"0435");
+    assert!(tex("\u{435}\u{308}", Unmapped::Error).is_err());
+    assert!(tex("\u{451}", Unmapped::Error).is_err());
+    assert!(tex("\u{435}\u{338}", Unmapped::Error).is_err());
+    assert_eq!(tex("a\u{308}", Unmapped::Error).unwrap().0, "\\ddot{a}");
 }
 
 // 转换字符串为tex输出
+// 0. 用户映射 -> 映射的命令
 // 1. unicode+env -> tex命令
 // 2. 转义字符 -> 转义输出
-// 3. \d{1~5} -> \12345 unicode转换
-// return: (tex, tokens数量), \alpha -> (\alpha, 1)
-pub fn get_math_tex_many(s: &str, envs: &HashMap<String, bool>) -> (String, usize){
+// 3. 都没有的非ASCII字符 -> 按unmapped输出, 如: € -> \text{€}, unmapped为Error时返回Err
+// 基字符后面的组合字符和符号表中没有的预组合字母按combining处理, 如: x + U+0302 -> \hat{x}
+// used: 记录查符号表时用到的行需要的宏包
+// return: (tex, tokens数量), \alpha -> (\alpha, 1), 基字符和组合字符算一个
+pub fn get_math_tex_many(s: &str, packages: &PackageOrder, mapping: &Mapping, combining: &CombiningConfig, unmapped: Unmapped, used: &mut Packages) -> Result<(String, usize), String>{
     let mut res = String::new();
 
-    let chars = spilt_as_char(s);
-    let num = chars.len();
-    for c in chars {
+    let chars: Vec<char> = s.chars().collect();
+    let mut num = chars.len();
+    let mut text_end = None; // 上一个未映射字符输出的结尾
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
         if c == '\u{fe00}' {
             // -- we ignore 65024 VARIATION SELECTOR 1 to avoid putting it
             //     -- literally in the output ; it is used in mathml output.
@@ -160,65 +173,224 @@ pub fn get_math_tex_many(s: &str, envs: &HashMap<String, bool>) -> (String, usiz
             continue;
         }
 
-        if let Some(tex_cmd) = lookup_tex_cmd_table(&c, envs) {
-            res.push_str(&tex_cmd.val);
-
-            // [Accent, Rad, TOver, TUnder] -> Categories which require braces
-            if tex_cmd.category == "Accent" || tex_cmd.category == "Rad" || tex_cmd.category == "TOver" || tex_cmd.category == "TUnder" {
-                res.push_str("{}");
+        let marks = chars[i..].iter().take_while(|m| is_combining_accent(**m)).count();
+        if marks > 0 {
+            if let Some(tex) = combine_marks(c, &chars[i..i + marks], packages, mapping, combining, unmapped, used)? {
+                res.push_str(&tex);
+                i += marks;
+                num -= marks;
+                continue;
             }
-        }else if let Some(tex_cmd) = look_rev_text_unicode_table(&c) {
-            res.push_str(&tex_cmd);
+        }
+
+        if let Some(tex) = char_to_tex(c, packages, mapping, used) {
+            res.push_str(&tex);
+        }else if let Some(tex) = decompose_letter(c, packages, mapping, combining, unmapped, used)? {
+            res.push_str(&tex);
         }else if let Some(tex_cmd) = escape_latex(c) {
             res.push_str(&tex_cmd);
-        }else {
+        }else if c.is_ascii() {
             res.push(c);
+        }else {
+            let tex = unmapped_to_tex(c, unmapped, used)?;
+            // 连续的\text{}合并: \text{中}\text{文} -> \text{中文}
+            match tex.strip_prefix("\\text{") {
+                Some(rest) if text_end == Some(res.len()) => {
+                    res.pop();
+                    res.push_str(rest);
+                },
+                _ => res.push_str(&tex),
+            }
+            text_end = Some(res.len());
+        }
+    }
+    Ok((res, num))
+}
+
+// 符号表中没有的非ASCII字符, 按unmapped输出, \text需要amsmath
+fn unmapped_to_tex(c: char, unmapped: Unmapped, used: &mut Packages) -> Result<String, String>{
+    match unmapped {
+        Unmapped::Text => {
+            used.insert("amsmath".to_string());
+            Ok(format!("\\text{{{}}}", c))
+        },
+        Unmapped::Command => match text_command(c) {
+            Some((cmd, package)) => {
+                used.insert("amsmath".to_string());
+                used.insert(package.to_string());
+                Ok(format!("\\text{{{}}}", cmd))
+            },
+            None => unmapped_to_tex(c, Unmapped::Char, used),
+        },
+        Unmapped::Char => Ok(format!("\\char\"{:04X}", c as u32)),
+        Unmapped::Error => Err(format!("no math command for U+{:04X} {:?}", c as u32, c)),
+    }
+}
+
+// textcomp/tipa中的文本命令, 数学模式中需要\text{}包裹
+fn text_command(c: char) -> Option<(&'static str, &'static str)>{
+    match c {
+        '\u{20AC}' => Some(("\\texteuro", "textcomp")),
+        '\u{20A9}' => Some(("\\textwon", "textcomp")),
+        '\u{20A6}' => Some(("\\textnaira", "textcomp")),
+        '\u{20B1}' => Some(("\\textpeso", "textcomp")),
+        '\u{20AB}' => Some(("\\textdong", "textcomp")),
+        '\u{20A4}' => Some(("\\textlira", "textcomp")),
+        '\u{A2}' => Some(("\\textcent", "textcomp")),
+        '\u{A4}' => Some(("\\textcurrency", "textcomp")),
+        '\u{B0}' => Some(("\\textdegree", "textcomp")),
+        '\u{2030}' => Some(("\\textperthousand", "textcomp")),
+        '\u{2116}' => Some(("\\textnumero", "textcomp")),
+        '\u{2122}' => Some(("\\texttrademark", "textcomp")),
+        '\u{255}' => Some(("\\textctc", "tipa")),
+        '\u{291}' => Some(("\\textctz", "tipa")),
+        '\u{292}' => Some(("\\textyogh", "tipa")),
+        _ => None,
+    }
+}
+
+// 查用户映射, 符号表和text unicode表, 都没有时返回None
+fn char_to_tex(c: char, packages: &PackageOrder, mapping: &Mapping, used: &mut Packages) -> Option<String>{
+    if let Some(tex_cmd) = mapping.symbol(c) {
+        return Some(tex_cmd.to_string());
+    }
+    if let Some(tex_cmd) = lookup_tex_cmd_table(&c, packages) {
+        used.extend(tex_cmd.packages);
+        // [Accent, Rad, TOver, TUnder] -> Categories which require braces
+        return match tex_cmd.category.as_str() {
+            "Accent" | "Rad" | "TOver" | "TUnder" => Some(tex_cmd.val + "{}"),
+            _ => Some(tex_cmd.val),
+        };
+    }
+    // 样式字母, 如: ℝ -> \mathbb{R}
+    let (tex, package) = REV_TEXT_UNICODE_TABLE.get(c.to_string().as_str())?;
+    used.extend(package.map(str::to_string));
+    Some(tex.to_string())
+}
+
+// 可以和前面的基字符组合的字符: U+0338(否定)和DIACRITICALS_TABLE中的组合重音
+pub fn is_combining_accent(c: char) -> bool{
+    c == '\u{338}' || (is_combining_mark(c) && accent_command(c).is_some())
+}
+
+// 组合重音对应的命令, 基字符只有一个字符, 使用窄的版本: \widehat -> \hat
+fn accent_command(c: char) -> Option<String>{
+    let cmd = get_diacriticals(&c.to_string())?;
+    match cmd.as_str() {
+        "\\widehat" => Some("\\hat".to_string()),
+        "\\widetilde" => Some("\\tilde".to_string()),
+        _ if is_unavailable_diacritical(&cmd) => None,
+        _ => Some(cmd),
+    }
+}
+
+// 基字符 + 组合字符:
+// U+0338: precomposed时先找预组合字符(= + U+0338 -> ≠ -> \neq), 没有时和not一样用\not=
+// 其他组合重音由内向外包裹: x + U+0302 -> \hat{x}, accents为false时返回None, 逐个字符查表
+fn combine_marks(base: char, marks: &[char], packages: &PackageOrder, mapping: &Mapping, combining: &CombiningConfig, unmapped: Unmapped, used: &mut Packages) -> Result<Option<String>, String>{
+    let accents: Vec<char> = marks.iter().copied().filter(|m| *m != '\u{338}').collect();
+    if !accents.is_empty() && !combining.accents {
+        return Ok(None);
+    }
+
+    let mut tex = match marks.contains(&'\u{338}') {
+        true => negate(base, packages, mapping, combining.negation, unmapped, used)?,
+        false => char_to_tex_or_literal(base, packages, mapping, unmapped, used)?,
+    };
+    for m in accents {
+        let cmd = match accent_command(m) {
+            Some(cmd) => cmd,
+            None => return Ok(None),
+        };
+        used.extend(command_package(&cmd).map(str::to_string));
+        tex = format!("{}{{{}}}", cmd, tex);
+    }
+    Ok(Some(tex))
+}
+
+fn negate(base: char, packages: &PackageOrder, mapping: &Mapping, negation: Negation, unmapped: Unmapped, used: &mut Packages) -> Result<String, String>{
+    if negation == Negation::Precomposed {
+        if let Some(tex) = compose(base, '\u{338}').and_then(|c| char_to_tex(c, packages, mapping, used)) {
+            return Ok(tex);
         }
+    }
+    let tex = char_to_tex_or_literal(base, packages, mapping, unmapped, used)?;
+    Ok(match tex.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        true => format!("\\not {}", tex),
+        false => format!("\\not{}", tex),
+    })
+}
+
+// 符号表中没有的预组合字母按NFD分解后组合: é -> e + U+0301 -> \acute{e}
+fn decompose_letter(c: char, packages: &PackageOrder, mapping: &Mapping, combining: &CombiningConfig, unmapped: Unmapped, used: &mut Packages) -> Result<Option<String>, String>{
+    if !combining.decompose {
+        return Ok(None);
+    }
+    let mut chars = Vec::new();
+    decompose_canonical(c, |d| chars.push(d));
+    if chars.len() < 2 || !chars[1..].iter().all(|m| is_combining_accent(*m)) {
+        return Ok(None);
+    }
+    combine_marks(chars[0], &chars[1..], packages, mapping, combining, unmapped, used)
+}
 
+// 组合字符的基字符, 和单独的字符一样, 符号表中没有的非ASCII字符按unmapped输出
+fn char_to_tex_or_literal(c: char, packages: &PackageOrder, mapping: &Mapping, unmapped: Unmapped, used: &mut Packages) -> Result<String, String>{
+    if let Some(tex) = char_to_tex(c, packages, mapping, used).or_else(|| escape_latex(c)) {
+        return Ok(tex);
+    }
+    match c.is_ascii() {
+        true => Ok(c.to_string()),
+        false => unmapped_to_tex(c, unmapped, used),
     }
-    (res, num)
 }
 
 #[test]
 fn test_lookup_tex_cmd_table(){
-    let mut envs = HashMap::new();
+    let mut envs = Envs::new();
     envs.insert("amsmath".to_string(), true);
     envs.insert("amssymb".to_string(), true);
-    assert_eq!(lookup_tex_cmd_table(&'∔', &envs), Some(TexCmdVal{
+    assert_eq!(lookup_tex_cmd_table(&'∔', &enabled_packages(&envs)), Some(TexCmdVal{
         category: "Bin".to_string(),
         val: "\\dotplus".to_string(),
+        packages: vec!["amssymb".to_string()],
     }));
+
+    // 按envs的顺序选择宏包, 值为false的宏包不可用
+    envs.insert("unicode-math".to_string(), true);
+    assert_eq!(lookup_tex_cmd_table(&'\u{21ba}', &enabled_packages(&envs)).unwrap().val, "\\circlearrowleft");
+    envs.shift_remove("amssymb");
+    envs.insert("amssymb".to_string(), true);
+    assert_eq!(lookup_tex_cmd_table(&'\u{21ba}', &enabled_packages(&envs)).unwrap().val, "\\acwopencirclearrow");
+    envs.insert("unicode-math".to_string(), false);
+    assert_eq!(lookup_tex_cmd_table(&'\u{21ba}', &enabled_packages(&envs)).unwrap().val, "\\circlearrowleft");
 }
 
-// 查表, 转换unicode码点为tex命令
+// 查表, 转换unicode码点为tex命令, packages为enabled_packages(envs)
 // \120432 -> \mathtt{A}; env = base
-fn lookup_tex_cmd_table(c: &char, envs: &HashMap<String, bool>) -> Option<TexCmdVal>{
-    // try base symbol
-    if let Some(base) = TEX_TABLE.get(("base_".to_string() + c.to_string().as_str()).as_str()) {
-        let res = TexCmdVal{
-            category: base.category.to_string(),
-            val: base.val.to_string(),
-        };
-        return Some(res);
-    }else{
-        // try other envs
-        for (env, _) in envs {
-            if let Some(base) = TEX_TABLE.get((env.to_string() + "_" + c.to_string().as_str()).as_str()) {
-                let res = TexCmdVal{
-                    category: base.category.to_string(),
-                    val: base.val.to_string(),
-                };
-                return Some(res);
-            }
-        }
+// 多个宏包都提供时按packages的顺序选择, 见Symbol::priority
+fn lookup_tex_cmd_table(c: &char, packages: &PackageOrder) -> Option<TexCmdVal>{
+    symbol_db().resolve(*c, packages).map(TexCmdVal::from)
+}
+
+// envs中可用的宏包, 保持envs的顺序, 同一次写出的所有查询共用
+pub fn enabled_packages(envs: &Envs) -> PackageOrder{
+    let names: Vec<String> = envs.iter().filter(|(_, on)| **on).map(|(name, _)| name.clone()).collect();
+    PackageOrder::new(&names)
+}
+
+// 符号表加载失败时panic, 与其他表一致
+fn symbol_db() -> &'static SymbolDb{
+    match SymbolDb::global() {
+        Ok(db) => db,
+        Err(e) => panic!("read records err for tex_cmd_table.csv: {}", e),
     }
-    None
 }
 
 pub fn is_mathop_base(e: &Exp) -> bool{
     match e{
         Exp::ESymbol(node::TeXSymbolType::Op, s) => {
-            if let Some(res) = lookup_tex_cmd_base_with_not_escape(s) {
+            if let Some(res) = lookup_tex_cmd_base(s) {
                 if res.category == "Op" {
                     return true;
                 }
@@ -232,34 +404,20 @@ pub fn is_mathop_base(e: &Exp) -> bool{
 }
 
 #[test]
-fn test_lookup_tex_cmd_base_with_not_escape(){
-    let s = "\\8722";
-    let res = lookup_tex_cmd_base_with_not_escape(s);
+fn test_lookup_tex_cmd_base(){
+    let s = "\u{2212}";
+    let res = lookup_tex_cmd_base(s);
     assert_eq!(res, Some(TexCmdVal{
         category: "Bin".to_string(),
         val: "-".to_string(),
+        packages: vec![],
     }));
 }
 
-fn lookup_tex_cmd_base_with_not_escape(s: &str) -> Option<TexCmdVal> {
-    let escaped;
-    if s.starts_with("\\"){
-        escaped = parse_as_unicode_char(s).unwrap();
-    }else{
-        if s.len() == 0{
-            return None;
-        }
-        escaped = s.chars().next().unwrap();
-    };
-
-    if let Some(base) = TEX_TABLE.get(("base_".to_string() + escaped.to_string().as_str()).as_str()) {
-        let res = TexCmdVal{
-            category: base.category.to_string(),
-            val: base.val.to_string(),
-        };
-        return Some(res);
-    }
-    None
+// 查base表, 以s的第一个字符为准
+fn lookup_tex_cmd_base(s: &str) -> Option<TexCmdVal> {
+    let c = s.chars().next()?;
+    symbol_db().by_code_point(c).into_iter().find(|s| s.package == "base").map(TexCmdVal::from)
 }
 
 #[test]
@@ -267,9 +425,31 @@ fn test_look_rev_text_unicode_table(){
     let case = parse_as_unicode_char("\\8488").unwrap();
     let res = look_rev_text_unicode_table(&case);
     assert_eq!(res, Some("\\mathfrak{Z}".to_string()));
+    // 表的第一行
+    assert_eq!(look_rev_text_unicode_table(&'\u{2102}'), Some("\\mathbb{C}".to_string()));
 }
 fn look_rev_text_unicode_table(unicode: &char) -> Option<String>{
-    REV_TEXT_UNICODE_TABLE.get(unicode.to_string().as_str()).map(|v| v.to_string())
+    REV_TEXT_UNICODE_TABLE.get(unicode.to_string().as_str()).map(|(tex, _)| tex.to_string())
+}
+
+// 样式字母对应的命令, 如: ℝ -> \mathbb{R}
+pub fn lookup_styled_command(c: char) -> Option<String>{
+    look_rev_text_unicode_table(&c)
+}
+
+#[test]
+fn test_lookup_text_unicode(){
+    assert_eq!(lookup_text_unicode(&node::TextType::TextDoubleStruck, 'R'), Some('\u{211D}'));
+    assert_eq!(lookup_text_unicode(&node::TextType::TextBold, '\u{3b1}'), Some('\u{1d6c2}'));
+    // 表的第一行, 不能被当作表头
+    assert_eq!(lookup_text_unicode(&node::TextType::TextDoubleStruck, 'C'), Some('\u{2102}'));
+    assert_eq!(lookup_text_unicode(&node::TextType::TextNormal, 'C'), None);
+}
+
+// 查TEXT_UNICODE_TABLE, 把字符转换为对应样式的数学字母:
+// TextDoubleStruck + C -> ℂ
+pub fn lookup_text_unicode(t: &node::TextType, c: char) -> Option<char>{
+    TEXT_UNICODE_TABLE.get(format!("{:?}_{}", t, c).as_str()).copied()
 }
 
 fn str_to_text_type(s: &str) -> node::TextType{
@@ -328,85 +508,58 @@ fn text_type_cmd(t: &node::TextType) -> String{
     }.to_string()
 }
 
-#[derive(PartialEq, Debug)]
-struct TexCmdKey{
-    env: String,
-    c: char,
-}
 #[derive(PartialEq, Debug)]
 struct TexCmdVal{
     pub category: String,
     pub val: String,
+    pub packages: Vec<String>,
 }
 
-lazy_static! {
-    static ref TEX_TABLE: HashMap<&'static str, &'static TexCmdVal, BuildHasherDefault<AHasher>> = {
-        let prefix = config::get_config().table_dir.as_str();
-        let path = prefix.to_string() + "/tex_cmd_table.csv";
-        let mut key_vals = csv::Reader::from_path(path).expect("read records err for tex_cmd_table.csv");
-
-        let mut m :HashMap<&'static str, &'static TexCmdVal, BuildHasherDefault<AHasher>> = HashMap::with_hasher(BuildHasherDefault::<AHasher>::default());
-        for result in key_vals.records() {
-            let record = result.expect("Could not read record");
-            let unicode_str = record.get(1).expect("Missing unicode");
-            let unicode;
-            if unicode_str.len() != 1 {
-                // \开头的unicode码点, 转换为char
-                let c = parse_as_unicode_char(unicode_str).expect("parse unicode err");
-                unicode = c;
-            }else{
-                // 非\开头的unicode码点, 直接转换为char
-                unicode = unicode_str.chars().next().expect("parse unicode err");
-            }
-
-            // dbg!(unicode.clone());
-
-            // env_c -> tex命令
-            let key = Box::leak(Box::new(
-                format!("{}_{}", record.get(0).expect("Missing env"), unicode)
-            ));
-            let val = Box::leak(Box::new(TexCmdVal{
-                category: record.get(2).expect("Missing category").to_string(),
-                val: record.get(3).expect("Missing val").to_string(),
-            }));
-
-            // ? TIPS: tex_cmd_table中有些字符顺序是反的, 所以只添加第一个
-            if m.contains_key(key.as_str()) {
-                continue;
-            }
-            m.insert(key, val);
+impl From<&Symbol> for TexCmdVal {
+    fn from(s: &Symbol) -> Self {
+        TexCmdVal{
+            category: format!("{:?}", s.symbol_type),
+            val: s.command.clone(),
+            packages: s.required_packages().into_iter().map(|p| p.to_string()).collect(),
         }
-        m
-    };
+    }
+}
 
-    // text type + text -> unicode
-    static ref TEXT_UNICODE_TABLE: HashMap<&'static str, &'static str, BuildHasherDefault<AHasher>> = {
+lazy_static! {
+    // text type + 字符 -> 对应样式的字符
+    // 表中非ascii字符以\+十进制码点存储, 加载时转换为字符
+    static ref TEXT_UNICODE_TABLE: HashMap<&'static str, char, BuildHasherDefault<AHasher>> = {
         let prefix = config::get_config().table_dir.as_str();
         let path = prefix.to_string() + "/text_unicode_table.csv";
-        let mut reader = csv::Reader::from_path(path).expect("read records err for text_unicode_table.csv");
-        let mut m :HashMap<&'static str, &'static str, BuildHasherDefault<AHasher>> = HashMap::with_hasher(BuildHasherDefault::<AHasher>::default());
+        // 表没有表头, 第一行(TextDoubleStruck C -> ℂ)也是数据
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).from_path(path).expect("read records err for text_unicode_table.csv");
+        let mut m :HashMap<&'static str, char, BuildHasherDefault<AHasher>> = HashMap::with_hasher(BuildHasherDefault::<AHasher>::default());
         for result in reader.records() {
             let record = result.expect("Could not read record");
             let text_type_str = record.get(0).expect("Missing text_type");
 
             let text = record.get(1).expect("Missing text");
-            let unicode = record.get(2).expect("Missing Unicode");
+            let text = match text.starts_with("\\") {
+                true => parse_as_unicode_char(text).expect("parse unicode err"),
+                false => text.chars().next().expect("Missing text"),
+            };
+            let unicode = parse_as_unicode_char(record.get(2).expect("Missing Unicode")).expect("parse unicode err");
 
             let key = Box::leak(Box::new(format!("{}_{}", text_type_str, text)));
-            let val = Box::leak(Box::new(unicode.to_string()));
-            m.insert(key, val);
+            m.insert(key, unicode);
 
         }
         m
     };
 
-    // unicode码点对应的命令表, 如果相同则以最后一个为准
-    // 如: \u{xxxx} -> \mathbb{A}
-    static ref REV_TEXT_UNICODE_TABLE: HashMap<&'static str, &'static str, BuildHasherDefault<AHasher>> = {
+    // unicode码点对应的命令表和命令需要的宏包, 如果相同则以最后一个为准
+    // 如: \u{xxxx} -> (\mathbb{A}, amssymb)
+    static ref REV_TEXT_UNICODE_TABLE: HashMap<&'static str, (&'static str, Option<&'static str>), BuildHasherDefault<AHasher>> = {
         let prefix = config::get_config().table_dir.as_str();
         let path = prefix.to_string() + "/text_unicode_table.csv";
-        let mut reader = csv::Reader::from_path(path).expect("read records err for text_unicode_table.csv");
-        let mut m :HashMap<&'static str, &'static str, BuildHasherDefault<AHasher>> = HashMap::with_hasher(BuildHasherDefault::<AHasher>::default());
+        // 表没有表头, 第一行(TextDoubleStruck C -> ℂ)也是数据
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).from_path(path).expect("read records err for text_unicode_table.csv");
+        let mut m :HashMap<&'static str, (&'static str, Option<&'static str>), BuildHasherDefault<AHasher>> = HashMap::with_hasher(BuildHasherDefault::<AHasher>::default());
         for result in reader.records() {
             let record = result.expect("Could not read record");
             let text_type_str = record.get(0).expect("Missing text_type");
@@ -426,55 +579,65 @@ lazy_static! {
             let origin_unicode = record.get(2).expect("Missing Unicode");
             let parsed_unicode = parse_as_unicode_char(origin_unicode).expect("parse unicode err");
 
+            let package = command_package(&text_cmd);
             let val = Box::leak(Box::new(text_cmd + "{" + &unicode_parsed_text + "}"));
 
             let key = Box::leak(Box::new(parsed_unicode.to_string()));
-            m.insert(key, val);
+            m.insert(key, (val.as_str(), package));
 
             // println!("key: {:?}, val: {:?}", key, val);
         }
         m
     };
 }
+
+// 符号表是否已加载, 用于server的/readyz
+// 表文件不存在时直接返回false, 否则触发加载, 加载失败(panic)时返回false
+pub fn tables_loaded() -> bool {
+    let prefix = config::get_config().table_dir.as_str();
+    for name in ["tex_cmd_table.csv", "text_unicode_table.csv"] {
+        if !std::path::Path::new(prefix).join(name).exists() {
+            return false;
+        }
+    }
+    std::panic::catch_unwind(|| {
+        SymbolDb::global().is_ok() && !TEXT_UNICODE_TABLE.is_empty() && !REV_TEXT_UNICODE_TABLE.is_empty()
+    }).unwrap_or(false)
+}
+
+// 符号表中出现的宏包名, 不包括base和"mathabx -amssymb"这类组合条件
+// 符号表未加载时返回空
+pub fn package_names() -> Vec<String> {
+    if !tables_loaded() {
+        return Vec::new();
+    }
+    SymbolDb::global().map(|db| db.packages()).unwrap_or_default()
+}
+
 #[test]
 fn test_is_delimiters(){
-    let mut envs = HashMap::new();
+    let mut envs = Envs::new();
     envs.insert("amsmath".to_string(), true);
     envs.insert("amssymb".to_string(), true);
+    let packages = enabled_packages(&envs);
     let s = "\u{27e8}";
-    let res = is_delimiters(&s, &envs);
+    let res = is_delimiters(&s, &packages);
     assert_eq!(res, true);
 
     let s = "|";
-    let res = is_delimiters(&s, &envs);
+    let res = is_delimiters(&s, &packages);
     assert_eq!(res, true);
+
+    assert!(!is_delimiters("\\arrowvert", &packages));
+    assert!(!is_delimiters("", &packages));
 }
 
-pub fn is_delimiters(s: &str, envs: &HashMap<String, bool>) -> bool{
-    if s.len() == 0 {
-        return false;
-    }
-    let mut c = s.chars().next().unwrap();
-    if s.len() > 1 && s.starts_with("\\") {
-        // 可能是unicode码点
-        if let Some(unicode) = parse_as_unicode_char(s) {
-            c = unicode;
-        }else{
-            // \arrowvert 这样的命令
-            return false;
-        }
-    }
-    // TODO: 对envs的每个环境都生成一个列表, 再判断s是否在列表中, 这里直接查Open, Close可行吗?
-    let base_cmds = vec!['.', '(', ')', '[', ']', '|', '\u{2016}', '{', '}'
-                         , '\u{2309}', '\u{2308}', '\u{2329}', '\u{232A}'
-                         , '\u{230B}', '\u{230A}', '\u{231C}', '\u{231D}'];
-    // 这里仅仅判断了最基本的情况
-    if base_cmds.contains(&c){
-        return true;
-    }else if let Some(cmd) = lookup_tex_cmd_table(&c, envs) {
-        if cmd.category == "Open" || cmd.category == "Close" {
-            return true;
-        }
+// 是否可以作为\left, \right的定界符, \arrowvert这样的命令不是, 见SymbolDb::is_delimiter
+pub fn is_delimiters(s: &str, packages: &PackageOrder) -> bool{
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some('\\'), Some(_)) => false,
+        (Some(c), _) => symbol_db().is_delimiter(c, packages),
+        (None, _) => false,
     }
-    return false;
 }
@@ -1,13 +1,18 @@
 use core::panic;
-use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
+use std::sync::Arc;
 use crate::ast::tex_unicode::{escapse_text, get_math_tex_many};
-use crate::pretty_print_hex;
 use super::tex_unicode::escaped_text_md;
-use super::{judge, shared, tex_unicode};
+use super::{judge, mapping, shared, tex_pretty, tex_unicode};
+use super::mapping::Mapping;
+use super::symbol_db::PackageOrder;
+use crate::config::{self, CombiningConfig, ScaledFallback, Unmapped};
+#[cfg(test)]
+use super::shared::pretty_print_hex;
 use super::shared::{is_fancy, is_mathoperator, FenceType, Position};
-use super::node::{Alignment, ArrayLines, Exp, FractionType, InEDelimited, TeXSymbolType, TextType};
+use super::node::{self, Alignment, ArrayLines, Exp, FractionType, InEDelimited, TeXSymbolType, TextType};
+use super::writer::{Envs, Packages, Writer, WriterOptions, WriterOutput, WriterWarning};
 
 // Tex
 // #[derive(Debug, PartfialEq)]
@@ -21,13 +26,22 @@ use super::node::{Alignment, ArrayLines, Exp, FractionType, InEDelimited, TeXSym
 
 pub struct TexWriterContext {
     tex: String, // 输出的文本
-    envs: HashMap<String, bool>,
+    envs: Envs,
+    enabled: PackageOrder, // envs中启用的宏包和优先级, 开始写出时由envs生成, 查符号表时使用
     need_space: bool, // 对于\alpha\beta这种, 如果遇到字母, 需要输出空格: \alphax\beta -> \alpha x\beta
     convertible: bool, // 是否可转换
+    mapping: Arc<Mapping>, // 用户自定义的符号和宏映射
+    packages: Packages, // 输出中用到的宏包
+    combining: CombiningConfig, // 组合字符的处理方式
+    unmapped: Unmapped, // 符号表中没有的字符的处理方式
+    scaled: ScaledFallback, // 不能用\big..\Bigg表示的EScaled的处理方式
+    warnings: Vec<WriterWarning>, // 没有如实转换的节点
+    path: Vec<(usize, &'static str)>, // 正在写出的节点路径: (在父节点Exp::children()中的下标, 节点名)
+    detached: usize, // 大于0时正在写出合成的节点, 其中的节点不加入path
 }
 
 pub fn default_context() -> TexWriterContext {
-    let mut envs = HashMap::new();
+    let mut envs = Envs::new();
     envs.insert("amsmath".to_string(), true);
     envs.insert("amssymb".to_string(), true);
     envs.insert("mathbb".to_string(), true);
@@ -35,12 +49,51 @@ pub fn default_context() -> TexWriterContext {
     envs.insert("fourier".to_string(), true);
     TexWriterContext {
         tex: String::new(),
+        enabled: tex_unicode::enabled_packages(&envs),
         envs,
         need_space: false,
         convertible: false,
+        mapping: mapping::empty(),
+        packages: Packages::new(),
+        combining: config::get_config().convert.combining.clone(),
+        unmapped: config::get_config().convert.unmapped,
+        scaled: config::get_config().convert.scaled,
+        warnings: Vec::new(),
+        path: Vec::new(),
+        detached: 0,
     }
 }
 
+#[test]
+fn test_write_with_mapping(){
+    let mut m = Mapping::default();
+    m.add_symbol("U+2264", "\\leqslant").unwrap();
+    m.add_exp(r#"EStyled TextDoubleStruck [EIdentifier "R"]"#, "\\R").unwrap();
+    let mut twc = default_context();
+    twc.mapping = Arc::new(m);
+    let exps = vec![
+        Exp::EIdentifier("x".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "\u{2264}".into()),
+        Exp::EStyled(TextType::TextDoubleStruck, vec![Exp::EIdentifier("R".into())]),
+        Exp::EStyled(TextType::TextDoubleStruck, vec![Exp::EIdentifier("N".into())]),
+    ];
+    assert_eq!(write_tex_inline(exps, &mut twc).unwrap(), "x \\leqslant \\R\\mathbb{N}");
+}
+
+// 按WriterOptions创建上下文: envs, extra中选择的映射表, 未映射字符和EScaled的处理方式
+fn context_with_opts(opts: &WriterOptions) -> Result<TexWriterContext, String> {
+    let mut twc = default_context();
+    twc.envs = opts.envs.clone();
+    twc.mapping = mapping::select(&opts.extra)?;
+    if let Some(unmapped) = opts.extra.get("unmapped") {
+        twc.unmapped = Unmapped::parse(unmapped)?;
+    }
+    if let Some(scaled) = opts.extra.get("scaled") {
+        twc.scaled = ScaledFallback::parse(scaled)?;
+    }
+    Ok(twc)
+}
+
 impl TexWriterContext {
     // 硬性添加空格, 会检查是否需要添加空格
     fn push_space(&mut self){
@@ -58,6 +111,48 @@ impl TexWriterContext {
 
         self.tex.push(' ');
     }
+    // 写出的结果和用到的宏包
+    fn into_output(self, text: String) -> WriterOutput{
+        WriterOutput { text, packages: self.packages.into_iter().collect(), warnings: self.warnings }
+    }
+    // 开始写出输入的表达式列表: 由envs生成查符号表用的宏包优先级, 清空节点路径
+    fn begin(&mut self){
+        self.enabled = tex_unicode::enabled_packages(&self.envs);
+        self.path.clear();
+        self.detached = 0;
+    }
+    // 进入节点, index为节点在父节点Exp::children()中的下标(最外层为输入列表中的下标), 返回是否加入了path
+    fn enter(&mut self, index: usize, exp: &Exp) -> bool{
+        if self.detached > 0 {
+            return false;
+        }
+        self.path.push((index, exp.name()));
+        true
+    }
+    fn leave(&mut self, entered: bool){
+        if entered {
+            self.path.pop();
+        }
+    }
+    // 记录一个警告, 路径为当前正在写出的节点
+    fn warn(&mut self, kind: &'static str, lost: &str, emitted: &str){
+        let path: Vec<String> = self.path.iter().map(|(i, name)| format!("{}:{}", i, name)).collect();
+        self.warnings.push(WriterWarning {
+            path: path.join("/"),
+            kind,
+            lost: lost.to_string(),
+            emitted: emitted.to_string(),
+        });
+    }
+    // 宏包是否可用, mathtools会加载amsmath
+    fn has_package(&self, name: &str) -> bool{
+        let enabled = |p: &str| self.envs.get(p) == Some(&true);
+        enabled(name) || (name == "amsmath" && enabled("mathtools"))
+    }
+    // 字符串转换为tex, 记录用到的符号表行的宏包, unmapped为Error时遇到未映射字符返回Err
+    fn escape(&mut self, s: &str) -> Result<String, String>{
+        shared::escape_text_as_tex(s, &self.enabled, &self.mapping, &self.combining, self.unmapped, &mut self.packages)
+    }
     // 添加文本, 不会考虑是否需要添加空格
     fn push_raw(&mut self, s: &str){
         self.tex.push_str(s);
@@ -96,6 +191,14 @@ impl TexWriterContext {
             self.need_space = false;
         }
     }
+    // 添加以命令开头的文本, 如: \boxed, \mspace{7mu}, 记录命令需要的宏包(见shared::command_package)
+    fn push_command(&mut self, s: &str) {
+        let end = 1 + s[1..].bytes().take_while(|b| b.is_ascii_alphabetic()).count();
+        if let Some(p) = shared::command_package(&s[..end]) {
+            self.packages.insert(p.to_string());
+        }
+        self.push_text(s);
+    }
 }
 #[test]
 fn test_tex_write_file_lines(){
@@ -151,7 +254,7 @@ fn test_text_writer_file(){
 
     let exp = super::ast_reader::read_ast(&native).unwrap();
     dbg!(exp.clone());
-    let mut envs = HashMap::new();
+    let mut envs = Envs::new();
     envs.insert("amsmath".to_string(), true);
     envs.insert("amssymb".to_string(), true);
     envs.insert("mathbb".to_string(), true);
@@ -200,15 +303,16 @@ fn test_text_writer_file(){
     f.write("\n".as_bytes()).unwrap();
 }
 // 把Exp转换为TeX, 带上环境
-pub fn write_tex_with_env(exps: Vec<Exp>, envs: &HashMap<String, bool>) -> Result<String, String>{
-    let twc = &mut TexWriterContext {
-        tex: String::new(),
-        need_space: false,
-        envs: envs.clone(),
-        convertible: false,
-    };
-    for exp in &exps {
-        write_exp(twc, exp)?;
+pub fn write_tex_with_env(exps: Vec<Exp>, envs: &Envs) -> Result<String, String>{
+    let mut twc = default_context();
+    twc.envs = envs.clone();
+    write_tex_inline(exps, &mut twc)
+}
+
+fn write_tex_inline(exps: Vec<Exp>, twc: &mut TexWriterContext) -> Result<String, String>{
+    twc.begin();
+    for (i, exp) in exps.iter().enumerate() {
+        write_exp(twc, i, exp)?;
     }
     Ok(twc.tex.clone())
 }
@@ -216,45 +320,45 @@ pub fn write_tex_with_env(exps: Vec<Exp>, envs: &HashMap<String, bool>) -> Resul
 #[test]
 fn test_write_tex_default(){
     let exps = vec![
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
-        Exp::EText(TextType::TextNormal, "hello".to_string()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
+        Exp::EText(TextType::TextNormal, "hello".into()),
     ];
 
     let res = write_tex_default(exps).unwrap();
     println!("res: {:?}", res);
 
     let exps = vec![
-        Exp::EText(TextType::TextNormal, "hello".to_string()),
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
+        Exp::EText(TextType::TextNormal, "hello".into()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
     ];
 
     let res = write_tex_default(exps).unwrap();
     println!("res: {:?}", res);
 
     let exps = vec![
-        Exp::EText(TextType::TextNormal, "hello".to_string()),
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
-        Exp::EText(TextType::TextNormal, "world".to_string()),
+        Exp::EText(TextType::TextNormal, "hello".into()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
+        Exp::EText(TextType::TextNormal, "world".into()),
     ];
 
     let res = write_tex_default(exps).unwrap();
     println!("res: {:?}", res);
 
     let exps = vec![
-        Exp::EText(TextType::TextNormal, "hello".to_string()),
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
-        Exp::EText(TextType::TextNormal, "world".to_string()),
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
+        Exp::EText(TextType::TextNormal, "hello".into()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
+        Exp::EText(TextType::TextNormal, "world".into()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
     ];
 
     let res = write_tex_default(exps).unwrap();
@@ -263,84 +367,104 @@ fn test_write_tex_default(){
 
 fn write_tex_default(exps: Vec<Exp>) -> Result<String, String>{
     let mut twc = default_context();
-    for exp in &exps {
-        write_exp(&mut twc, exp)?;
+    twc.begin();
+    for (i, exp) in exps.iter().enumerate() {
+        write_exp(&mut twc, i, exp)?;
     }
     Ok(twc.tex.clone())
 }
-pub fn write_tex_equation(exps: Vec<Exp>) -> Result<String, String>{
+// 独立公式, 用open, close包裹, 如: \[ \], $$ $$
+pub fn write_tex_display(exps: Vec<Exp>, envs: &Envs, open: &str, close: &str) -> Result<String, String>{
     let mut twc = default_context();
-    twc.push_raw("\\[");
-    for exp in &exps {
-        write_exp(&mut twc, exp)?;
+    twc.envs = envs.clone();
+    write_tex_display_with(exps, &mut twc, open, close)
+}
+
+fn write_tex_display_with(exps: Vec<Exp>, twc: &mut TexWriterContext, open: &str, close: &str) -> Result<String, String>{
+    twc.push_raw(open);
+    twc.begin();
+    for (i, exp) in exps.iter().enumerate() {
+        write_exp(twc, i, exp)?;
     }
-    twc.push_raw("\\]");
+    twc.push_raw(close);
     Ok(twc.tex.clone())
 }
 
+// 多行美化输出, 与write_tex_with_env的结果token一致
+pub fn write_tex_pretty(exps: Vec<Exp>, envs: &Envs, opts: &tex_pretty::PrettyOptions) -> Result<String, String>{
+    let tex = write_tex_with_env(exps, envs)?;
+    Ok(tex_pretty::pretty_print(&tex, opts))
+}
+
 
 #[test]
 fn test_write_tex_with_md(){
-    let envs = HashMap::new();
+    let envs = Envs::new();
 
     // hello f(x) = \sqrt{x} world
     // [EIdentifier "N",ESymbol Rel "=",ESymbol Ord "\8709"]
     let exps = vec![
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
-        Exp::EText(TextType::TextNormal, "hello".to_string()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
+        Exp::EText(TextType::TextNormal, "hello".into()),
     ];
 
-    let res = write_tex_with_md(exps, &envs).unwrap();
+    let res = write_tex_with_md(exps, &envs, "\\(", "\\)").unwrap();
     println!("res: {:?}", res);
 
     let exps = vec![
-        Exp::EText(TextType::TextNormal, "hello".to_string()),
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
+        Exp::EText(TextType::TextNormal, "hello".into()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
     ];
 
-    let res = write_tex_with_md(exps, &envs).unwrap();
+    let res = write_tex_with_md(exps, &envs, "\\(", "\\)").unwrap();
     println!("res: {:?}", res);
 
     let exps = vec![
-        Exp::EText(TextType::TextNormal, "hello".to_string()),
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
-        Exp::EText(TextType::TextNormal, "world".to_string()),
+        Exp::EText(TextType::TextNormal, "hello".into()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
+        Exp::EText(TextType::TextNormal, "world".into()),
     ];
 
-    let res = write_tex_with_md(exps, &envs).unwrap();
+    let res = write_tex_with_md(exps, &envs, "\\(", "\\)").unwrap();
     println!("res: {:?}", res);
 
     let exps = vec![
-        Exp::EText(TextType::TextNormal, "hello".to_string()),
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
-        Exp::EText(TextType::TextNormal, "world".to_string()),
-        Exp::EIdentifier("N".to_string()),
-        Exp::ESymbol(TeXSymbolType::Rel, "=".to_string()),
-        Exp::ESymbol(TeXSymbolType::Ord, "\\8709".to_string()),
+        Exp::EText(TextType::TextNormal, "hello".into()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
+        Exp::EText(TextType::TextNormal, "world".into()),
+        Exp::EIdentifier("N".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{2205}".into()),
     ];
 
-    let res = write_tex_with_md(exps, &envs).unwrap();
+    let res = write_tex_with_md(exps, &envs, "\\(", "\\)").unwrap();
     println!("res: {:?}", res);
 
     let exps = vec![
-        Exp::EText(TextType::TextNormal, "as\\${}sfa*#% ".to_string()),
+        Exp::EText(TextType::TextNormal, "as\\${}sfa*#% ".into()),
     ];
 
-    let res = write_tex_with_md(exps, &envs).unwrap();
+    let res = write_tex_with_md(exps, &envs, "\\(", "\\)").unwrap();
     println!("res: {:?}", res);
 }
 
-pub fn write_tex_with_md(exps: Vec<Exp>, envs: &HashMap<String, bool>) -> Result<String, String>{
+// 行内公式用open, close包裹, 如: \( \), $ $
+pub fn write_tex_with_md(exps: Vec<Exp>, envs: &Envs, open: &str, close: &str) -> Result<String, String>{
     let mut twc = default_context();
     twc.envs = envs.clone();
+    write_tex_md_with(exps, &mut twc, open, close)
+}
+
+fn write_tex_md_with(exps: Vec<Exp>, twc: &mut TexWriterContext, open: &str, close: &str) -> Result<String, String>{
+    twc.begin();
     if exps.len() == 1{
         return match exps[0] {
             Exp::EText(TextType::TextNormal, ref s) => {
@@ -348,43 +472,323 @@ pub fn write_tex_with_md(exps: Vec<Exp>, envs: &HashMap<String, bool>) -> Result
                 Ok(twc.tex.clone())
             },
             _ => {
-                // \\( \\)包裹
-                twc.push_raw(" \\( ");
-                write_exp(&mut twc, &exps[0])?;
-                twc.push_raw(" \\) ");
+                // open, close包裹
+                twc.push_raw(&format!(" {} ", open));
+                write_exp(twc, 0, &exps[0])?;
+                twc.push_raw(&format!(" {} ", close));
                 Ok(twc.tex.clone())
             }
         }
     }
 
     let mut in_exp = false;
-    for exp in &exps {
+    for (i, exp) in exps.iter().enumerate() {
         // EText直接输出
         match exp {
             Exp::EText(TextType::TextNormal, s) => {
                 if in_exp {
-                    twc.push_raw("\\) ");
+                    twc.push_raw(&format!("{} ", close));
                     in_exp = false;
                 }
                 twc.push_text(&escaped_text_md(s));
             },
             _ => {
                 if !in_exp {
-                    twc.push_raw(" \\(");
+                    twc.push_raw(&format!(" {}", open));
                     in_exp = true;
                 }
-                write_exp(&mut twc, exp)?;
+                write_exp(twc, i, exp)?;
             }
         }
     }
 
     if in_exp {
-        twc.push_raw("\\) ");
+        twc.push_raw(&format!("{} ", close));
     }
 
     Ok(twc.tex.clone().trim().to_string())
 }
 
+#[test]
+fn test_write_output_packages(){
+    let opts = WriterOptions::default();
+    let exps = vec![
+        Exp::EBoxed(Box::new(Exp::EIdentifier("x".into()))),
+        Exp::ESymbol(TeXSymbolType::Bin, "\u{2214}".into()),
+        Exp::EStyled(TextType::TextDoubleStruck, vec![Exp::EIdentifier("R".into())]),
+    ];
+    let output = TexWriter.write_output(exps, &opts).unwrap();
+    assert_eq!(output.text, "\\boxed{x} \\dotplus \\mathbb{R}");
+    assert_eq!(output.packages, vec!["amsmath", "amssymb"]);
+
+    let exps = vec![Exp::ESymbol(TeXSymbolType::Bin, "+".into()), Exp::EIdentifier("x".into())];
+    assert!(TexWriter.write_output(exps, &opts).unwrap().packages.is_empty());
+
+    let exps = vec![Exp::EStyled(TextType::TextBoldItalic, vec![Exp::EIdentifier("x".into())])];
+    assert_eq!(PrettyTexWriter.write_output(exps, &opts).unwrap().packages, vec!["unicode-math"]);
+}
+
+#[test]
+fn test_write_output_warnings(){
+    let mut opts = WriterOptions::default();
+    opts.extra.insert("scaled".to_string(), "ignore".to_string());
+    let x = || Box::new(Exp::EIdentifier("x".into()));
+    let exps = vec![
+        Exp::EStyled(TextType::TextBoldFraktur, vec![Exp::EIdentifier("A".into())]),
+        Exp::EScaled(node::Rational { numerator: 3, denominator: 2 }, x()),
+        Exp::EGrouped(vec![Exp::EIdentifier("y".into()), Exp::EOver(false, x(), Box::new(Exp::ESymbol(TeXSymbolType::TOver, "\u{23b4}".into())))]),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{11}".into()),
+        Exp::EDelimited("a".into(), ")".into(), vec![InEDelimited::Right(Exp::EFraction(FractionType::NormalFrac, x(), x()))]),
+        Exp::EText(TextType::TextDoubleStruck, "R".into()),
+    ];
+    let output = TexWriter.write_output(exps, &opts).unwrap();
+    let warnings: Vec<(&str, &str, &str, &str)> = output.warnings.iter()
+        .map(|w| (w.path.as_str(), w.kind, w.lost.as_str(), w.emitted.as_str()))
+        .collect();
+    assert_eq!(warnings, vec![
+        ("0:EStyled", "style", "TextBoldFraktur", "\\mathfrak"),
+        ("1:EScaled", "scaled", "scale 3/2", "x"),
+        ("2:EGrouped/1:EOver", "accent", "\\overbracket", "\\overset{\\text{\u{23b4}}}{x}"),
+        ("3:ESymbol", "symbol", "U+0011", ""),
+        ("4:EDelimited", "delimiter", "a", "\\left. a"),
+        ("5:EText", "text_style", "TextDoubleStruck", "\\text"),
+    ]);
+
+    let exps = vec![Exp::EStyled(TextType::TextBold, vec![Exp::EIdentifier("A".into())])];
+    assert!(TexWriter.write_output(exps, &opts).unwrap().warnings.is_empty());
+
+    // 下标是节点在Exp::children()中的位置, 与写出顺序无关:
+    // ESub的上标是EGrouped时直接写出其中的元素; EUnderOver中的重音改写为EUnder, 合成的节点不在路径中
+    let fraktur = || Box::new(Exp::EStyled(TextType::TextBoldFraktur, vec![Exp::EIdentifier("A".into())]));
+    let exps = vec![
+        Exp::ESub(x(), Box::new(Exp::EGrouped(vec![Exp::EIdentifier("y".into()), *fraktur()]))),
+        Exp::EUnderOver(false, fraktur(), Box::new(Exp::ESymbol(TeXSymbolType::Accent, "\u{302}".into())), x()),
+    ];
+    let output = TexWriter.write_output(exps, &opts).unwrap();
+    let paths: Vec<&str> = output.warnings.iter().map(|w| w.path.as_str()).collect();
+    assert_eq!(paths, vec!["0:ESub/1:EGrouped/1:EStyled", "1:EUnderOver"]);
+
+    // 直接写出子节点的环境(pmatrix, \binom, \substack)同样经过中间的节点
+    let center = |a: Exp<'static>, b: Exp<'static>| Exp::EArray(vec![Alignment::AlignCenter], vec![vec![vec![a]], vec![vec![b]]]);
+    let exps = vec![
+        Exp::EDelimited("(".into(), ")".into(), vec![InEDelimited::Right(center(*x(), *fraktur()))]),
+        Exp::EDelimited("(".into(), ")".into(), vec![InEDelimited::Right(Exp::EFraction(FractionType::NoLineFrac, x(), fraktur()))]),
+        Exp::EUnder(true, Box::new(Exp::ESymbol(TeXSymbolType::Op, "\u{2211}".into())), Box::new(center(*x(), *fraktur()))),
+    ];
+    let output = TexWriter.write_output(exps, &opts).unwrap();
+    let paths: Vec<&str> = output.warnings.iter().map(|w| w.path.as_str()).collect();
+    assert_eq!(paths, vec!["0:EDelimited/0:EArray/1:EStyled", "1:EDelimited/0:EFraction/1:EStyled", "2:EUnder/1:EArray/1:EStyled"]);
+}
+
+#[test]
+fn test_write_combining(){
+    let opts = WriterOptions::default();
+    let exps = vec![
+        Exp::EIdentifier("x\u{302}".into()),
+        Exp::ESymbol(TeXSymbolType::Rel, "=\u{338}".into()),
+        Exp::EIdentifier("\u{e9}".into()),
+    ];
+    assert_eq!(TexWriter.write(exps, &opts).unwrap(), "\\hat{x} \\neq \\acute{e}");
+}
+
+#[test]
+fn test_write_unmapped(){
+    let exps = vec![
+        Exp::ENumber("5".into()),
+        Exp::ESymbol(TeXSymbolType::Ord, "\u{20AC}".into()),
+        Exp::EMathOperator("\u{20B9}".into()),
+    ];
+    let write = |unmapped: &str| {
+        let mut opts = WriterOptions::default();
+        opts.extra.insert("unmapped".to_string(), unmapped.to_string());
+        TexWriter.write_output(exps.clone(), &opts)
+    };
+    assert_eq!(write("text").unwrap().text, "5\\text{\u{20AC}}\\operatorname{\\text{\u{20B9}}}");
+    let output = write("command").unwrap();
+    assert_eq!(output.text, "5\\text{\\texteuro}\\operatorname{\\char\"20B9}");
+    assert!(output.packages.contains(&"textcomp".to_string()));
+    assert_eq!(write("char").unwrap().text, "5\\char\"20AC\\operatorname{\\char\"20B9}");
+    assert!(write("error").is_err());
+    assert!(write("raw").is_err());
+}
+
+#[test]
+fn test_write_space(){
+    let space = |n: i32, d: i32| Exp::ESpace(node::Rational { numerator: n, denominator: d });
+    let exps = vec![
+        Exp::EIdentifier("a".into()),
+        space(-2, 9),
+        Exp::EIdentifier("b".into()),
+        space(1, 2),
+        Exp::EIdentifier("c".into()),
+        space(1, 7),
+    ];
+    let output = TexWriter.write_output(exps.clone(), &WriterOptions::default()).unwrap();
+    assert_eq!(output.text, "a\\negmedspace b\\enspace c\\mspace{2.571mu}");
+    assert_eq!(output.packages, vec!["amsmath"]);
+    let warnings: Vec<(&str, &str, &str)> = output.warnings.iter().map(|w| (w.kind, w.lost.as_str(), w.emitted.as_str())).collect();
+    assert_eq!(warnings, vec![("space", "1 % 7", "\\mspace{2.571mu}")]);
+
+    // 没有amsmath时用\mskip
+    let mut opts = WriterOptions::default();
+    opts.envs.insert("amsmath".to_string(), false);
+    assert_eq!(TexWriter.write(exps.clone(), &opts).unwrap(), "a\\mskip -4mu\\relax b\\enspace c\\mskip 2.571mu\\relax");
+
+    // envs中没有amsmath同样按不可用处理, mathtools会加载amsmath
+    opts.envs = Envs::new();
+    opts.envs.insert("amssymb".to_string(), true);
+    assert_eq!(TexWriter.write(exps.clone(), &opts).unwrap(), "a\\mskip -4mu\\relax b\\enspace c\\mskip 2.571mu\\relax");
+    opts.envs.insert("mathtools".to_string(), true);
+    assert_eq!(TexWriter.write(exps, &opts).unwrap(), "a\\negmedspace b\\enspace c\\mspace{2.571mu}");
+}
+
+#[test]
+fn test_write_xarrow(){
+    let f = || Box::new(Exp::EIdentifier("f".into()));
+    let arrow = |s: &'static str| Box::new(Exp::ESymbol(TeXSymbolType::Op, s.into()));
+    let exps = vec![
+        Exp::EOver(false, arrow("\u{2192}"), f()),
+        Exp::EOver(false, arrow("\u{21A6}"), f()),
+        Exp::EUnderOver(false, arrow("\u{21D2}"), f(), f()),
+    ];
+    let output = TexWriter.write_output(exps.clone(), &WriterOptions::default()).unwrap();
+    assert_eq!(output.text, "\\xrightarrow{f}\\overset{f}{\\mapsto}\\underset{f}{\\overset{f}{\\Rightarrow}}");
+
+    let mut opts = WriterOptions::default();
+    opts.envs.insert("mathtools".to_string(), true);
+    let output = TexWriter.write_output(exps, &opts).unwrap();
+    assert_eq!(output.text, "\\xrightarrow{f}\\xmapsto{f}\\xRightarrow[{f}]{f}");
+    assert_eq!(output.packages, vec!["amsmath", "mathtools"]);
+
+    // Rel不是可伸缩的箭头, 原样输出\overset, 不需要extarrows
+    let rel = |s: &'static str| Box::new(Exp::ESymbol(TeXSymbolType::Rel, s.into()));
+    let def = || Box::new(Exp::EIdentifier("def".into()));
+    let exps = vec![Exp::EOver(false, rel("="), def()), Exp::EOver(false, rel("\u{2192}"), f())];
+    opts.envs.insert("extarrows".to_string(), true);
+    let output = TexWriter.write_output(exps, &opts).unwrap();
+    assert_eq!(output.text, "\\overset{def}{=}\\overset{f}{\\rightarrow}");
+    assert_eq!(output.packages, vec!["amsmath"]);
+}
+
+#[test]
+fn test_write_scaled(){
+    let scaled = |n: i32, d: i32, e: Exp<'static>| Exp::EScaled(node::Rational { numerator: n, denominator: d }, Box::new(e));
+    let symbol = |t: TeXSymbolType, s: &'static str| Exp::ESymbol(t, s.into());
+    let exps = vec![
+        scaled(6, 5, symbol(TeXSymbolType::Open, "(")),
+        Exp::EIdentifier("a".into()),
+        scaled(9, 5, symbol(TeXSymbolType::Fence, "|")),
+        Exp::EIdentifier("b".into()),
+        scaled(6, 5, symbol(TeXSymbolType::Ord, "/")),
+        scaled(12, 5, symbol(TeXSymbolType::Close, ")")),
+        scaled(3, 2, Exp::EIdentifier("x".into())),
+    ];
+    let output = TexWriter.write_output(exps.clone(), &WriterOptions::default()).unwrap();
+    assert_eq!(output.text, "\\bigl(a\\Bigm|b\\big/\\biggr)\\mathchoice{\\scalebox{1.5}{\\(\\displaystyle x\\)}}\
+        {\\scalebox{1.5}{\\(\\textstyle x\\)}}{\\scalebox{1.5}{\\(\\scriptstyle x\\)}}{\\scalebox{1.5}{\\(\\scriptscriptstyle x\\)}}");
+    assert_eq!(output.packages, vec!["graphicx"]);
+    assert!(output.warnings.is_empty());
+
+    // 超过\Bigg的界定符, ignore时忽略缩放并报告警告
+    let mut opts = WriterOptions::default();
+    opts.extra.insert("scaled".to_string(), "ignore".to_string());
+    let output = TexWriter.write_output(vec![scaled(4, 1, symbol(TeXSymbolType::Open, "("))], &opts).unwrap();
+    assert_eq!(output.text, "(");
+    assert_eq!(output.warnings[0].lost, "scale 4/1");
+
+    // markdown中用$...$包裹时, \scalebox里不能再出现$
+    let mut opts = WriterOptions::default();
+    opts.inline_delimiters = ("$".to_string(), "$".to_string());
+    let exps = vec![Exp::EText(TextType::TextNormal, "see".into()), scaled(3, 2, Exp::EIdentifier("x".into()))];
+    let text = MarkdownWriter.write(exps, &opts).unwrap();
+    assert_eq!(text.matches('$').count(), 2);
+    assert!(text.starts_with("see $\\mathchoice{\\scalebox{1.5}{\\(\\displaystyle x\\)}}"));
+    assert!(text.ends_with("$"));
+}
+
+#[test]
+fn test_render_preamble(){
+    let packages = vec!["amsmath".to_string(), "amssymb".to_string()];
+    assert_eq!(render_preamble(&packages), "\\usepackage{amsmath}\n\\usepackage{amssymb}\n");
+    // mathtools会加载amsmath
+    let packages = vec!["amsmath".to_string(), "mathtools".to_string()];
+    assert_eq!(render_preamble(&packages), "\\usepackage{mathtools}\n");
+    assert_eq!(render_preamble(&[]), "");
+}
+
+// 按用到的宏包生成最小的导言区, 每个宏包一行\usepackage, 如: \usepackage{amsmath}
+// 用到unicode-math时需要用XeLaTeX或LuaLaTeX编译
+pub fn render_preamble(packages: &[String]) -> String{
+    let has_mathtools = packages.iter().any(|p| p == "mathtools");
+    packages.iter()
+        .filter(|p| !(has_mathtools && *p == "amsmath"))
+        .map(|p| format!("\\usepackage{{{}}}\n", p))
+        .collect()
+}
+
+// 纯TeX输出, equation时用display_delimiters包裹
+pub struct TexWriter;
+
+impl Writer for TexWriter {
+    fn name(&self) -> &'static str {
+        "tex"
+    }
+
+    fn write(&self, exps: Vec<Exp>, opts: &WriterOptions) -> Result<String, String> {
+        self.write_output(exps, opts).map(|output| output.text)
+    }
+
+    fn write_output(&self, exps: Vec<Exp>, opts: &WriterOptions) -> Result<WriterOutput, String> {
+        let mut twc = context_with_opts(opts)?;
+        let tex = match opts.equation {
+            true => write_tex_display_with(exps, &mut twc, &opts.display_delimiters.0, &opts.display_delimiters.1)?,
+            false => write_tex_inline(exps, &mut twc)?,
+        };
+        Ok(twc.into_output(tex))
+    }
+}
+
+// markdown中的TeX, 公式用inline_delimiters包裹, 文本转义markdown特殊字符
+pub struct MarkdownWriter;
+
+impl Writer for MarkdownWriter {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn write(&self, exps: Vec<Exp>, opts: &WriterOptions) -> Result<String, String> {
+        self.write_output(exps, opts).map(|output| output.text)
+    }
+
+    fn write_output(&self, exps: Vec<Exp>, opts: &WriterOptions) -> Result<WriterOutput, String> {
+        let mut twc = context_with_opts(opts)?;
+        let tex = match opts.equation {
+            true => write_tex_display_with(exps, &mut twc, &opts.display_delimiters.0, &opts.display_delimiters.1)?,
+            false => write_tex_md_with(exps, &mut twc, &opts.inline_delimiters.0, &opts.inline_delimiters.1)?,
+        };
+        Ok(twc.into_output(tex))
+    }
+}
+
+// 多行美化的TeX
+pub struct PrettyTexWriter;
+
+impl Writer for PrettyTexWriter {
+    fn name(&self) -> &'static str {
+        "pretty"
+    }
+
+    fn write(&self, exps: Vec<Exp>, opts: &WriterOptions) -> Result<String, String> {
+        self.write_output(exps, opts).map(|output| output.text)
+    }
+
+    fn write_output(&self, exps: Vec<Exp>, opts: &WriterOptions) -> Result<WriterOutput, String> {
+        let mut twc = context_with_opts(opts)?;
+        let tex = write_tex_inline(exps, &mut twc)?;
+        Ok(twc.into_output(tex_pretty::pretty_print(&tex, &tex_pretty::PrettyOptions::default())))
+    }
+}
+
 #[test]
 fn test_write_grouped_exp(){
     // \sqrt{aaa}
@@ -392,27 +796,28 @@ fn test_write_grouped_exp(){
 
     c.tex.push_str("\\sqrt");
 
-    let exp = Exp::EIdentifier("aaa".to_string());
+    let exp = Exp::EIdentifier("aaa".into());
 
-    write_grouped_exp(&mut c, &exp).unwrap();
+    write_grouped_exp(&mut c, 0, &exp).unwrap();
     println!("res: {:?}", c.tex);
 }
 
 // 保证输出一对{}且不重复
 // 但如果Exp是EGrouped, 直接调用write_tex会导致输出两对{}, 所以需要特殊处理
-fn write_grouped_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
+// index为exp在父节点中的下标, 见write_exp
+fn write_grouped_exp(c: &mut TexWriterContext, index: usize, exp: &Exp) -> Result<(), String>{
    return match exp {
-        Exp::EGrouped(exp_list) => {
+        Exp::EGrouped(exp_list) => within(c, index, exp, |c| {
             c.push_text("{");
-            for e in exp_list{
-                write_exp(c, e)?;
+            for (i, e) in exp_list.iter().enumerate(){
+                write_exp(c, i, e)?;
             }
             c.push_text("}");
             Ok(())
-        },
+        }),
         _ => {
             c.push_text("{");
-            write_exp(c, exp)?;
+            write_exp(c, index, exp)?;
             c.push_text("}");
             Ok(())
         }
@@ -435,17 +840,21 @@ fn write_array_aligns(c: &mut TexWriterContext, aligns: &Vec<Alignment>) {
 // exp1 & exp2 & exp3 \\
 // exp4 & exp5 & exp6
 fn write_array_rows(c: &mut TexWriterContext, rows: &Vec<ArrayLines>) -> Result<(), String> {
+    // 元素在EArray的Exp::children()中按行, 列展开
+    let mut index = 0;
     // array rows
     for (i, row) in rows.iter().enumerate(){
         for (j, ele) in row.iter().enumerate(){
             match ele.len() {
                 0 => {},
                 1 => {
-                    write_exp(c, &ele[0])?;
+                    write_exp(c, index, &ele[0])?;
+                    index += 1;
                 },
                 _ => {
                     for e in ele{
-                        write_exp(c, e)?;
+                        write_exp(c, index, e)?;
+                        index += 1;
                     }
                 }
             }
@@ -495,6 +904,9 @@ fn write_array_table(c: &mut TexWriterContext, name: &str, aligns: &Vec<Alignmen
     c.push_text("\\begin{");
     c.push_text(name);
     c.push_text("}");
+    if let Some(p) = shared::environment_package(name) {
+        c.packages.insert(p.to_string());
+    }
 
     write_array_aligns(c, aligns);
     write_array_rows(c, rows)?;
@@ -508,49 +920,86 @@ fn write_array_table(c: &mut TexWriterContext, name: &str, aligns: &Vec<Alignmen
     Ok(())
 }
 
-// 当Delimited只有一个Right元素且里面是EArray时调用
+#[test]
+fn test_write_without_amsmath_env(){
+    // 请求可以只指定自己的packages, envs中没有amsmath时按不可用处理, 不能panic
+    let id = |s: &'static str| Exp::EIdentifier(s.into());
+    let column = |a: Exp<'static>, b: Exp<'static>| vec![vec![vec![a]], vec![vec![b]]];
+    let exps = vec![
+        Exp::EDelimited("(".into(), ")".into(), vec![InEDelimited::Right(Exp::EArray(vec![Alignment::AlignCenter], column(id("a"), id("b"))))]),
+        Exp::EDelimited("{".into(), "".into(), vec![InEDelimited::Right(Exp::EArray(vec![Alignment::AlignLeft, Alignment::AlignLeft], vec![vec![vec![id("x")], vec![id("y")]]]))]),
+        Exp::EDelimited("(".into(), ")".into(), vec![InEDelimited::Right(Exp::EFraction(FractionType::NoLineFrac, Box::new(id("n")), Box::new(id("k"))))]),
+        Exp::EUnder(true, Box::new(Exp::ESymbol(TeXSymbolType::Op, "\u{2211}".into())), Box::new(Exp::EArray(vec![Alignment::AlignCenter], column(id("i"), id("j"))))),
+        Exp::EArray(vec![Alignment::AlignCenter, Alignment::AlignCenter], vec![vec![vec![id("c")], vec![id("d")]]]),
+        Exp::EBoxed(Box::new(id("e"))),
+    ];
+    let mut opts = WriterOptions::default();
+    opts.envs = Envs::new();
+    opts.envs.insert("amssymb".to_string(), true);
+    let output = TexWriter.write_output(exps.clone(), &opts).unwrap();
+    assert_eq!(output.text, "\\left( \\begin{array}{c}\na \\\\\nb\n\\end{array} \\right)\
+        \\left\\{\\begin{array}{ll}\nx & y\n\\end{array} \\right.\
+        n\\choose k\
+        \\sum_{\\begin{array}{c}\ni \\\\\nj\n\\end{array}}\
+        \\begin{array}{cc}\nc & d\n\\end{array}e");
+    assert!(!output.packages.contains(&"amsmath".to_string()));
+
+    // 同样的输入在amsmath下使用amsmath的环境
+    let output = TexWriter.write(exps, &WriterOptions::default()).unwrap();
+    assert_eq!(output, "\\begin{pmatrix}\na \\\\\nb\n\\end{pmatrix}\
+        \\begin{cases}\nx & y\n\\end{cases}\
+        \\binom{n}{k}\
+        \\sum_{\\substack{i \\\\\nj\n}}\
+        \\begin{matrix}\nc & d\n\\end{matrix}\\boxed{e}");
+}
+
+// 当Delimited只有一个Right元素且里面是EArray时调用, array在EDelimited中的下标为0
 // Delimited open close [Right (EArray [AlignCenter] [[[x]],[[y]]])]
-fn delimited_write_right_array(c: &mut TexWriterContext, open: &String, close: &String, aligns: &Vec<Alignment>, rows: &Vec<ArrayLines>) -> Result<(), String> {
-    match (c.envs["amsmath"], open.as_str(), close.as_str()) {
+fn delimited_write_right_array(c: &mut TexWriterContext, open: &str, close: &str, array: &Exp) -> Result<(), String> {
+    let (aligns, rows) = match array {
+        Exp::EArray(aligns, rows) => (aligns, rows),
+        _ => return Err(format!("expected EArray in delimited_write_right_array, got {}", array.name())),
+    };
+    match (c.has_package("amsmath"), open, close) {
         (true, "{", "") => {
             if aligns.len() == 2 && aligns[0] == Alignment::AlignLeft && aligns[1] == Alignment::AlignLeft {
                 // \begin{cases} \end{cases}
-                write_array_table(c, "cases", &Vec::<Alignment>::new(), rows)?;
+                within(c, 0, array, |c| write_array_table(c, "cases", &Vec::<Alignment>::new(), rows))?;
                 return Ok(());
             }
         }
         (true, "(", ")") => {
             if shared::aligns_is_all_center(aligns) {
                 // \begin{pmatrix} \end{pmatrix}
-                write_array_table(c, "pmatrix", &Vec::<Alignment>::new(), rows)?;
+                within(c, 0, array, |c| write_array_table(c, "pmatrix", &Vec::<Alignment>::new(), rows))?;
                 return Ok(());
             }
         }
         (true, "[", "]") => {
             if shared::aligns_is_all_center(aligns) {
                 // \begin{bmatrix} \end{bmatrix}
-                write_array_table(c, "bmatrix", &Vec::<Alignment>::new(), rows)?;
+                within(c, 0, array, |c| write_array_table(c, "bmatrix", &Vec::<Alignment>::new(), rows))?;
                 return Ok(());
             }
         }
         (true, "{", "}") => {
             if shared::aligns_is_all_center(aligns) {
                 // \begin{Bmatrix} \end{Bmatrix}
-                write_array_table(c, "Bmatrix", &Vec::<Alignment>::new(), rows)?;
+                within(c, 0, array, |c| write_array_table(c, "Bmatrix", &Vec::<Alignment>::new(), rows))?;
                 return Ok(());
             }
         }
-        (true, "\\8739", "\\8739") => {
+        (true, "\u{2223}", "\u{2223}") => {
             if shared::aligns_is_all_center(aligns) {
                 // \begin{vmatrix} \end{vmatrix}
-                write_array_table(c, "vmatrix", &Vec::<Alignment>::new(), rows)?;
+                within(c, 0, array, |c| write_array_table(c, "vmatrix", &Vec::<Alignment>::new(), rows))?;
                 return Ok(());
             }
         }
-        (true, "\\8741", "\\8741") => {
+        (true, "\u{2225}", "\u{2225}") => {
             if shared::aligns_is_all_center(aligns) {
                 // \begin{Vmatrix} \end{Vmatrix}
-                write_array_table(c, "Vmatrix", &Vec::<Alignment>::new(), rows)?;
+                within(c, 0, array, |c| write_array_table(c, "Vmatrix", &Vec::<Alignment>::new(), rows))?;
                 return Ok(());
             }
         }
@@ -559,45 +1008,45 @@ fn delimited_write_right_array(c: &mut TexWriterContext, open: &String, close: &
         },
     };
 
-    delimited_write_delim(c, FenceType::DLeft, open);
-    write_exp(c, &Exp::EArray(aligns.clone(), rows.clone()))?;
-    delimited_write_delim(c, FenceType::DRight, close);
+    delimited_write_delim(c, FenceType::DLeft, open)?;
+    write_exp(c, 0, array)?;
+    delimited_write_delim(c, FenceType::DRight, close)?;
     Ok(())
 }
 
 fn write_binom(c: &mut TexWriterContext, cmd: &str, e1: &Exp, e2: &Exp) -> Result<(), String>{
-    if c.envs["amsmath"]{
+    if c.has_package("amsmath"){
         match cmd{
             "\\choose" => {
-                c.push_text("\\binom");
+                c.push_command("\\binom");
             },
             "\\brack" => {
-                c.push_text(shared::get_general_frac("[", "]").as_str());
+                c.push_command(shared::get_general_frac("[", "]").as_str());
             },
             "\\brace" => {
-                c.push_text(shared::get_general_frac("\\{", "\\}").as_str());
+                c.push_command(shared::get_general_frac("\\{", "\\}").as_str());
             },
             "\\bangle" => {
-                c.push_text(shared::get_general_frac("\\langle", "\\rangle").as_str());
+                c.push_command(shared::get_general_frac("\\langle", "\\rangle").as_str());
             },
             _ => {
                 return Err(format!("unknown cmd in write_binom: {}", cmd));
             }
         }
-        write_grouped_exp(c, e1)?;
-        write_grouped_exp(c, e2)?;
+        write_grouped_exp(c, 0, e1)?;
+        write_grouped_exp(c, 1, e2)?;
     }else{
         // 不是这些, 则直接输出
-        write_exp(c, e1)?;
+        write_exp(c, 0, e1)?;
         c.push_text(cmd);
-        write_exp(c, e2)?;
+        write_exp(c, 1, e2)?;
     }
     Ok(())
 }
 
-// 处理 EDelimited open close [Right (EFraction NoLineFrac e1 e2)]
-fn delimited_fraction_noline(c: &mut TexWriterContext, left: &String, right: &String, frac_exp1: &Exp, frac_exp2: &Exp) -> Result<(), String> {
-    Ok(match (left.as_str(), right.as_str()) {
+// 处理 EDelimited open close [Right (EFraction NoLineFrac e1 e2)], 调用时已进入EFraction
+fn delimited_fraction_noline(c: &mut TexWriterContext, left: &str, right: &str, frac_exp1: &Exp, frac_exp2: &Exp) -> Result<(), String> {
+    Ok(match (left, right) {
         ("(", ")") => {
             // \choose: 类似于二项
             write_binom(c, "\\choose", frac_exp1, frac_exp2)?;
@@ -611,7 +1060,7 @@ fn delimited_fraction_noline(c: &mut TexWriterContext, left: &String, right: &St
             write_binom(c, "\\brace", frac_exp1, frac_exp2)?;
         },
         // 左右尖括号
-        ("\\10216", "\\10217") => {
+        ("\u{27E8}", "\u{27E9}") => {
             // \\bangle
             write_binom(c, "\\bangle", frac_exp1, frac_exp2)?;
         },
@@ -620,21 +1069,21 @@ fn delimited_fraction_noline(c: &mut TexWriterContext, left: &String, right: &St
             // writeExp (EDelimited open close [Right (EArray [AlignCenter]
             //     [[[x]],[[y]]])])
 
-            delimited_write_right_array(c, left, right,
-                                        &vec![Alignment::AlignCenter],
-                                        &vec![
-                                            vec![vec![frac_exp1.clone()]],
-                                            vec![vec![frac_exp2.clone()]]
-                                        ])?;
+            let array = Exp::EArray(vec![Alignment::AlignCenter], vec![
+                vec![vec![frac_exp1.clone()]],
+                vec![vec![frac_exp2.clone()]]
+            ]);
+            synthesized(c, |c| delimited_write_right_array(c, left, right, &array))?;
         }
     })
 }
 
-fn delimited_write_delim(c: &mut TexWriterContext, ft: FenceType, delim: &str){
-    let tex_delim = shared::escape_text_as_tex(delim, &c.envs);
-    let valid = tex_unicode::is_delimiters(delim, &c.envs); // 界定符号是否有效
+fn delimited_write_delim(c: &mut TexWriterContext, ft: FenceType, delim: &str) -> Result<(), String>{
+    let start = c.tex.len();
+    let tex_delim = c.escape(delim)?;
+    let valid = tex_unicode::is_delimiters(delim, &c.enabled); // 界定符号是否有效
 
-    let null_lim = shared::escape_text_as_tex(".", &c.envs); // TODO: 空的界定符号
+    let null_lim = c.escape(".")?; // TODO: 空的界定符号
 
     let delim_cmd = match valid {
         true => tex_delim.clone(),
@@ -671,6 +1120,12 @@ fn delimited_write_delim(c: &mut TexWriterContext, ft: FenceType, delim: &str){
             }
         },
     }
+    if !valid {
+        // 不能用\left, \middle, \right伸缩的界定符, 原样输出, 失去伸缩
+        let emitted = c.tex[start..].trim().to_string();
+        c.warn("delimiter", delim, &emitted);
+    }
+    Ok(())
 }
 
 #[test]
@@ -684,46 +1139,18 @@ fn test_delimited_write_general_exp(){
     //     , Right (EIdentifier "H")
     // ])
 
-    let open = "\\10216".to_string();
-    let close = "\\10217".to_string();
+    let open = "\u{27E8}".to_string();
+    let close = "\u{27E9}".to_string();
     let exp_list = vec![
-        InEDelimited::Right(Exp::EIdentifier("H".to_string())),
-        InEDelimited::Right(Exp::ESymbol(TeXSymbolType::Rel, "\\8739".to_string())),
-        InEDelimited::Right(Exp::EIdentifier("H".to_string())),
+        InEDelimited::Right(Exp::EIdentifier("H".into())),
+        InEDelimited::Right(Exp::ESymbol(TeXSymbolType::Rel, "\u{2223}".into())),
+        InEDelimited::Right(Exp::EIdentifier("H".into())),
     ];
     delimited_write_general_exp(&mut c, &open, &close, &exp_list).unwrap();
     println!("res: {:?}", c.tex);
 }
 
-fn is_undefined(s: &str) -> bool {
-    // \597
-    // \657
-    // \658
-    // \8364
-    // \8377
-
-    match s {
-        "\\597" => {
-            return true;
-        },
-        "\\657" => {
-            return true;
-        },
-        "\\658" => {
-            return true;
-        },
-        "\\8364" => {
-            return true;
-        },
-        "\\8377" => {
-            return true;
-        },
-        _ => {
-            return false;
-        }
-    }
-}
-fn delimited_write_general_exp(c: &mut TexWriterContext, open: &String, close: &String, exp_list: &Vec<InEDelimited>) -> Result<(), String>{
+fn delimited_write_general_exp(c: &mut TexWriterContext, open: &str, close: &str, exp_list: &Vec<InEDelimited>) -> Result<(), String>{
 //     writeExp (EDelimited open close es)
 //   | all isStandardHeight es
 //   , open == "(" || open == "[" || open == "|"
@@ -740,7 +1167,7 @@ fn delimited_write_general_exp(c: &mut TexWriterContext, open: &String, close: &
 //   isStandardHeight (Right (ESymbol ty _)) = ty elem` [Ord, Op, Bin, Rel, Pun]
 //   isStandardHeight _ = False
     let is_open_close =
-        match (open.as_str(), close.as_str()){
+        match (open, close){
             ("(", ")") => {
                 true
             },
@@ -758,53 +1185,60 @@ fn delimited_write_general_exp(c: &mut TexWriterContext, open: &String, close: &
     let is_right = shared::is_all_right(exp_list);
     let is_standard_height = shared::is_all_standard_height(exp_list);
     return if is_open_close && is_right && is_standard_height {
-        c.push_text(&shared::escape_text_as_tex(open, &c.envs));
+        let open = c.escape(open)?;
+        c.push_text(&open);
         // mapM_ (either (writeDelim DMiddle) writeExp) es
+        let mut index = 0; // Right元素在Exp::children()中的下标
         for exp in exp_list {
             match exp {
                 InEDelimited::Left(delim) => {
-                    delimited_write_delim(c, FenceType::DMiddle, delim);
+                    delimited_write_delim(c, FenceType::DMiddle, delim)?;
                 },
                 InEDelimited::Right(exp) => {
-                    write_exp(c, exp)?;
+                    write_exp(c, index, exp)?;
+                    index += 1;
                 }
             }
         }
-        c.push_text(&shared::escape_text_as_tex(close, &c.envs));
+        let close = c.escape(close)?;
+        c.push_text(&close);
         Ok(())
     } else {
         // writeExp (EDelimited open close es) =  do
         // writeDelim DLeft open
         // mapM_ (either (writeDelim DMiddle) writeExp) es
         // writeDelim DRight close
-        delimited_write_delim(c, FenceType::DLeft, open);
+        delimited_write_delim(c, FenceType::DLeft, open)?;
+        let mut index = 0;
         for exp in exp_list {
             match exp {
                 InEDelimited::Left(delim) => {
-                    delimited_write_delim(c, FenceType::DMiddle, delim);
+                    delimited_write_delim(c, FenceType::DMiddle, delim)?;
                 },
                 InEDelimited::Right(exp) => {
-                    write_exp(c, exp)?;
+                    write_exp(c, index, exp)?;
+                    index += 1;
                 }
             }
         }
-        delimited_write_delim(c, FenceType::DRight, close);
+        delimited_write_delim(c, FenceType::DRight, close)?;
         Ok(())
     }
 }
 
 #[test]
 fn test_write_script(){
-    let mut envs = HashMap::new();
+    let mut envs = Envs::new();
     envs.insert("amsmath".to_string(), true);
     let mut c = default_context();
     // EUnder False (ESymbol Op "\8749") (EIdentifier "S")
-    let b = Exp::ESymbol(TeXSymbolType::Op, "\\8749".to_string());
-    let e1 = Exp::EIdentifier("S".to_string());
+    let b = Exp::ESymbol(TeXSymbolType::Op, "\u{222D}".into());
+    let e1 = Exp::EIdentifier("S".into());
     write_script(&mut c, &Position::Under, &false, &b, &e1).unwrap();
     println!("res: {:?}", c.tex);
 }
 
+// b, e1为EOver/EUnder的子节点, 下标为0, 1
 fn write_script(c: &mut TexWriterContext, p: &Position, convertible: &bool, b: &Exp, e1: &Exp) -> Result<(), String>{
     let dia_cmd = match e1{
         Exp::ESymbol(t, s) => {
@@ -820,8 +1254,8 @@ fn write_script(c: &mut TexWriterContext, p: &Position, convertible: &bool, b: &
     };
 
     if let Some(cmd) = dia_cmd {
-        c.push_text(&cmd);
-        write_grouped_exp(c, b)?;
+        c.push_command(&cmd);
+        write_grouped_exp(c, 0, b)?;
         return Ok(());
     }
 
@@ -833,16 +1267,16 @@ fn write_script(c: &mut TexWriterContext, p: &Position, convertible: &bool, b: &
         if !tex_unicode::is_mathop_base(b){
             c.push_text("\\mathop{");
             if shared::is_fancy(b){
-                write_grouped_exp(c, b)?;
+                write_grouped_exp(c, 0, b)?;
             }else{
-                write_exp(c, b)?;
+                write_exp(c, 0, b)?;
             }
             c.push_text("}");
         }else{
             if shared::is_fancy(b){
-                write_grouped_exp(c, b)?;
+                write_grouped_exp(c, 0, b)?;
             }else{
-                write_exp(c, b)?;
+                write_exp(c, 0, b)?;
             }
         }
         
@@ -867,14 +1301,14 @@ fn write_script(c: &mut TexWriterContext, p: &Position, convertible: &bool, b: &
 
         if is_fancy(e1){
             c.push_text("{");
-            write_if_substack(c, e1)?;
+            write_if_substack(c, 1, e1)?;
             c.push_text("}");
         }else{
-            write_if_substack(c, e1)?;
+            write_if_substack(c, 1, e1)?;
         }
         c.convertible = false; // reset
         return Ok(());
-    }else if p==&Position::Over && e1 == &Exp::ESymbol(TeXSymbolType::Accent, "\\831".to_string()){
+    }else if p==&Position::Over && e1 == &Exp::ESymbol(TeXSymbolType::Accent, "\u{33F}".into()){
         // 特殊情况的处理: \831 -> \u{033F}, unicode中表示上双横线 -> 用\overline{\overline{b}}代替
         // double bar
         // tell [ControlSeq "\\overline", Literal "{",
@@ -883,7 +1317,7 @@ fn write_script(c: &mut TexWriterContext, p: &Position, convertible: &bool, b: &
         // tell [Literal "}"]
 
         c.push_text("\\overline{\\overline");
-        write_grouped_exp(c, b)?;
+        write_grouped_exp(c, 0, b)?;
         c.push_text("}");
     }else{
         // case pos of
@@ -891,17 +1325,26 @@ fn write_script(c: &mut TexWriterContext, p: &Position, convertible: &bool, b: &
         // Under  -> tell [ControlSeq "\\underset"]
         // tellGroup (writeExp e1)
         // tellGroup (writeExp b)
+        let start = c.tex.len();
         match p {
             Position::Over => {
-                c.push_text("\\overset");
+                c.push_command("\\overset");
             },
             Position::Under => {
-                c.push_text("\\underset");
+                c.push_command("\\underset");
             }
         }
 
-        write_grouped_exp(c, e1)?;
-        write_grouped_exp(c, b)?;
+        write_grouped_exp(c, 1, e1)?;
+        write_grouped_exp(c, 0, b)?;
+
+        // \overbracket, \underbracket不能作为重音输出, 用\overset, \underset代替
+        if let Exp::ESymbol(TeXSymbolType::Accent | TeXSymbolType::TOver | TeXSymbolType::TUnder, s) = e1 {
+            if let Some(cmd) = shared::get_diacriticals(s).filter(|cmd| shared::is_unavailable_diacritical(cmd)) {
+                let emitted = c.tex[start..].to_string();
+                c.warn("accent", &cmd, &emitted);
+            }
+        }
     }
 
     Ok(())
@@ -926,7 +1369,7 @@ fn write_underover_accent(c: &mut TexWriterContext, exp: &Exp) -> Result<bool, S
                     Box::new(new_under_base),
                     (*e1).clone()
                 );
-                write_exp(c, &new_under)?;
+                synthesized(c, |c| write_exp(c, 0, &new_under))?;
                 return Ok(true);
             }else if let Exp::ESymbol(TeXSymbolType::Accent,_) = **e2 {
                 // e2是accent
@@ -940,7 +1383,7 @@ fn write_underover_accent(c: &mut TexWriterContext, exp: &Exp) -> Result<bool, S
                     Box::new(new_over_base),
                     (*e2).clone()
                 );
-                write_exp(c, &new_over)?;
+                synthesized(c, |c| write_exp(c, 0, &new_over))?;
                 return Ok(true);
             }
             Ok(false)
@@ -954,74 +1397,147 @@ fn write_underover_accent(c: &mut TexWriterContext, exp: &Exp) -> Result<bool, S
 // 在某个字符下面书写多行文本时调用, 如\sum:
 // \sum_{\substack{0 \le i \le m \\ 0 \le j \le n}} a_{i,j}
 // 如果不符合条件, 则调用writeExp
-fn write_if_substack(c: &mut TexWriterContext, e:&Exp) -> Result<(), String>{
-    // (EArray [AlignCenter] rows) 模式且 envs["amsmath"] = True
+fn write_if_substack(c: &mut TexWriterContext, index: usize, e:&Exp) -> Result<(), String>{
+    // (EArray [AlignCenter] rows) 模式且 has_package("amsmath")
     // Otherwise -> writeExp e
     if let Exp::EArray(aligns, rows) = e {
-        if c.envs["amsmath"] && aligns.len() == 1 && aligns[0] == Alignment::AlignCenter {
-            c.push_text("{\\substack{");
-            write_array_rows(c, rows)?;
-            c.push_text("}}");
-            return Ok(());
+        if c.has_package("amsmath") && aligns.len() == 1 && aligns[0] == Alignment::AlignCenter {
+            return within(c, index, e, |c| {
+                c.push_text("{\\substack{");
+                write_array_rows(c, rows)?;
+                c.push_text("}}");
+                Ok(())
+            });
         }
     }
 
-    return write_under_over_add_group(c, e);
+    write_under_over_add_group(c, index, e)
 }
 
-fn write_under_over_add_group(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
+fn write_under_over_add_group(c: &mut TexWriterContext, index: usize, exp: &Exp) -> Result<(), String>{
     // 如果exp是一个ENumber, EIdentifier的情况下, 长度大于1, 此时前面有_或^的情况下:
     // 出现 100_100, 100^100 这种情况, 需要添加{}, 否则会被解析为100_1 00, 100^1 00
     match exp{
         Exp::ENumber(n) => {
-            if n.len() > 1{
+            if n.chars().count() > 1{
                 c.push_text("{");
-                write_exp(c, exp)?;
+                write_exp(c, index, exp)?;
                 c.push_text("}");
             }else{
-                write_exp(c, exp)?;
+                write_exp(c, index, exp)?;
             }
             Ok(())
         },
         Exp::EIdentifier(s) => {
-            if s.len() > 1{
+            if s.chars().count() > 1{
                 c.push_text("{");
-                write_exp(c, exp)?;
+                write_exp(c, index, exp)?;
                 c.push_text("}");
             }else{
-                write_exp(c, exp)?;
+                write_exp(c, index, exp)?;
             }
             Ok(())
         },
         Exp::EMathOperator(s) => {
-            if s.len() > 1{
+            if s.chars().count() > 1{
                 c.push_text("{");
-                write_exp(c, exp)?;
+                write_exp(c, index, exp)?;
                 c.push_text("}");
             }else{
-                write_exp(c, exp)?;
+                write_exp(c, index, exp)?;
             }
             Ok(())
         },
         _ => {
-            write_grouped_exp(c, exp)?;
+            write_grouped_exp(c, index, exp)?;
             Ok(())
         }
     }
 }
 
-fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
+// 每层嵌套都经过write_exp, 在这里检查剩余的栈空间, 并记录节点路径
+// index为exp在父节点Exp::children()中的下标, 最外层为输入列表中的下标
+fn write_exp(c: &mut TexWriterContext, index: usize, exp: &Exp) -> Result<(), String>{
+    within(c, index, exp, |c| node::with_stack(|| write_exp_inner(c, exp)))
+}
+
+// 进入节点后调用f, 用于跳过一层直接写出子节点的情况, 如write_grouped_exp直接写出EGrouped的元素
+fn within(c: &mut TexWriterContext, index: usize, exp: &Exp, f: impl FnOnce(&mut TexWriterContext) -> Result<(), String>) -> Result<(), String>{
+    let entered = c.enter(index, exp);
+    let res = f(c);
+    c.leave(entered);
+    res
+}
+
+// 写出时合成的节点(如EUnderOver中的重音改写为EUnder)不在输入中, 其中的节点都不加入path, 警告算在最近的输入节点上
+fn synthesized(c: &mut TexWriterContext, f: impl FnOnce(&mut TexWriterContext) -> Result<(), String>) -> Result<(), String>{
+    c.detached += 1;
+    let res = f(c);
+    c.detached -= 1;
+    res
+}
+
+// 可伸缩的箭头(见shared::get_xarrow), 写出时返回true:
+// 宏包可用: \xrightarrow[below]{above}, \xmapsto[below]{above}
+// 宏包不可用: \underset{below}{\overset{above}{\mapsto}}, 没有amsmath时返回false, 按一般的EOver/EUnderOver写出
+// b, below, above为EOver(b, above)或EUnderOver(b, below, above)的子节点
+fn write_xarrow(c: &mut TexWriterContext, b: &Exp, below: Option<&Exp>, above: &Exp) -> Result<bool, String>{
+    let above_index = match below {
+        Some(_) => 2,
+        None => 1,
+    };
+    let (cmd, package) = match shared::get_xarrow(b) {
+        Some(res) => res,
+        None => return Ok(false),
+    };
+    if c.has_package(package) {
+        c.push_command(cmd);
+        if let Some(below) = below {
+            c.push_text("[");
+            write_grouped_exp(c, 1, below)?;
+            c.push_text("]");
+        }
+        write_grouped_exp(c, above_index, above)?;
+        return Ok(true);
+    }
+    if !c.has_package("amsmath") {
+        return Ok(false);
+    }
+    if let Some(below) = below {
+        c.push_command("\\underset");
+        write_grouped_exp(c, 1, below)?;
+        c.push_text("{");
+    }
+    c.push_command("\\overset");
+    write_grouped_exp(c, above_index, above)?;
+    c.push_text("{");
+    write_exp(c, 0, b)?;
+    c.push_text("}");
+    if below.is_some() {
+        c.push_text("}");
+    }
+    Ok(true)
+}
+
+fn write_exp_inner(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
+    // 用户映射中的子树整体替换为宏
+    if let Some(tex) = c.mapping.exp(exp) {
+        let tex = tex.to_string();
+        c.push_text(&tex);
+        return Ok(());
+    }
     match exp{
         Exp::ENumber(n) => {
-            c.push_text(shared::escape_text_as_tex(n, &c.envs).as_str());
+            let escaped = c.escape(n)?;
+            c.push_text(&escaped);
         },
 
         Exp::EBoxed(exp) => {
-            if c.envs["amsmath"]{
-                c.push_text("\\boxed");
-                write_grouped_exp(c, exp)?;
+            if c.has_package("amsmath"){
+                c.push_command("\\boxed");
+                write_grouped_exp(c, 0, exp)?;
             }else{
-                write_exp(c, exp)?;
+                write_exp(c, 0, exp)?;
             }
         },
 
@@ -1040,11 +1556,11 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
             if exp_list.len() == 0{
                 c.push_text("{}");
             }else if exp_list.len() == 1 && last_char != '^' && last_char != '_' && last_char != ' '{
-                write_exp(c, &exp_list[0])?;
+                write_exp(c, 0, &exp_list[0])?;
             }else{
                 c.push_text("{");
-                for exp in exp_list{
-                    write_exp(c, exp)?;
+                for (i, exp) in exp_list.iter().enumerate(){
+                    write_exp(c, i, exp)?;
                 }
                 c.push_text("}");
             }
@@ -1054,12 +1570,12 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
             if exp_list.len() == 1{
                 match &exp_list[0] {
                     // EDelimited open close [Right (EFraction NoLineFrac e1 e2)]
-                    InEDelimited::Right(Exp::EFraction(FractionType::NoLineFrac, e1, e2)) => {
-                        return delimited_fraction_noline(c, left, right, e1, e2);
+                    InEDelimited::Right(frac @ Exp::EFraction(FractionType::NoLineFrac, e1, e2)) => {
+                        return within(c, 0, frac, |c| delimited_fraction_noline(c, left, right, e1, e2));
                     },
                     // EDelimited open close [Right (EArray aligns rows)]
-                    InEDelimited::Right(Exp::EArray(aligns, rows)) => {
-                        return delimited_write_right_array(c, left, right, aligns, rows);
+                    InEDelimited::Right(array @ Exp::EArray(..)) => {
+                        return delimited_write_right_array(c, left, right, array);
                     },
                     _ => {
                         // go to below
@@ -1070,23 +1586,21 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
         },
 
         Exp::ESymbol(symbol_type, symbol) => {
-            // ? TIPS: 非法字符, 套\text{}处理 \8364,\8377
-            if is_undefined(&symbol){
-                c.push_text("\\text{");
-                c.push_text(shared::escape_text_as_tex(symbol, &c.envs).as_str());
-                c.push_text("}");
-                return Ok(());
-            }else if symbol == "\\17"{
+            // 符号表中没有的字符按c.unmapped处理, 如: € -> \text{€}
+            if symbol == "\u{11}"{
+                c.warn("symbol", "U+0011", "");
                 return Ok(());
             }
-            let escaped = shared::escape_text_as_tex(&symbol, &c.envs);
+            let escaped = c.escape(symbol)?;
+            // 组合字符和前面的基字符算一个字符: =\u{338} -> \neq
+            let count = symbol.chars().filter(|ch| !tex_unicode::is_combining_accent(*ch)).count();
             
             // 如果是Bin, Rel则需要添加一个空格
             if *symbol_type == TeXSymbolType::Bin || *symbol_type == TeXSymbolType::Rel{
                 c.push_space();
             }
 
-            if symbol.chars().next().unwrap() != '\\' && symbol.len() > 1 &&
+            if !symbol.starts_with('\\') && count > 1 &&
                 (symbol_type == &TeXSymbolType::Bin || symbol_type == &TeXSymbolType::Rel || symbol_type == &TeXSymbolType::Op) {
                 // 多字符的非控制关系符, 如要显示: a < b or a > b
                 // 这种情况下直接渲染的话, bora会黏在一起, 需要指示 \mathrel{\text{or}}
@@ -1103,11 +1617,12 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
                     },
                     _ => panic!("error in write_exp ESymbol: symbol_type is not Bin, Rel or Op"),
                 };
-                c.push_text(&("\\math".to_owned() + content + "{\\text{"));
+                c.push_text(&("\\math".to_owned() + content + "{"));
+                c.push_command("\\text{");
                 c.push_text(&escaped);
                 c.push_text("}}");
             }else{
-                if symbol.len() > 1{
+                if count > 1{
                     c.push_text("{");
                     c.push_text(&escaped);
                     c.push_text("}");
@@ -1124,69 +1639,33 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
 
         // ok
         Exp::ESpace(rational) => {
-            let width = rational.numerator as f32 / rational.denominator as f32 * 18.0;
-            let width = width.floor() as i32;
-            match width {
-                -3 => {
-                    c.push_text("\\!");
-                },
-                0 => {
-                    // ? 如果前面有_或^, 这里需要添加{}(占位符)
-                    // ESuper (ENumber "80" ) (ESymbol Ord "\8242" ) ,
-                    // ESuper (ESpace (0 % 1) ) (ENumber "3" )
-                    // 否则出现 80^{\prime}^3
-                    // 正确: 80^{\prime}{}^3
-                    let last_char = match c.tex.len() {
-                        0 => ' ',
-                        _ => c.tex.chars().last().unwrap(),
-                    };
-
-                    if last_char == '^' || last_char == '_'{
-                        c.push_text("{}");
-                    }
-                },
-                3 => {
-                    c.push_text("\\,");
-                },
-                4 => {
-                    // use: \\  \\: \\>
-                    c.push_text("\\ ");
-                },
-                5 => {
-                    c.push_text("\\;");
-                },
-                18 => {
-                    c.push_text("\\quad");
-                    return Ok(());
-                },
-                36 => {
-                    c.push_text("\\qquad");
-                    return Ok(());
-                },
-                n => {
-                    if c.envs["amsmath"]{
-                        c.push_text("\\mspace{");
-                        c.push_text(&n.to_string());
-                        c.push_text("mu}");
-                    }else{
-                        c.push_text("\\mskip{");
-                        c.push_text(&n.to_string());
-                        c.push_text("mu}");
-                    }
+            let (cmd, exact) = match shared::get_space_command(c.has_package("amsmath"), rational) {
+                Some(res) => res,
+                None => return Err(format!("invalid space width: {} % {}", rational.numerator, rational.denominator)),
+            };
+            if cmd.is_empty() {
+                // ? 如果前面有_或^, 这里需要添加{}(占位符)
+                // ESuper (ENumber "80" ) (ESymbol Ord "\8242" ) ,
+                // ESuper (ESpace (0 % 1) ) (ENumber "3" )
+                // 否则出现 80^{\prime}^3
+                // 正确: 80^{\prime}{}^3
+                if c.tex.ends_with('^') || c.tex.ends_with('_'){
+                    c.push_text("{}");
                 }
+                return Ok(());
+            }
+            c.push_command(&cmd);
+            if !exact {
+                // 宽度不能写成有限小数, 四舍五入到0.001mu
+                let lost = format!("{} % {}", rational.numerator, rational.denominator);
+                c.warn("space", &lost, &cmd);
             }
         },
 
         Exp::EIdentifier(identifier) => {
-            if is_undefined(&identifier){
-                c.push_text("\\text{");
-                c.push_text(shared::escape_text_as_tex(&identifier, &c.envs).as_str());
-                c.push_text("}");
-                return Ok(());
-            }
             // 为了防止连续的标识符被合并, 需要在标识符之间添加空格, 如:
             // \alphax -> \alpha x
-            let (escaped, nums) = get_math_tex_many(&identifier, &c.envs);
+            let (escaped, nums) = get_math_tex_many(identifier, &c.enabled, &c.mapping, &c.combining, c.unmapped, &mut c.packages)?;
             if escaped.len() == 0{
                 return Ok(());
             }
@@ -1209,28 +1688,18 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
         },
 
         Exp::EMathOperator(math_operator) => {
-            let escaped = shared::escape_text_as_tex(&math_operator, &c.envs);
+            let escaped = c.escape(math_operator)?;
         
             
             if is_mathoperator(escaped.as_str()) {
                 c.push_text(format!("\\{}", escaped).as_str());
             }else{
                 if c.convertible{
-                    c.push_text("\\operatorname*{");
+                    c.push_command("\\operatorname*{");
                 }else{
-                    c.push_text("\\operatorname{");
-                }
-                let mut new_escape = String::new();
-                for i in escaped.chars(){
-                    if i == '₹'{
-                        new_escape.push_str("\\text{");
-                        new_escape.push(i);
-                        new_escape.push_str("}");
-                    }else{
-                        new_escape.push(i);
-                    }
+                    c.push_command("\\operatorname{");
                 }
-                c.push_text(&new_escape);
+                c.push_text(&escaped);
                 c.push_text("}");
             }
         },
@@ -1239,50 +1708,50 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
             if shared::is_null_exp(exp1){
                 c.push_text("{}");
             }else if shared::is_fancy(exp1){
-                write_grouped_exp(c, exp1)?;
+                write_grouped_exp(c, 0, exp1)?;
             }else{
-                write_under_over_add_group(c, exp1)?;
+                write_under_over_add_group(c, 0, exp1)?;
             }
 
             c.push_text("_");
-            write_grouped_exp(c, exp2)?;
+            write_grouped_exp(c, 1, exp2)?;
         },
 
         Exp::ESuper(base, exp2) => {
             if shared::is_null_exp(base){
                 c.push_text("{}");
             }else if shared::is_fancy(base){
-                write_grouped_exp(c, base)?;
+                write_grouped_exp(c, 0, base)?;
             }else{
-                write_under_over_add_group(c, base)?;
+                write_under_over_add_group(c, 0, base)?;
             }
 
             c.push_text("^");
-            write_grouped_exp(c, exp2)?;
+            write_grouped_exp(c, 1, exp2)?;
         },
 
         Exp::ESubsup(exp1, exp2, exp3) => {
             if shared::is_null_exp(exp1){
                 c.push_text("{}");
             }else if shared::is_fancy(exp1){
-                write_grouped_exp(c, exp1)?;
+                write_grouped_exp(c, 0, exp1)?;
             }else{
-                write_under_over_add_group(c, exp1)?;
+                write_under_over_add_group(c, 0, exp1)?;
             }
 
             c.push_text("_");
-            write_grouped_exp(c, exp2)?;
+            write_grouped_exp(c, 1, exp2)?;
             c.push_text("^");
-            write_grouped_exp(c, exp3)?;
+            write_grouped_exp(c, 2, exp3)?;
         },
 
         Exp::ESqrt(exp) => {
             c.push_text("\\sqrt");
-            write_grouped_exp(c, exp)?;
+            write_grouped_exp(c, 0, exp)?;
         },
 
         Exp::EFraction(fraction_type, exp1, exp2) => {
-            c.push_text(format!("\\{}", match fraction_type{
+            c.push_command(format!("\\{}", match fraction_type{
                 FractionType::NormalFrac => {
                     "frac"
                 },
@@ -1299,8 +1768,8 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
                     "binom"
                 },
             }).as_str());
-            write_grouped_exp(c, exp1)?;
-            write_grouped_exp(c, exp2)?;
+            write_grouped_exp(c, 0, exp1)?;
+            write_grouped_exp(c, 1, exp2)?;
         },
 
         Exp::EText(text_type, str) => {
@@ -1308,24 +1777,30 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
                 return Ok(());
             }
             let (cmd, repeats) = shared::get_text_cmd(text_type);
+            if shared::is_degraded_text(text_type) {
+                c.warn("text_style", &format!("{:?}", text_type), cmd.trim_end_matches('{'));
+            }
             let text = &escapse_text(str);
 
-            c.push_text(&format!("{}{}{}", cmd, text, "}".repeat(repeats as usize)));
+            c.push_command(&format!("{}{}{}", cmd, text, "}".repeat(repeats as usize)));
         },
 
         Exp::EStyled(text_type, exp_list) => {
             let cmd = shared::get_style_latex_cmd(text_type, &c.envs);
-            c.push_text(cmd.as_str());
+            if shared::is_degraded_style(text_type) {
+                c.warn("style", &format!("{:?}", text_type), &cmd);
+            }
+            c.push_command(cmd.as_str());
             c.push_text("{");
-            for exp in exp_list{
-                write_exp(c, exp)?;
+            for (i, exp) in exp_list.iter().enumerate(){
+                write_exp(c, i, exp)?;
             }
             c.push_text("}");
         },
 
         Exp::EPhantom(exp) => {
             c.push_text("\\phantom");
-            write_grouped_exp(c, exp)?;
+            write_grouped_exp(c, 0, exp)?;
         },
 
         Exp::EArray(alignments, exp_lists) => {
@@ -1335,7 +1810,7 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
             // array: 其他情况
             let null_aligns = &Vec::<Alignment>::new();
             let(name, aligns, rows) =
-                match (shared::aligns_is_rlsequence(alignments), shared::aligns_is_all_center(alignments), c.envs["amsmath"]) {
+                match (shared::aligns_is_rlsequence(alignments), shared::aligns_is_all_center(alignments), c.has_package("amsmath")) {
                 (true, false, true) => {
                     // self.write_array_table("aligned", &Vec::<Alignment>::new(), exp_lists);
                     // self.last_cmd = TexSeqType::Control;
@@ -1360,13 +1835,9 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
         },
 
         Exp::EOver(convertible, b, e1) => {
-            // 特殊处理 \xrightarrow, \xleftarrow 情况
-            if let Some(exp) = shared::get_xarrow(b){
-                if c.envs["amsmath"]{
-                    c.push_text(exp.as_str());
-                    write_grouped_exp(c, e1)?;
-                    return Ok(());
-                }
+            // 特殊处理 \xrightarrow, \xleftarrow, \xmapsto等可伸缩的箭头
+            if write_xarrow(c, b, None, e1)? {
+                return Ok(());
             }
 
             write_script(c, &Position::Over, convertible, b, e1)?;
@@ -1395,19 +1866,10 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
                 }
             };
 
-            // xarrow: 在amsmath环境下, \xrightarrow, \xleftarrow
-            // 在箭头上下加上文本
+            // xarrow: 在箭头上下加上文本
             // \xrightarrow[below]{above}
-            if let Some(exp) = shared::get_xarrow(b){
-                if c.envs["amsmath"]{
-                    // \xrightarrow[below]{above}
-                    c.push_text(exp.as_str());
-                    c.push_text("[");
-                    write_grouped_exp(c, e1)?;
-                    c.push_text("]");
-                    write_grouped_exp(c, e2)?;
-                    return Ok(());
-                }
+            if write_xarrow(c, b, Some(e1), e2)? {
+                return Ok(());
             }
 
             if shared::is_operator(b){
@@ -1421,16 +1883,16 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
                 if !tex_unicode::is_mathop_base(b){
                     c.push_text("\\mathop{");
                     if shared::is_fancy(b){
-                        write_grouped_exp(c, b)?;
+                        write_grouped_exp(c, 0, b)?;
                     }else{
-                        write_exp(c, b)?;
+                        write_exp(c, 0, b)?;
                     }
                     c.push_text("}");
                 }else{
                     if shared::is_fancy(b){
-                        write_grouped_exp(c, b)?;
+                        write_grouped_exp(c, 0, b)?;
                     }else{
-                        write_exp(c, b)?;
+                        write_exp(c, 0, b)?;
                     }
                 }
                 
@@ -1442,19 +1904,19 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
 
                 if is_fancy(e1){
                     c.push_text("{");
-                    write_if_substack(c, e1)?;
+                    write_if_substack(c, 1, e1)?;
                     c.push_text("}");
                 }else{
-                    write_if_substack(c, e1)?;
+                    write_if_substack(c, 1, e1)?;
                 }
                 c.push_text("^");
                 
                 if is_fancy(e2){
                     c.push_text("{");
-                    write_if_substack(c, e2)?;
+                    write_if_substack(c, 2, e2)?;
                     c.push_text("}");
                 }else{
-                    write_if_substack(c, e2)?;
+                    write_if_substack(c, 2, e2)?;
                 }
 
                 c.convertible = false; // reset
@@ -1462,7 +1924,7 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
                 return Ok(());
             }
             // writeExp (EUnder convertible (EOver convertible b e2) e1)
-            write_exp(c, &Exp::EUnder(
+            synthesized(c, |c| write_exp(c, 0, &Exp::EUnder(
                 *convertible,
                 Box::new(Exp::EOver(
                     *convertible,
@@ -1470,29 +1932,62 @@ fn write_exp(c: &mut TexWriterContext, exp: &Exp) -> Result<(), String>{
                     (*e2).clone()
                 )),
                 (*e1).clone()
-            ))?;
+            )))?;
         },
 
         Exp::ERoot(exp1, exp2) => {
             c.push_text("\\sqrt[");
-            write_exp(c, exp1)?;
+            write_exp(c, 0, exp1)?;
             c.push_text("]");
-            write_grouped_exp(c, exp2)?;
+            write_grouped_exp(c, 1, exp2)?;
         },
 
         Exp::EScaled(size, e) => {
-            let flag = match **e {
-                Exp::ESymbol(TeXSymbolType::Open, _) => true,
-                Exp::ESymbol(TeXSymbolType::Close, _) => true,
-                _ => false,
+            if size.denominator == 0 {
+                return Err(format!("invalid scale: {} % {}", size.numerator, size.denominator));
+            }
+            // 界定符按类型用\bigl, \bigr, \bigm, \big缩放, /也可以缩放
+            let scaler = match &**e {
+                Exp::ESymbol(symbol_type @ (TeXSymbolType::Open | TeXSymbolType::Close), _) => shared::get_scaler_cmd(size, symbol_type),
+                Exp::ESymbol(symbol_type, symbol) if symbol == "/" || tex_unicode::is_delimiters(symbol, &c.enabled) => {
+                    shared::get_scaler_cmd(size, symbol_type)
+                },
+                _ => None,
             };
-            if flag{
-                if let Some(cmd) = shared::get_scaler_cmd(&size){
-                    c.push_text(cmd.as_str());
-                }
-                write_exp(c, e)?;
-            }else{
-                write_exp(c, e)?;
+            if let Some(cmd) = &scaler {
+                c.push_text(cmd.as_str());
+                return write_exp(c, 0, e);
+            }
+            if size.numerator == size.denominator {
+                return write_exp(c, 0, e);
+            }
+            match c.scaled {
+                ScaledFallback::Scalebox => {
+                    // 不是界定符或超过\Bigg: \scalebox{1.5}{\(x\)}
+                    // \scalebox里的公式会回到\textstyle, 所以用\mathchoice按当前的样式分别输出,
+                    // e只写一次(宏包和警告只记录一次), 其他样式复制写出的内容
+                    // 里面用\(\)而不用$, 否则markdown中$...$包裹的公式会在里面的$处结束
+                    let factor = shared::get_scale_factor(size);
+                    c.push_text("\\mathchoice{");
+                    c.push_command("\\scalebox{");
+                    c.push_text(&factor);
+                    c.push_text("}{\\(");
+                    c.push_text("\\displaystyle");
+                    let start = c.tex.len();
+                    write_exp(c, 0, e)?;
+                    let inner = c.tex[start..].to_string();
+                    c.push_text("\\)}}");
+                    for style in ["textstyle", "scriptstyle", "scriptscriptstyle"] {
+                        c.push_text(&format!("{{\\scalebox{{{}}}{{\\(\\{}{}\\)}}}}", factor, style, inner));
+                    }
+                },
+                ScaledFallback::Ignore => {
+                    let start = c.tex.len();
+                    write_exp(c, 0, e)?;
+                    let lost = format!("scale {}/{}", size.numerator, size.denominator);
+                    let emitted = c.tex[start..].to_string();
+                    c.warn("scaled", &lost, &emitted);
+                },
             }
         },
     }
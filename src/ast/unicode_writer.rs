@@ -0,0 +1,411 @@
+// Exp -> 线性Unicode文本, 用于全文检索和图片的alt文本:
+// x² + y² = z², ∑ᵢ aᵢ, √(x+1), a/b
+// 能用Unicode上下标表示时使用上下标字符, 否则退化为^(...)和_(...)
+use super::node::{self, ArrayLines, Exp, InEDelimited, TeXSymbolType};
+use super::shared::{self, Position};
+use super::tex_unicode::lookup_text_unicode;
+use super::writer::{Writer, WriterOptions};
+
+pub struct UnicodeWriterContext {
+    text: String, // 输出的文本
+    compact: bool, // 子表达式(上下标, 分数, 根号)内Bin, Rel两边不加空格: √(x+1)
+}
+
+impl UnicodeWriterContext {
+    fn push_text(&mut self, s: &str) {
+        self.text.push_str(s);
+    }
+
+    // 添加一个空格, 不输出重复的空格
+    fn push_space(&mut self) {
+        if self.text.is_empty() || self.text.ends_with(' ') {
+            return;
+        }
+        self.text.push(' ');
+    }
+}
+
+// 上标字符
+fn to_superscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰', '1' => '¹', '2' => '²', '3' => '³', '4' => '⁴',
+        '5' => '⁵', '6' => '⁶', '7' => '⁷', '8' => '⁸', '9' => '⁹',
+        '+' => '⁺', '-' | '\u{2212}' => '⁻', '=' => '⁼', '(' => '⁽', ')' => '⁾',
+        'a' => 'ᵃ', 'b' => 'ᵇ', 'c' => 'ᶜ', 'd' => 'ᵈ', 'e' => 'ᵉ', 'f' => 'ᶠ',
+        'g' => 'ᵍ', 'h' => 'ʰ', 'i' => 'ⁱ', 'j' => 'ʲ', 'k' => 'ᵏ', 'l' => 'ˡ',
+        'm' => 'ᵐ', 'n' => 'ⁿ', 'o' => 'ᵒ', 'p' => 'ᵖ', 'r' => 'ʳ', 's' => 'ˢ',
+        't' => 'ᵗ', 'u' => 'ᵘ', 'v' => 'ᵛ', 'w' => 'ʷ', 'x' => 'ˣ', 'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'A' => 'ᴬ', 'B' => 'ᴮ', 'D' => 'ᴰ', 'E' => 'ᴱ', 'G' => 'ᴳ', 'H' => 'ᴴ',
+        'I' => 'ᴵ', 'J' => 'ᴶ', 'K' => 'ᴷ', 'L' => 'ᴸ', 'M' => 'ᴹ', 'N' => 'ᴺ',
+        'O' => 'ᴼ', 'P' => 'ᴾ', 'R' => 'ᴿ', 'T' => 'ᵀ', 'U' => 'ᵁ', 'V' => 'ⱽ',
+        'W' => 'ᵂ',
+        'β' => 'ᵝ', 'γ' => 'ᵞ', 'δ' => 'ᵟ', 'θ' => 'ᶿ', 'φ' => 'ᵠ', 'χ' => 'ᵡ',
+        // 撇号本身就写在上标位置
+        '′' | '″' | '‴' | '*' => c,
+        _ => return None,
+    })
+}
+
+// 下标字符
+fn to_subscript(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀', '1' => '₁', '2' => '₂', '3' => '₃', '4' => '₄',
+        '5' => '₅', '6' => '₆', '7' => '₇', '8' => '₈', '9' => '₉',
+        '+' => '₊', '-' | '\u{2212}' => '₋', '=' => '₌', '(' => '₍', ')' => '₎',
+        'a' => 'ₐ', 'e' => 'ₑ', 'h' => 'ₕ', 'i' => 'ᵢ', 'j' => 'ⱼ', 'k' => 'ₖ',
+        'l' => 'ₗ', 'm' => 'ₘ', 'n' => 'ₙ', 'o' => 'ₒ', 'p' => 'ₚ', 'r' => 'ᵣ',
+        's' => 'ₛ', 't' => 'ₜ', 'u' => 'ᵤ', 'v' => 'ᵥ', 'x' => 'ₓ',
+        'β' => 'ᵦ', 'γ' => 'ᵧ', 'ρ' => 'ᵨ', 'φ' => 'ᵩ', 'χ' => 'ᵪ',
+        _ => return None,
+    })
+}
+
+// 组合重音符号: EOver b (ESymbol Accent "\771") -> b̃
+fn to_combining_accent(c: char) -> Option<char> {
+    Some(match c {
+        '^' | '\u{02C6}' | '\u{0302}' => '\u{0302}',
+        '~' | '\u{02DC}' | '\u{0303}' => '\u{0303}',
+        '\u{00AF}' | '\u{203E}' | '\u{0304}' | '\u{0305}' => '\u{0305}',
+        '\u{02D9}' | '\u{0307}' => '\u{0307}',
+        '\u{00A8}' | '\u{0308}' => '\u{0308}',
+        '\u{00B4}' | '\u{0301}' => '\u{0301}',
+        '`' | '\u{0300}' => '\u{0300}',
+        '\u{02D8}' | '\u{0306}' => '\u{0306}',
+        '\u{02C7}' | '\u{030C}' => '\u{030C}',
+        '\u{2192}' | '\u{20D7}' => '\u{20D7}',
+        '\u{20DB}' => '\u{20DB}',
+        _ => return None,
+    })
+}
+
+// 单独渲染一个Exp, 用于上下标, 分数等需要先得到子表达式文本的情况
+fn render(exp: &Exp) -> Result<String, String> {
+    let mut c = UnicodeWriterContext { text: String::new(), compact: true };
+    write_exp(&mut c, exp)?;
+    Ok(c.text.trim().to_string())
+}
+
+fn render_list(exps: &[Exp]) -> Result<String, String> {
+    let mut c = UnicodeWriterContext { text: String::new(), compact: true };
+    for exp in exps {
+        write_exp(&mut c, exp)?;
+    }
+    Ok(c.text.trim().to_string())
+}
+
+// 原子表达式不需要加括号: x, 12, αβ
+fn is_atomic(s: &str) -> bool {
+    s.chars().count() <= 1 || s.chars().all(|c| c.is_alphanumeric())
+}
+
+fn parenthesize(s: &str) -> String {
+    if is_atomic(s) {
+        s.to_string()
+    } else {
+        format!("({})", s)
+    }
+}
+
+#[test]
+fn test_write_script() {
+    let mut c = UnicodeWriterContext { text: String::new(), compact: false };
+    write_script(&mut c, &Position::Over, &Exp::EGrouped(vec![
+        Exp::EIdentifier("n".into()),
+        Exp::ESymbol(TeXSymbolType::Bin, "+".into()),
+        Exp::ENumber("1".into()),
+    ])).unwrap();
+    assert_eq!(c.text, "ⁿ⁺¹");
+
+    let mut c = UnicodeWriterContext { text: String::new(), compact: false };
+    write_script(&mut c, &Position::Under, &Exp::EIdentifier("q".into())).unwrap();
+    assert_eq!(c.text, "_q");
+
+    let mut c = UnicodeWriterContext { text: String::new(), compact: false };
+    write_script(&mut c, &Position::Under, &Exp::EGrouped(vec![
+        Exp::EIdentifier("q".into()),
+        Exp::ESymbol(TeXSymbolType::Pun, ",".into()),
+        Exp::EIdentifier("r".into()),
+    ])).unwrap();
+    assert_eq!(c.text, "_(q,r)");
+}
+
+// 输出上标或下标: 所有字符都有Unicode上下标时直接转换, 否则输出^(...)或_(...)
+fn write_script(c: &mut UnicodeWriterContext, p: &Position, e: &Exp) -> Result<(), String> {
+    let s = render(e)?;
+    if s.is_empty() {
+        return Ok(());
+    }
+    let convert = match p {
+        Position::Over => to_superscript,
+        Position::Under => to_subscript,
+    };
+    let converted: Option<String> = s.chars().filter(|ch| *ch != ' ').map(convert).collect();
+    match converted {
+        Some(script) => c.push_text(&script),
+        None => {
+            c.push_text(match p {
+                Position::Over => "^",
+                Position::Under => "_",
+            });
+            c.push_text(&parenthesize(&s));
+        },
+    }
+    Ok(())
+}
+
+// EOver/EUnder: 重音用组合字符, 其余当作上下标
+fn write_over_under(c: &mut UnicodeWriterContext, p: &Position, b: &Exp, e: &Exp) -> Result<(), String> {
+    if let (Position::Over, Exp::ESymbol(_, s)) = (p, e) {
+        let chars: Vec<char> = s.chars().collect();
+        if chars.len() == 1 {
+            if let Some(accent) = to_combining_accent(chars[0]) {
+                let base = render(b)?;
+                c.push_text(&parenthesize(&base));
+                c.push_text(&accent.to_string());
+                return Ok(());
+            }
+        }
+    }
+    write_exp(c, b)?;
+    write_script(c, p, e)?;
+    if shared::is_operator(b) {
+        c.push_space();
+    }
+    Ok(())
+}
+
+fn write_array_rows(c: &mut UnicodeWriterContext, rows: &[ArrayLines]) -> Result<(), String> {
+    // [a, b; c, d]
+    c.push_text("[");
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            c.push_text("; ");
+        }
+        for (j, cell) in row.iter().enumerate() {
+            if j > 0 {
+                c.push_text(", ");
+            }
+            c.push_text(&render_list(cell)?);
+        }
+    }
+    c.push_text("]");
+    Ok(())
+}
+
+fn write_exp(c: &mut UnicodeWriterContext, exp: &Exp) -> Result<(), String> {
+    node::with_stack(|| write_exp_inner(c, exp))
+}
+
+fn write_exp_inner(c: &mut UnicodeWriterContext, exp: &Exp) -> Result<(), String> {
+    match exp {
+        Exp::ENumber(s) | Exp::EIdentifier(s) => {
+            c.push_text(s);
+        },
+
+        Exp::EMathOperator(s) => {
+            c.push_text(s);
+            c.push_space();
+        },
+
+        Exp::ESymbol(symbol_type, s) => {
+            let text: String = s.chars()
+                .filter(|ch| !('\u{2061}'..='\u{2064}').contains(ch))
+                .collect();
+            match symbol_type {
+                TeXSymbolType::Bin | TeXSymbolType::Rel if !c.compact => {
+                    c.push_space();
+                    c.push_text(&text);
+                    c.push_space();
+                },
+                TeXSymbolType::Pun if !c.compact => {
+                    c.push_text(&text);
+                    c.push_space();
+                },
+                _ => {
+                    c.push_text(&text);
+                }
+            }
+        },
+
+        Exp::ESpace(rational) => {
+            if rational.numerator > 0 {
+                c.push_space();
+            }
+        },
+
+        Exp::EGrouped(exp_list) => {
+            for e in exp_list {
+                write_exp(c, e)?;
+            }
+        },
+
+        Exp::EDelimited(open, close, exp_list) => {
+            c.push_text(open);
+            for e in exp_list {
+                match e {
+                    InEDelimited::Left(delim) => {
+                        c.push_space();
+                        c.push_text(delim);
+                        c.push_space();
+                    },
+                    InEDelimited::Right(e) => {
+                        write_exp(c, e)?;
+                    },
+                }
+            }
+            // 去掉右界定符前的空格: (a, b ) -> (a, b)
+            while c.text.ends_with(' ') {
+                c.text.pop();
+            }
+            c.push_text(close);
+        },
+
+        Exp::ESub(b, e) => {
+            write_exp(c, b)?;
+            write_script(c, &Position::Under, e)?;
+        },
+
+        Exp::ESuper(b, e) => {
+            write_exp(c, b)?;
+            write_script(c, &Position::Over, e)?;
+        },
+
+        Exp::ESubsup(b, e1, e2) => {
+            write_exp(c, b)?;
+            write_script(c, &Position::Under, e1)?;
+            write_script(c, &Position::Over, e2)?;
+        },
+
+        Exp::EOver(_, b, e) => {
+            write_over_under(c, &Position::Over, b, e)?;
+        },
+
+        Exp::EUnder(_, b, e) => {
+            write_over_under(c, &Position::Under, b, e)?;
+        },
+
+        Exp::EUnderOver(_, b, e1, e2) => {
+            write_exp(c, b)?;
+            write_script(c, &Position::Under, e1)?;
+            write_script(c, &Position::Over, e2)?;
+            if shared::is_operator(b) {
+                c.push_space();
+            }
+        },
+
+        Exp::EFraction(_, num, den) => {
+            c.push_text(&parenthesize(&render(num)?));
+            c.push_text("/");
+            c.push_text(&parenthesize(&render(den)?));
+        },
+
+        Exp::ESqrt(e) => {
+            c.push_text("√");
+            c.push_text(&parenthesize(&render(e)?));
+        },
+
+        Exp::ERoot(index, e) => {
+            // ∛x, ∜x, 其他情况: ⁵√x
+            match render(index)?.as_str() {
+                "3" => c.push_text("∛"),
+                "4" => c.push_text("∜"),
+                _ => {
+                    write_script(c, &Position::Over, index)?;
+                    c.push_text("√");
+                }
+            }
+            c.push_text(&parenthesize(&render(e)?));
+        },
+
+        Exp::EPhantom(_) => {
+            c.push_space();
+        },
+
+        Exp::EBoxed(e) | Exp::EScaled(_, e) => {
+            write_exp(c, e)?;
+        },
+
+        Exp::EArray(_, rows) => {
+            write_array_rows(c, rows)?;
+        },
+
+        Exp::EText(_, s) => {
+            c.push_text(s);
+        },
+
+        Exp::EStyled(text_type, exp_list) => {
+            // 使用数学字母数字符号: EStyled TextDoubleStruck [EIdentifier "R"] -> ℝ
+            let s = render_list(exp_list)?;
+            let styled: String = s.chars()
+                .map(|ch| lookup_text_unicode(text_type, ch).unwrap_or(ch))
+                .collect();
+            c.push_text(&styled);
+        },
+    }
+    Ok(())
+}
+
+#[test]
+fn test_write_unicode() {
+    // x^2 + y^2 = z^2
+    let exps = vec![
+        Exp::ESuper(Box::new(Exp::EIdentifier("x".into())), Box::new(Exp::ENumber("2".into()))),
+        Exp::ESymbol(TeXSymbolType::Bin, "+".into()),
+        Exp::ESuper(Box::new(Exp::EIdentifier("y".into())), Box::new(Exp::ENumber("2".into()))),
+        Exp::ESymbol(TeXSymbolType::Rel, "=".into()),
+        Exp::ESuper(Box::new(Exp::EIdentifier("z".into())), Box::new(Exp::ENumber("2".into()))),
+    ];
+    assert_eq!(write_unicode(exps).unwrap(), "x² + y² = z²");
+
+    // \sum_i a_i
+    let exps = vec![
+        Exp::EUnder(true, Box::new(Exp::ESymbol(TeXSymbolType::Op, "\u{2211}".into())), Box::new(Exp::EIdentifier("i".into()))),
+        Exp::ESub(Box::new(Exp::EIdentifier("a".into())), Box::new(Exp::EIdentifier("i".into()))),
+    ];
+    assert_eq!(write_unicode(exps).unwrap(), "∑ᵢ aᵢ");
+
+    // \sqrt{x+1}, a/b
+    let exps = vec![
+        Exp::ESqrt(Box::new(Exp::EGrouped(vec![
+            Exp::EIdentifier("x".into()),
+            Exp::ESymbol(TeXSymbolType::Bin, "+".into()),
+            Exp::ENumber("1".into()),
+        ]))),
+        Exp::ESymbol(TeXSymbolType::Pun, ",".into()),
+        Exp::EFraction(crate::ast::node::FractionType::NormalFrac,
+            Box::new(Exp::EIdentifier("a".into())),
+            Box::new(Exp::EIdentifier("b".into()))),
+    ];
+    assert_eq!(write_unicode(exps).unwrap(), "√(x+1), a/b");
+}
+
+#[test]
+fn test_write_unicode_styled() {
+    let exps = vec![
+        Exp::EStyled(crate::ast::node::TextType::TextDoubleStruck, vec![Exp::EIdentifier("R".into())]),
+        Exp::EStyled(crate::ast::node::TextType::TextDoubleStruck, vec![Exp::EIdentifier("C".into())]),
+    ];
+    assert_eq!(write_unicode(exps).unwrap(), "\u{211D}\u{2102}");
+}
+
+// 把Exp转换为线性Unicode文本
+pub fn write_unicode(exps: Vec<Exp>) -> Result<String, String> {
+    let mut c = UnicodeWriterContext { text: String::new(), compact: false };
+    for exp in &exps {
+        write_exp(&mut c, exp)?;
+    }
+    Ok(c.text.trim().to_string())
+}
+
+pub struct UnicodeWriter;
+
+impl Writer for UnicodeWriter {
+    fn name(&self) -> &'static str {
+        "unicode"
+    }
+
+    fn write(&self, exps: Vec<Exp>, _opts: &WriterOptions) -> Result<String, String> {
+        write_unicode(exps)
+    }
+}
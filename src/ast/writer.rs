@@ -0,0 +1,158 @@
+// 输出格式的统一接口: 每种输出(TeX, Unicode文本, 朗读文本...)实现Writer, 注册到WRITERS
+// server的to字段和命令行都通过格式名在WRITERS中查找, 新增格式不需要修改server.rs
+use std::collections::{BTreeSet, HashMap};
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use super::node::{self, Exp};
+use super::{speech_writer, tex_writer, unicode_writer};
+
+// 可用的宏包, 按插入顺序为优先级: 多个宏包都提供同一个字符时, 靠前的宏包的命令优先
+// 值为false的宏包视为不可用
+pub type Envs = IndexMap<String, bool>;
+
+// 输出中用到的宏包, 按名字排序, 不包括base
+pub type Packages = BTreeSet<String>;
+
+// 写出时的选项
+// envs: 可用的宏包, 如: amsmath, amssymb
+// equation: 是否作为独立公式(display)输出
+// inline_delimiters/display_delimiters: 行内/独立公式的界定符, 如: \( \), \[ \]
+// extra: 各格式自己的选项, 如: speech的phrases(措辞表csv路径)
+pub struct WriterOptions {
+    pub envs: Envs,
+    pub equation: bool,
+    pub inline_delimiters: (String, String),
+    pub display_delimiters: (String, String),
+    pub extra: HashMap<String, String>,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        let mut envs = Envs::new();
+        envs.insert("amsmath".to_string(), true);
+        envs.insert("amssymb".to_string(), true);
+        envs.insert("mathbb".to_string(), true);
+        WriterOptions {
+            envs,
+            equation: false,
+            inline_delimiters: ("\\(".to_string(), "\\)".to_string()),
+            display_delimiters: ("\\[".to_string(), "\\]".to_string()),
+            extra: HashMap::new(),
+        }
+    }
+}
+
+// 写出时丢失或降级的信息
+// path: 节点路径, 如: 0:EStyled/1:EIdentifier, 数字为节点在父节点Exp::children()中的下标
+// kind: style, text_style, scaled, accent, symbol, delimiter
+// lost: 没有表示出来的信息, emitted: 实际的输出
+#[derive(Debug, Clone, PartialEq, serde::Serialize, schemars::JsonSchema)]
+#[schemars(rename = "Warning")]
+pub struct WriterWarning {
+    #[schemars(example = "example_path", description = "Node path, each step is the index in the parent's children and the node name")]
+    pub path: String,
+    pub kind: &'static str,
+    #[schemars(description = "What was not represented, e.g. TextBoldFraktur")]
+    pub lost: String,
+    #[schemars(description = "What was written instead, e.g. \\mathfrak")]
+    pub emitted: String,
+}
+
+fn example_path() -> &'static str {
+    "0:EGrouped/1:EStyled"
+}
+
+// 写出的结果
+// packages: 输出需要的宏包, 如: amsmath, unicode-math, 只有TeX输出会记录
+// warnings: 没有如实转换的节点, 只有TeX输出会记录
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WriterOutput {
+    pub text: String,
+    pub packages: Vec<String>,
+    pub warnings: Vec<WriterWarning>,
+}
+
+pub trait Writer: Send + Sync {
+    // 格式名, 即server的to字段和命令行的--<name>
+    fn name(&self) -> &'static str;
+    fn write(&self, exps: Vec<Exp>, opts: &WriterOptions) -> Result<String, String>;
+
+    // 同时返回用到的宏包和警告, 默认都没有
+    fn write_output(&self, exps: Vec<Exp>, opts: &WriterOptions) -> Result<WriterOutput, String> {
+        self.write(exps, opts).map(|text| WriterOutput { text, ..Default::default() })
+    }
+}
+
+lazy_static! {
+    static ref WRITERS: HashMap<&'static str, Box<dyn Writer>> = {
+        let writers: Vec<Box<dyn Writer>> = vec![
+            Box::new(tex_writer::TexWriter),
+            Box::new(tex_writer::MarkdownWriter),
+            Box::new(tex_writer::PrettyTexWriter),
+            Box::new(unicode_writer::UnicodeWriter),
+            Box::new(speech_writer::SpeechWriter),
+        ];
+        writers.into_iter().map(|w| (w.name(), w)).collect()
+    };
+}
+
+pub fn get_writer(name: &str) -> Option<&'static dyn Writer> {
+    WRITERS.get(name).map(|w| w.as_ref())
+}
+
+// 所有已注册的格式名, 按名字排序
+pub fn writer_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = WRITERS.keys().copied().collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_write_with() {
+    use super::node::TeXSymbolType;
+    let exps = vec![
+        Exp::EIdentifier("x".into()),
+        Exp::ESymbol(TeXSymbolType::Bin, "+".into()),
+        Exp::ENumber("1".into()),
+    ];
+    let opts = WriterOptions::default();
+    assert_eq!(write_with("tex", exps.clone(), &opts).unwrap(), "x + 1");
+    assert_eq!(write_with("unicode", exps.clone(), &opts).unwrap(), "x + 1");
+    assert_eq!(write_with("speech", exps.clone(), &opts).unwrap(), "x plus 1");
+    assert!(write_with("mathml", exps, &opts).is_err());
+}
+
+#[test]
+fn test_write_too_deep() {
+    let chain = |d: usize| {
+        let mut e = Exp::EIdentifier("x".into());
+        for _ in 0..d {
+            e = Exp::EGrouped(vec![e]);
+        }
+        vec![e]
+    };
+    let opts = WriterOptions::default();
+    for name in writer_names() {
+        assert!(write_with(name, chain(node::DEFAULT_MAX_DEPTH - 1), &opts).is_ok(), "{}", name);
+        let err = write_with(name, chain(100_000), &opts).unwrap_err();
+        assert!(err.contains("nested deeper than"), "{}", name);
+    }
+}
+
+// 按格式名写出, 格式不存在时返回错误, 嵌套超过node::DEFAULT_MAX_DEPTH时返回错误
+pub fn write_with(name: &str, exps: Vec<Exp>, opts: &WriterOptions) -> Result<String, String> {
+    write_output_with(name, exps, opts).map(|output| output.text)
+}
+
+// 同write_with, 同时返回输出用到的宏包和警告
+pub fn write_output_with(name: &str, exps: Vec<Exp>, opts: &WriterOptions) -> Result<WriterOutput, String> {
+    let writer = match get_writer(name) {
+        Some(w) => w,
+        None => return Err(format!("unknown output format: {}, available: {}", name, writer_names().join(", "))),
+    };
+    if node::depth(&exps) > node::DEFAULT_MAX_DEPTH {
+        node::drop_deep(exps);
+        return Err(format!("expression nested deeper than {}", node::DEFAULT_MAX_DEPTH));
+    }
+    writer.write_output(exps, opts)
+}
@@ -1,11 +1,233 @@
+use std::collections::HashMap;
 use lazy_static::lazy_static;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub table_dir: String,
     pub server_port: u16,
     pub server_addr: String,
+    #[serde(default)]
+    pub convert: ConvertConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+}
+
+// server的转换结果缓存
+// [cache]
+// capacity = 4096 # 最多缓存的结果数, 0表示不缓存
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub capacity: usize,
+}
+
+// server的资源限制
+// [limits]
+// max_body_bytes = 1048576 # 请求体的最大字节数
+// max_depth = 200 # 输入中括号的最大嵌套深度
+// timeout_ms = 5000 # 单个转换的超时时间
+// max_concurrency = 64 # 同时进行的转换数
+// max_batch_items = 1000 # 一次批量转换的最大项数
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub max_body_bytes: usize,
+    pub max_depth: usize,
+    pub timeout_ms: u64,
+    pub max_concurrency: usize,
+    pub max_batch_items: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_body_bytes: 1024 * 1024,
+            max_depth: 200,
+            timeout_ms: 5000,
+            max_concurrency: 64,
+            max_batch_items: 1000,
+        }
+    }
+}
+
+// 转换的默认选项, 请求中没有指定时使用
+// [convert]
+// dialect = "latex"
+// [convert.dialects.mathjax]
+// packages = ["amsmath", "amssymb"]
+// inline = ["\\(", "\\)"]
+// display = ["\\[", "\\]"]
+// mapping = "house" # 默认使用的自定义映射表, 可以省略
+// [convert.mappings]
+// house = "mappings/house.toml" # 映射表的名字 -> 文件, toml或csv
+#[derive(Debug, Deserialize)]
+pub struct ConvertConfig {
+    #[serde(default = "default_dialect")]
+    pub dialect: String,
+    #[serde(default)]
+    pub dialects: HashMap<String, DialectConfig>,
+    #[serde(default)]
+    pub mapping: Option<String>,
+    #[serde(default)]
+    pub mappings: HashMap<String, String>,
+    #[serde(default)]
+    pub combining: CombiningConfig,
+    #[serde(default)]
+    pub unmapped: Unmapped,
+    #[serde(default)]
+    pub scaled: ScaledFallback,
+}
+
+// 数学中的组合字符, 如OCR得到的x + U+0302
+// [convert.combining]
+// accents = true # 基字符 + 组合重音 -> \hat{x}, false时逐个字符查表
+// negation = "precomposed" # = + U+0338: precomposed -> \neq(没有预组合字符时用\not), not -> \not=
+// decompose = true # 符号表中没有的预组合字母按NFD分解, é -> \acute{e}
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CombiningConfig {
+    pub accents: bool,
+    pub negation: Negation,
+    pub decompose: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Negation {
+    Precomposed,
+    Not,
+}
+
+impl Default for CombiningConfig {
+    fn default() -> Self {
+        CombiningConfig {
+            accents: true,
+            negation: Negation::Precomposed,
+            decompose: true,
+        }
+    }
+}
+
+// 符号表中没有对应数学命令的非ASCII字符, 如€, 中文, 直接输出时pdfLaTeX会报错
+// unmapped = "text" # text -> \text{€}, command -> \text{\texteuro}(没有文本命令时同char), char -> \char"20AC, error -> 转换失败
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Unmapped {
+    #[default]
+    Text,
+    Command,
+    Char,
+    Error,
+}
+
+impl Unmapped {
+    // 解析writer选项中的unmapped
+    pub fn parse(s: &str) -> Result<Unmapped, String> {
+        match s {
+            "text" => Ok(Unmapped::Text),
+            "command" => Ok(Unmapped::Command),
+            "char" => Ok(Unmapped::Char),
+            "error" => Ok(Unmapped::Error),
+            _ => Err(format!("unknown unmapped policy: {}, available: text, command, char, error", s)),
+        }
+    }
+}
+
+// EScaled不能用\big..\Bigg表示时(不是界定符, 或倍数超过\Bigg)
+// scaled = "scalebox" # scalebox -> \scalebox{1.5}{$x$}(graphicx), ignore -> 忽略缩放并报告警告
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaledFallback {
+    #[default]
+    Scalebox,
+    Ignore,
+}
+
+impl ScaledFallback {
+    // 解析writer选项中的scaled
+    pub fn parse(s: &str) -> Result<ScaledFallback, String> {
+        match s {
+            "scalebox" => Ok(ScaledFallback::Scalebox),
+            "ignore" => Ok(ScaledFallback::Ignore),
+            _ => Err(format!("unknown scaled fallback: {}, available: scalebox, ignore", s)),
+        }
+    }
+}
+
+// 目标方言: 可用的宏包和公式的界定符, 宏包按优先级排列
+#[derive(Debug, Deserialize, Serialize, Clone, schemars::JsonSchema)]
+#[schemars(rename = "Dialect")]
+pub struct DialectConfig {
+    pub packages: Vec<String>,
+    #[serde(default = "default_inline")]
+    #[schemars(with = "[String; 2]", description = "Inline math delimiters")]
+    pub inline: (String, String),
+    #[serde(default = "default_display")]
+    #[schemars(with = "[String; 2]", description = "Display math delimiters")]
+    pub display: (String, String),
+}
+
+fn default_dialect() -> String {
+    "latex".to_string()
+}
+
+fn default_inline() -> (String, String) {
+    ("\\(".to_string(), "\\)".to_string())
+}
+
+fn default_display() -> (String, String) {
+    ("\\[".to_string(), "\\]".to_string())
+}
+
+impl Default for ConvertConfig {
+    fn default() -> Self {
+        ConvertConfig {
+            dialect: default_dialect(),
+            dialects: HashMap::new(),
+            mapping: None,
+            mappings: HashMap::new(),
+            combining: CombiningConfig::default(),
+            unmapped: Unmapped::default(),
+            scaled: ScaledFallback::default(),
+        }
+    }
+}
+
+impl ConvertConfig {
+    // 查找方言, config.toml中没有配置latex时使用内置的latex
+    pub fn get_dialect(&self, name: &str) -> Option<DialectConfig> {
+        if let Some(d) = self.dialects.get(name) {
+            return Some(d.clone());
+        }
+        match name {
+            "latex" => Some(DialectConfig {
+                packages: vec!["amsmath".to_string(), "amssymb".to_string(), "mathbb".to_string()],
+                inline: default_inline(),
+                display: default_display(),
+            }),
+            _ => None,
+        }
+    }
+
+    // 所有可用的方言名, 包括内置的latex
+    pub fn dialect_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.dialects.keys().cloned().collect();
+        if !self.dialects.contains_key("latex") {
+            names.push("latex".to_string());
+        }
+        names.sort();
+        names
+    }
+
+    // 所有自定义映射表的名字
+    pub fn mapping_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.mappings.keys().cloned().collect();
+        names.sort();
+        names
+    }
 }
 
 fn load_config(filename: &str) -> Result<Config, Box<dyn std::error::Error>> {
@@ -21,4 +243,4 @@ lazy_static!{
 
 pub fn get_config() -> &'static Config {
     &CONFIG
-}
\ No newline at end of file
+}
@@ -0,0 +1,3 @@
+// 转换库: 读取native/json格式的AST并写出TeX等格式, main.rs和benches共用
+pub mod ast;
+pub mod config;
@@ -1,11 +1,10 @@
-use std::collections::HashMap;
 use std::{fs, panic};
 use std::path::Path;
 use std::io;
 use std::io::Write;
 
-mod ast;
-mod config;
+use texmath_rust::{ast, config};
+use texmath_rust::ast::writer::Envs;
 
 use std::time::Instant;
 use nom::AsBytes;
@@ -13,6 +12,7 @@ use tokio::task;
 use ast::judge::{judge_by_texmath, JudgeResult};
 
 use crate::ast::ast_reader;
+use crate::ast::shared::pretty_print_hex;
 
 fn read_dir_files_to_vec(dir: &Path) -> io::Result<Vec<String>> {
     let mut file_contents = Vec::new();
@@ -126,27 +126,6 @@ fn read_dir_files(dir: &Path) -> io::Result<(Vec<String>, Vec<String>, Vec<Strin
     Ok((filenames, natives, texs))
 }
 
-fn pretty_print_hex(output: String) -> String{
-    // 第一行显示hex, 第二行显示字符:
-    // 40 41 42
-    // @  A  B
-
-    // 把0D 0A替换成0A
-
-    let output = output.replace("\r\n", "\n");
-    let mut hex = String::new();
-    let mut cs = String::new();
-    for c in output.chars() {
-        hex.push_str(&format!("{:02x} ", c as u8));
-        match c {
-            '\n' => cs.push_str("\\n "),
-            '\t' => cs.push_str("\\t "),
-            '\r' => cs.push_str("\\r "),
-            _ => cs.push_str(&format!("{}  ", c)),
-        }
-    }
-    return format!("{}\n{}", hex, cs);
-}
 fn test_totex_and_judge(){
     let dir = "./src/test";
 
@@ -165,7 +144,7 @@ fn test_totex_and_judge(){
     
     let mut success = 0;
     let now = Instant::now();
-    let mut envs = HashMap::new();
+    let mut envs = Envs::new();
     envs.insert("amsmath".to_string(), true);
     envs.insert("amssymb".to_string(), true);
     envs.insert("mathbb".to_string(), true);
@@ -302,7 +281,7 @@ fn bench_test_totex(){
 
     let mut success = 0;
     let now = Instant::now();
-    let mut envs = HashMap::new();
+    let mut envs = Envs::new();
     envs.insert("amsmath".to_string(), true);
     envs.insert("amssymb".to_string(), true);
     envs.insert("mathbb".to_string(), true);
@@ -399,7 +378,7 @@ fn bench_test_jsonl(filename: &str){
     println!("{} files found, start testing, using {} ms", test_cases.len(), now.elapsed().as_millis());
 
 
-    let mut envs = HashMap::new();
+    let mut envs = Envs::new();
     envs.insert("amsmath".to_string(), true);
     envs.insert("amssymb".to_string(), true);
     envs.insert("mathbb".to_string(), true);
@@ -510,32 +489,96 @@ fn merge_jsonl_as(src: &str, target: &str, max: &str){
     f.write_all(']'.to_string().as_bytes()).unwrap();
     println!("write to file done");
 }
+// symbols                       列出符号表中的宏包
+// symbols <字符|U+2264> [宏包 ...] 给定宏包下可以输出该字符的命令
+// symbols cmd <命令>             命令对应的字符和类型, 如: symbols cmd \leqslant
+// symbols pkg <宏包>             宏包提供的符号
+fn print_symbols(args: &[String]) -> Result<(), String> {
+    use ast::symbol_db::{format_code_point, parse_char_query, PackageOrder, Symbol, SymbolDb};
+
+    let db = SymbolDb::global()?;
+    let symbols: Vec<&Symbol> = match args {
+        [] => {
+            for name in db.packages() {
+                println!("{}", name);
+            }
+            return Ok(());
+        },
+        [kind, cmd] if kind == "cmd" => db.by_command(cmd),
+        [kind, name] if kind == "pkg" => db.package(name),
+        [c, packages @ ..] => match parse_char_query(c) {
+            Some(c) => db.commands_for(c, &PackageOrder::new(packages)),
+            None => return Err(format!("invalid character: {}, expect a single character or U+XXXX", c)),
+        },
+    };
+    for s in symbols {
+        println!("{}\t{}\t{}\t{:?}\t{}\t{}", format_code_point(s.code_point), s.code_point, s.command, s.symbol_type, s.package, s.description);
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> io::Result<()> {
     let args = std::env::args().collect::<Vec<String>>();
     if args.len() != 1 {
         return match args[1] {
             ref s if s == "server" => {
-                ast::server::run_server(config::get_config().server_addr.clone(), config::get_config().server_port).await;
-                Ok(())
+                ast::server::run_server(config::get_config().server_addr.clone(), config::get_config().server_port).await
             },
             ref s if s == "cmd" => {
                 let sync_result = task::spawn_blocking(move || {
-                    let mut envs = HashMap::new();
-                    envs.insert("amsmath".to_string(), true);
-                    envs.insert("amssymb".to_string(), true);
-                    envs.insert("mathbb".to_string(), true);
                     let filename = &args[2];
                     // read file
                     let content = fs::read_to_string(filename).unwrap();
-                    // parse ast
-                    let exps = ast_reader::read_ast(&content).unwrap();
-                    let tex = ast::tex_writer::write_tex_with_env(exps, &envs).unwrap();
-                    println!("{}", tex);
+                    // 按扩展名选择reader, 如: input.json, 其他扩展名按native读取
+                    let from = match std::path::Path::new(filename).extension().and_then(|e| e.to_str()) {
+                        Some(ext) if ast::reader::get_reader(ext).is_some() => ext,
+                        _ => "native",
+                    };
+                    let exps = match ast::reader::read_with(from, &content) {
+                        Ok(exps) => exps,
+                        Err(e) => panic!("{}", e),
+                    };
+                    // cmd <file> [--<format>] [key=value ...]
+                    // format为已注册的输出格式, 默认tex, 如: --pretty, --unicode, --speech phrases=phrases_zh.csv, --tex preamble=true
+                    let mut opts = ast::writer::WriterOptions::default();
+                    let format = match args.get(3) {
+                        Some(flag) => flag.trim_start_matches("--"),
+                        None => "tex",
+                    };
+                    for kv in args.iter().skip(4) {
+                        match kv.split_once('=') {
+                            Some((k, v)) => {
+                                opts.extra.insert(k.to_string(), v.to_string());
+                            },
+                            None => panic!("invalid option: {}, expect key=value", kv),
+                        }
+                    }
+                    // preamble=true时先输出用到的宏包的\usepackage
+                    let preamble = opts.extra.remove("preamble").is_some_and(|v| v == "true");
+                    let output = match ast::writer::write_output_with(format, exps, &opts) {
+                        Ok(output) => output,
+                        Err(e) => panic!("{}", e),
+                    };
+                    for w in &output.warnings {
+                        eprintln!("warning: {}: {}: {} -> {:?}", w.path, w.kind, w.lost, w.emitted);
+                    }
+                    if preamble {
+                        print!("{}", ast::tex_writer::render_preamble(&output.packages));
+                    }
+                    println!("{}", output.text);
                 }).await;
                 sync_result.unwrap();
                 Ok(())
             },
+            ref s if s == "symbols" => {
+                let sync_result = task::spawn_blocking(move || print_symbols(&args[2..])).await;
+                if let Err(e) = sync_result.unwrap() {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+                Ok(())
+            },
             ref s if s == "merge_jsonl" => {
                 let _ = task::spawn_blocking(move || {
                     merge_jsonl_as(&args[2], &args[3], &args[4]);